@@ -1,21 +1,30 @@
 import { DeviceMotion, DeviceMotionMeasurement } from "expo-sensors";
 import { StatusBar } from "expo-status-bar";
 import { useEffect, useRef, useState } from "react";
-import { StyleSheet, Text, View } from "react-native";
-import { Connection } from "./src/socket/connection";
-import {
-  MessageType,
-  Transform,
-  TransformType,
-  messageToBytes,
-} from "./src/socket/message";
+import { Platform, StyleSheet, Text, View } from "react-native";
+import { Connection, describeError } from "./src/socket/connection";
+import { type Message } from "./src/socket/message";
+
+// on the web, `?transport=broadcast` drives a renderer in another tab without the relay
+function usesBroadcast() {
+  return (
+    Platform.OS === "web" &&
+    new URLSearchParams(window.location.search).get("transport") === "broadcast"
+  );
+}
 
 function App() {
   const conn = useRef(new Connection("phone"));
   const [angles, setAngles] = useState<[number, number, number]>([0, 0, 0]);
+  const [error, setError] = useState<string | null>(null);
 
   useEffect(() => {
-    conn.current.connect();
+    conn.current.onError = (error) => setError(describeError(error));
+    if (usesBroadcast()) {
+      conn.current.connectBroadcast();
+    } else {
+      conn.current.connect();
+    }
 
     function listener(event: DeviceMotionMeasurement) {
       const roll = event.rotation.alpha;
@@ -32,12 +41,11 @@ function App() {
   }, []);
 
   useEffect(() => {
-    const msg: Transform = {
-      type: MessageType.TRANSFORM,
-      transform: {
-        type: TransformType.ROTATE,
-        data: angles,
-      },
+    const msg: Message = {
+      type: "transform",
+      target: "owner",
+      kind: "rotate",
+      value: angles,
     };
     conn.current.send(msg);
   }, [angles]);
@@ -46,6 +54,7 @@ function App() {
       <Text>Roll: {angles[0]}</Text>
       <Text>Pitch: {angles[1]}</Text>
       <Text>Yaw: {angles[2]}</Text>
+      {error && <Text style={styles.error}>Protocol error: {error}</Text>}
       <StatusBar style="auto" />
     </View>
   );
@@ -58,6 +67,9 @@ const styles = StyleSheet.create({
     alignItems: "center",
     justifyContent: "center",
   },
+  error: {
+    color: "#c00",
+  },
 });
 
 export default App;
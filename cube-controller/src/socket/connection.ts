@@ -1,12 +1,40 @@
 /* eslint-disable @typescript-eslint/no-empty-function */
-import { messageFromBytes, type Message, messageToBytes } from "./message";
+import {
+  ErrorCode,
+  PROTOCOL_VERSION,
+  messageFromBytes,
+  messageToBytes,
+  type Envelope,
+  type Handshake,
+  type Message,
+  type Stamp,
+} from "./message";
+
+export type ProtocolError = Extract<Envelope, { type: "error" }>;
+
+export function describeError(error: ProtocolError) {
+  return `${ErrorCode[error.code] ?? error.code} (${error.detail}) in frame ${error.offending}`;
+}
 
 export class Connection {
   socket: WebSocket | null = null;
+  // used instead of the socket when the renderer runs in another tab
+  channel: BroadcastChannel | null = null;
+
+  // outgoing stamp
+  private sender = Math.floor(Math.random() * 0xffffffff);
+  private sequence = 0;
+
+  // handshakes received, by sender
+  peers = new Map<number, Handshake>();
+
+  // last sequence number acked by a peer
+  acked = 0;
 
   // listeners
   onOpen: () => void = () => {};
-  onMessage: (event: Message) => void = () => {};
+  onMessage: (event: Envelope) => void = () => {};
+  onError: (error: ProtocolError) => void = () => {};
 
   constructor(public name: string) {
     this.name = name;
@@ -19,10 +47,52 @@ export class Connection {
     return `ws://${host}:${port}/${path}`;
   }
 
+  private handshake(): Message {
+    return {
+      type: "handshake",
+      name: this.name,
+      role: "controller",
+      versions: [PROTOCOL_VERSION, PROTOCOL_VERSION],
+      codecs: ["binary"],
+      bindings: ["owner"],
+    };
+  }
+
   private open() {
+    this.peers.clear();
+    this.send(this.handshake());
     this.onOpen?.();
   }
 
+  private receive(envelope: Envelope) {
+    if (envelope.type === "handshake") {
+      // answer peers we haven't met, they may have connected after us
+      const isNew = !this.peers.has(envelope.sender);
+      this.peers.set(envelope.sender, envelope);
+      if (isNew) {
+        this.send(this.handshake());
+      }
+      return;
+    }
+    if (envelope.type === "ping") {
+      this.send({ type: "pong", to: envelope.sender, id: envelope.id });
+      return;
+    }
+    if (envelope.type === "ack") {
+      if (envelope.to === this.sender) {
+        this.acked = Math.max(this.acked, envelope.acked);
+      }
+      return;
+    }
+    if (envelope.type === "error") {
+      if (envelope.to === this.sender) {
+        this.onError?.(envelope);
+      }
+      return;
+    }
+    this.onMessage?.(envelope);
+  }
+
   private message(event: MessageEvent) {
     const data = event.data;
     if (data instanceof Blob) {
@@ -30,8 +100,7 @@ export class Connection {
         .arrayBuffer()
         .then((buffer) => {
           const buf = new Uint8Array(buffer);
-          const message = messageFromBytes(buf);
-          this.onMessage?.(message);
+          this.receive(messageFromBytes(buf));
         })
         .catch((err) => {
           console.error(err);
@@ -39,19 +108,43 @@ export class Connection {
     }
   }
 
+  private nextStamp(): Stamp {
+    this.sequence = (this.sequence + 1) >>> 0;
+    return {
+      sender: this.sender,
+      sequence: this.sequence,
+      timestamp: Date.now(),
+    };
+  }
+
   connect() {
     this.socket = new WebSocket(Connection.getUrl());
     this.socket.addEventListener("open", this.open.bind(this));
     this.socket.addEventListener("message", this.message.bind(this));
   }
 
+  // talks to a renderer in another tab of this browser, without the relay
+  connectBroadcast(name = "cube") {
+    this.channel = new BroadcastChannel(name);
+    this.channel.addEventListener("message", (event: MessageEvent) => {
+      if (event.data instanceof Uint8Array) {
+        this.receive(messageFromBytes(event.data));
+      }
+    });
+    this.open();
+  }
+
   disconnect() {
     this.socket?.close();
+    this.channel?.close();
+    this.channel = null;
   }
 
   send(message: Message) {
-    if (this.socket?.readyState === WebSocket.OPEN) {
-      this.socket?.send(messageToBytes(message));
+    if (this.channel) {
+      this.channel.postMessage(messageToBytes(message, this.nextStamp()));
+    } else if (this.socket?.readyState === WebSocket.OPEN) {
+      this.socket?.send(messageToBytes(message, this.nextStamp()));
     }
   }
 }
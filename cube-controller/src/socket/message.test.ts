@@ -0,0 +1,56 @@
+import assert from "node:assert/strict";
+import { describe, it } from "node:test";
+import golden from "./golden.json";
+import {
+  MAX_NAME_LEN,
+  PROTOCOL_VERSION,
+  messageFromBytes,
+  messageToBytes,
+  type Envelope,
+  type Message,
+} from "./message";
+
+function fromHex(hex: string): Uint8Array {
+  const bytes = hex.match(/../g) ?? [];
+  return new Uint8Array(bytes.map((b) => parseInt(b, 16)));
+}
+
+describe("golden vectors", () => {
+  for (const vector of golden) {
+    const envelope = vector.message as Envelope;
+
+    it(`decodes ${vector.name}`, () => {
+      assert.deepEqual(messageFromBytes(fromHex(vector.hex)), envelope);
+    });
+
+    it(`encodes ${vector.name}`, () => {
+      const { sender, sequence, timestamp } = envelope;
+      const bytes = messageToBytes(envelope, { sender, sequence, timestamp });
+      assert.deepEqual(bytes, fromHex(vector.hex));
+    });
+  }
+});
+
+describe("names", () => {
+  const handshake = (name: string): Message => ({
+    type: "handshake",
+    name,
+    role: "controller",
+    versions: [PROTOCOL_VERSION, PROTOCOL_VERSION],
+    codecs: ["binary"],
+    bindings: [],
+  });
+  const stamp = { sender: 1, sequence: 1, timestamp: 0 };
+
+  it("encodes names up to MAX_NAME_LEN bytes", () => {
+    const bytes = messageToBytes(handshake("x".repeat(MAX_NAME_LEN)), stamp);
+    const decoded = messageFromBytes(bytes);
+    assert.equal(decoded.type === "handshake" && decoded.name.length, MAX_NAME_LEN);
+  });
+
+  it("refuses longer names", () => {
+    assert.throws(() => messageToBytes(handshake("x".repeat(MAX_NAME_LEN + 1)), stamp));
+    // counted in UTF-8 bytes, not characters
+    assert.throws(() => messageToBytes(handshake("é".repeat(128)), stamp));
+  });
+});
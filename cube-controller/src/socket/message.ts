@@ -1,172 +1,483 @@
-//! Message types and structs
-//!
-//! This module contains the message types and structs used by the server and client.
-//!
-//! # Summary
-//! The first byte of every message is the message type. The message type is used to determine
-//! how to parse the rest of the message. The message types are defined in the `message_types`
-//! module.
-//!
-//! # Message Types
-//! The message types are defined in the `message_types` module. The message types are:
-//! - `HANDSHAKE`: The first message sent by the client to the server. The client sends the
-//!    server the client's name. The server responds with the server's name.
-//! - `SYNC`: The server sends this message to the client to synchronize the client's state
-//!   with the server's state. The client does not respond to this message.
-//! - `TRANSFORM`: The client sends this message to the server indicating that some change
-//!  has been made to the client's state. The server does not respond to this message.
-//!
-//! ## Transform Message
-//! The `TRANSFORM` message type is used to indicate that some change has been made to the
-//! client's state. The `TRANSFORM` message type is followed by a transform type byte. The
-//! transform type byte is used to determine how to parse the rest of the message. The
-//! transform types are defined in the `transform` module.
-//!
-//! ### Transform Types
-//! - `ROTATE`: The client has rotated the object.
-//! - `TRANSLATE`: The client has translated the object.
-//! - `SCALE`: The client has scaled the object.
-
-enum MessageType {
+// Generated from cube-renderer/src/utils/message, do not edit.
+// Regenerate with `UPDATE_BINDINGS=1 cargo test bindings` in cube-renderer.
+// The message and transform encoders and decoders are generated from the layouts in
+// codegen.rs; the primitives they are built from are written in codegen.ts.
+
+export const MAGIC = [0xc0, 0xbe];
+export const PROTOCOL_VERSION = 3;
+export const HEADER_LEN = 24;
+export const MAX_NAME_LEN = 255;
+
+export enum MessageType {
 	HANDSHAKE = 0,
 	SYNC = 1,
-	TRANSFORM = 2
+	TRANSFORM = 2,
+	BATCH = 3,
+	SNAPSHOT = 4,
+	PING = 5,
+	PONG = 6,
+	ACK = 7,
+	ERROR = 8,
+}
+
+export enum TargetType {
+	OWNER = 0,
+	ID = 1,
+	NAME = 2,
 }
 
-enum TransformType {
+export enum TransformType {
 	ROTATE = 0,
 	TRANSLATE = 1,
-	SCALE = 2
+	SCALE = 2,
+	ROTATE_QUAT = 3,
 }
 
-type Transform = {
-	type: MessageType.TRANSFORM;
-	transform: Rotate | Translate | Scale;
-};
+export enum RoleType {
+	CONTROLLER = 0,
+	VIEWER = 1,
+	RECORDER = 2,
+}
 
-type Rotate = {
-	type: TransformType.ROTATE;
-	data: [number, number, number];
-};
+export enum CodecFlag {
+	BINARY = 1,
+	JSON = 2,
+	COMPACT = 4,
+}
 
-type Translate = {
-	type: TransformType.TRANSLATE;
-	data: [number, number, number];
-};
+export enum ErrorCode {
+	INVALID_MAGIC = 0,
+	UNSUPPORTED_VERSION = 1,
+	UNSUPPORTED_FLAGS = 2,
+	INVALID_MESSAGE_TYPE = 3,
+	INVALID_TRANSFORM_TYPE = 4,
+	INVALID_TARGET_TYPE = 5,
+	INVALID_MESSAGE_LENGTH = 6,
+	INVALID_STRING = 7,
+	MISSING_BASELINE = 8,
+	INVALID_JSON = 9,
+	INVALID_ROLE = 10,
+	UNSUPPORTED_CODECS = 11,
+	FRAME_TOO_LARGE = 12,
+	TRAILING_BYTES = 13,
+	NAME_TOO_LONG = 14,
+	TOO_MANY_ITEMS = 15,
+	NON_FINITE = 16,
+	OUT_OF_RANGE = 17,
+}
 
-type Scale = {
-	type: TransformType.SCALE;
-	data: [number, number, number];
-};
+export type Vec3 = [number, number, number];
+export type Quat = [number, number, number, number];
+
+export type Target = 'owner' | { id: number } | { name: string };
+
+export type Transform =
+	| { target: Target; kind: 'rotate'; value: Vec3 }
+	| { target: Target; kind: 'rotate_quat'; value: Quat }
+	| { target: Target; kind: 'translate'; value: Vec3 }
+	| { target: Target; kind: 'scale'; value: Vec3 };
 
-type Handshake = {
-	type: MessageType.HANDSHAKE;
+export type Role = 'controller' | 'viewer' | 'recorder';
+export type Codec = 'binary' | 'json' | 'compact';
+
+export type Handshake = {
 	name: string;
+	role: Role;
+	versions: [number, number];
+	codecs: Codec[];
+	bindings: Target[];
 };
 
-type Sync = {
-	type: MessageType.SYNC;
-	transform: Transform;
+export type EntitySnapshot = {
+	id: number;
+	name: string | null;
+	position: Vec3;
+	rotation: Quat;
+	scale: Vec3;
 };
 
-type Message = Handshake | Sync | Transform;
+export type Message =
+	| ({ type: 'handshake' } & Handshake)
+	| { type: 'sync' }
+	| { type: 'snapshot'; entities: EntitySnapshot[] }
+	| ({ type: 'transform' } & Transform)
+	| { type: 'batch'; transforms: Transform[] }
+	| { type: 'ping'; id: number }
+	| { type: 'pong'; to: number; id: number }
+	| { type: 'ack'; to: number; acked: number }
+	| { type: 'error'; to: number; offending: number; code: ErrorCode; detail: number };
+
+export type Stamp = { sender: number; sequence: number; timestamp: number };
+
+export type Envelope = Message & Stamp & { version: number };
+
+class Writer {
+	private bytes: number[] = [];
+
+	u8(value: number) {
+		this.bytes.push(value & 0xff);
+	}
+
+	u16(value: number) {
+		this.raw(2, (view) => view.setUint16(0, value, true));
+	}
+
+	u32(value: number) {
+		this.raw(4, (view) => view.setUint32(0, value, true));
+	}
+
+	f32(value: number) {
+		this.raw(4, (view) => view.setFloat32(0, value, true));
+	}
+
+	f64(value: number) {
+		this.raw(8, (view) => view.setFloat64(0, value, true));
+	}
+
+	floats(values: number[]) {
+		values.forEach((value) => this.f32(value));
+	}
+
+	string(value: string) {
+		const bytes = new TextEncoder().encode(value);
+		if (bytes.length > MAX_NAME_LEN) {
+			throw new Error(`Name too long: ${bytes.length} bytes`);
+		}
+		this.u8(bytes.length);
+		bytes.forEach((b) => this.u8(b));
+	}
+
+	/** Writes the number of items that follow, in `size` bytes. */
+	count(value: number, size: 1 | 2) {
+		if (value > (size === 1 ? 0xff : 0xffff)) {
+			throw new Error(`Too many items: ${value}`);
+		}
+		if (size === 1) {
+			this.u8(value);
+		} else {
+			this.u16(value);
+		}
+	}
 
-function vec3ToBytes(vec: [number, number, number]): Uint8Array {
-	const bytes = new Uint8Array(3 * 4);
-	const view = new DataView(bytes.buffer, 0, 12);
-	view.setFloat32(0, vec[0], true);
-	view.setFloat32(4, vec[1], true);
-	view.setFloat32(8, vec[2], true);
-	return bytes;
+	finish(): Uint8Array {
+		return new Uint8Array(this.bytes);
+	}
+
+	private raw(length: number, write: (view: DataView) => void) {
+		const view = new DataView(new ArrayBuffer(length));
+		write(view);
+		new Uint8Array(view.buffer).forEach((b) => this.bytes.push(b));
+	}
 }
 
-function vec3FromBytes(bytes: Uint8Array): [number, number, number] {
-	const vec: [number, number, number] = [0, 0, 0];
-	const view = new DataView(bytes.buffer, bytes.byteOffset, 12);
-	vec[0] = view.getFloat32(0, true);
-	vec[1] = view.getFloat32(4, true);
-	vec[2] = view.getFloat32(8, true);
-	return vec;
+class Reader {
+	private offset = 0;
+	private view: DataView;
+
+	constructor(private bytes: Uint8Array) {
+		this.view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+	}
+
+	u8(): number {
+		return this.view.getUint8(this.advance(1));
+	}
+
+	u16(): number {
+		return this.view.getUint16(this.advance(2), true);
+	}
+
+	u32(): number {
+		return this.view.getUint32(this.advance(4), true);
+	}
+
+	f32(): number {
+		return this.view.getFloat32(this.advance(4), true);
+	}
+
+	f64(): number {
+		return this.view.getFloat64(this.advance(8), true);
+	}
+
+	vec3(): Vec3 {
+		return [this.f32(), this.f32(), this.f32()];
+	}
+
+	quat(): Quat {
+		return [this.f32(), this.f32(), this.f32(), this.f32()];
+	}
+
+	string(): string {
+		const length = this.u8();
+		const start = this.advance(length);
+		return new TextDecoder().decode(this.bytes.subarray(start, start + length));
+	}
+
+	items<T>(count: number, read: () => T): T[] {
+		const items: T[] = [];
+		for (; count > 0; count--) {
+			items.push(read());
+		}
+		return items;
+	}
+
+	private advance(length: number): number {
+		if (this.offset + length > this.bytes.length) {
+			throw new Error('Invalid message length');
+		}
+		const offset = this.offset;
+		this.offset += length;
+		return offset;
+	}
 }
 
-function transformToBytes(transform: Transform): Uint8Array {
-	const transformBytes = new Uint8Array(1 + 1 + 3 * 8);
-	transformBytes[0] = MessageType.TRANSFORM;
-	transformBytes[1] = transform.transform.type;
-	const dataBytes = vec3ToBytes(transform.transform.data);
-	transformBytes.set(dataBytes, 2);
-	return transformBytes;
+function writeTarget(w: Writer, target: Target) {
+	if (target === 'owner') {
+		w.u8(TargetType.OWNER);
+	} else if ('id' in target) {
+		w.u8(TargetType.ID);
+		w.u32(target.id);
+	} else {
+		w.u8(TargetType.NAME);
+		w.string(target.name);
+	}
 }
 
-function transformFromBytes(bytes: Uint8Array): Transform {
-	const transformType = bytes[0];
-	const data = vec3FromBytes(bytes.subarray(1));
-	let transform: Rotate | Translate | Scale;
-	switch (transformType) {
-		case TransformType.ROTATE:
-		case TransformType.TRANSLATE:
-		case TransformType.SCALE:
-			transform = { type: transformType, data };
-			break;
+function readTarget(r: Reader): Target {
+	const type = r.u8();
+	switch (type) {
+		case TargetType.OWNER:
+			return 'owner';
+		case TargetType.ID:
+			return { id: r.u32() };
+		case TargetType.NAME:
+			return { name: r.string() };
 		default:
-			throw new Error(`Invalid transform type: ${transformType}`);
+			throw new Error(`Invalid target type: ${type}`);
 	}
-	return { type: MessageType.TRANSFORM, transform };
 }
 
-function handshakeToBytes(handshake: Handshake): Uint8Array {
-	const nameBytes = new TextEncoder().encode(handshake.name);
-	const handshakeBytes = new Uint8Array(1 + nameBytes.length);
-	handshakeBytes[0] = MessageType.HANDSHAKE;
-	handshakeBytes.set(nameBytes, 1);
-	return handshakeBytes;
+const ROLES: [Role, RoleType][] = [
+	['controller', RoleType.CONTROLLER],
+	['viewer', RoleType.VIEWER],
+	['recorder', RoleType.RECORDER],
+];
+
+const CODECS: [Codec, CodecFlag][] = [
+	['binary', CodecFlag.BINARY],
+	['json', CodecFlag.JSON],
+	['compact', CodecFlag.COMPACT],
+];
+
+function writeRole(w: Writer, role: Role) {
+	const found = ROLES.find(([name]) => name === role);
+	w.u8(found ? found[1] : RoleType.CONTROLLER);
+}
+
+function readRole(r: Reader): Role {
+	const type = r.u8();
+	const role = ROLES.find(([, value]) => value === type);
+	if (!role) {
+		throw new Error(`Invalid role: ${type}`);
+	}
+	return role[0];
 }
 
-function handshakeFromBytes(bytes: Uint8Array): Handshake {
-	const name = new TextDecoder().decode(bytes.subarray(1));
-	return { type: MessageType.HANDSHAKE, name };
+function writeCodecs(w: Writer, codecs: Codec[]) {
+	w.u8(
+		CODECS.filter(([name]) => codecs.includes(name)).reduce(
+			(flags, [, flag]) => flags | flag,
+			0,
+		),
+	);
 }
 
-function syncToBytes(sync: Sync): Uint8Array {
-	const transformBytes = transformToBytes(sync.transform);
-	const syncBytes = new Uint8Array(1 + transformBytes.length);
-	syncBytes[0] = MessageType.SYNC;
-	syncBytes.set(transformBytes, 1);
-	return syncBytes;
+function readCodecs(r: Reader): Codec[] {
+	const flags = r.u8();
+	return CODECS.filter(([, flag]) => flags & flag).map(([name]) => name);
 }
 
-function syncFromBytes(bytes: Uint8Array): Sync {
-	const transform = transformFromBytes(bytes.subarray(1));
-	return { type: MessageType.SYNC, transform };
+function writeTransform(w: Writer, transform: Transform) {
+	writeTarget(w, transform.target);
+	switch (transform.kind) {
+		case 'rotate':
+			w.u8(TransformType.ROTATE);
+			w.floats(transform.value);
+			break;
+		case 'translate':
+			w.u8(TransformType.TRANSLATE);
+			w.floats(transform.value);
+			break;
+		case 'scale':
+			w.u8(TransformType.SCALE);
+			w.floats(transform.value);
+			break;
+		case 'rotate_quat':
+			w.u8(TransformType.ROTATE_QUAT);
+			w.floats(transform.value);
+			break;
+	}
 }
 
-function messageToBytes(message: Message): Uint8Array {
-	const type = message.type;
+function readTransform(r: Reader): Transform {
+	const target = readTarget(r);
+	const type = r.u8();
 	switch (type) {
-		case MessageType.HANDSHAKE:
-			return handshakeToBytes(message);
-		case MessageType.SYNC:
-			return syncToBytes(message);
-		case MessageType.TRANSFORM:
-			return transformToBytes(message);
+		case TransformType.ROTATE:
+			return { target, kind: 'rotate', value: r.vec3() };
+		case TransformType.TRANSLATE:
+			return { target, kind: 'translate', value: r.vec3() };
+		case TransformType.SCALE:
+			return { target, kind: 'scale', value: r.vec3() };
+		case TransformType.ROTATE_QUAT:
+			return { target, kind: 'rotate_quat', value: r.quat() };
 		default:
-			throw new Error(`Invalid message type: ${type}`);
+			throw new Error(`Invalid transform type: ${type}`);
+	}
+}
+
+function writeMessage(w: Writer, message: Message) {
+	switch (message.type) {
+		case 'handshake':
+			w.u8(MessageType.HANDSHAKE);
+			writeRole(w, message.role);
+			w.u8(message.versions[0]);
+			w.u8(message.versions[1]);
+			writeCodecs(w, message.codecs);
+			w.string(message.name);
+			w.count(message.bindings.length, 1);
+			message.bindings.forEach((item) => writeTarget(w, item));
+			break;
+		case 'sync':
+			w.u8(MessageType.SYNC);
+			break;
+		case 'transform':
+			w.u8(MessageType.TRANSFORM);
+			writeTransform(w, message);
+			break;
+		case 'batch':
+			w.u8(MessageType.BATCH);
+			w.count(message.transforms.length, 2);
+			message.transforms.forEach((item) => writeTransform(w, item));
+			break;
+		case 'snapshot':
+			w.u8(MessageType.SNAPSHOT);
+			w.count(message.entities.length, 2);
+			for (const item of message.entities) {
+				w.u32(item.id);
+				w.string(item.name ?? '');
+				w.floats(item.position);
+				w.floats(item.rotation);
+				w.floats(item.scale);
+			}
+			break;
+		case 'ping':
+			w.u8(MessageType.PING);
+			w.u32(message.id);
+			break;
+		case 'pong':
+			w.u8(MessageType.PONG);
+			w.u32(message.to);
+			w.u32(message.id);
+			break;
+		case 'ack':
+			w.u8(MessageType.ACK);
+			w.u32(message.to);
+			w.u32(message.acked);
+			break;
+		case 'error':
+			w.u8(MessageType.ERROR);
+			w.u32(message.to);
+			w.u32(message.offending);
+			w.u8(message.code);
+			w.u8(message.detail);
+			break;
 	}
 }
 
-function messageFromBytes(bytes: Uint8Array): Message {
-	const messageType = bytes[0];
-	switch (messageType) {
+function readMessage(r: Reader): Message {
+	const type = r.u8();
+	switch (type) {
 		case MessageType.HANDSHAKE:
-			return handshakeFromBytes(bytes.subarray(1));
+			return {
+				type: 'handshake',
+				role: readRole(r),
+				versions: [r.u8(), r.u8()] as [number, number],
+				codecs: readCodecs(r),
+				name: r.string(),
+				bindings: r.items(r.u8(), () => readTarget(r)),
+			};
 		case MessageType.SYNC:
-			return syncFromBytes(bytes.subarray(1));
+			return { type: 'sync' };
 		case MessageType.TRANSFORM:
-			return transformFromBytes(bytes.subarray(1));
+			return { type: 'transform', ...readTransform(r) };
+		case MessageType.BATCH:
+			return { type: 'batch', transforms: r.items(r.u16(), () => readTransform(r)) };
+		case MessageType.SNAPSHOT:
+			return {
+				type: 'snapshot',
+				entities: r.items(r.u16(), () => ({
+					id: r.u32(),
+					name: r.string() || null,
+					position: r.vec3(),
+					rotation: r.quat(),
+					scale: r.vec3(),
+				})),
+			};
+		case MessageType.PING:
+			return { type: 'ping', id: r.u32() };
+		case MessageType.PONG:
+			return { type: 'pong', to: r.u32(), id: r.u32() };
+		case MessageType.ACK:
+			return { type: 'ack', to: r.u32(), acked: r.u32() };
+		case MessageType.ERROR:
+			return {
+				type: 'error',
+				to: r.u32(),
+				offending: r.u32(),
+				code: r.u8(),
+				detail: r.u8(),
+			};
 		default:
-			throw new Error(`Invalid message type: ${messageType}`);
+			throw new Error(`Invalid message type: ${type}`);
+	}
+}
+
+function messageToBytes(message: Message, stamp: Stamp): Uint8Array {
+	const payload = new Writer();
+	writeMessage(payload, message);
+	const body = payload.finish();
+
+	const w = new Writer();
+	MAGIC.forEach((b) => w.u8(b));
+	w.u8(PROTOCOL_VERSION);
+	w.u8(0);
+	w.u32(body.length);
+	w.u32(stamp.sender);
+	w.u32(stamp.sequence);
+	w.f64(stamp.timestamp);
+	body.forEach((b) => w.u8(b));
+	return w.finish();
+}
+
+function messageFromBytes(bytes: Uint8Array): Envelope {
+	const r = new Reader(bytes);
+	if (r.u8() !== MAGIC[0] || r.u8() !== MAGIC[1]) {
+		throw new Error('Invalid magic');
+	}
+	const version = r.u8();
+	if (version !== PROTOCOL_VERSION) {
+		throw new Error(`Unsupported version: ${version}`);
+	}
+	const flags = r.u8();
+	if (flags !== 0) {
+		throw new Error(`Unsupported flags: ${flags}`);
+	}
+	const length = r.u32();
+	if (bytes.length !== HEADER_LEN + length) {
+		throw new Error('Invalid message length');
 	}
+	const stamp = { sender: r.u32(), sequence: r.u32(), timestamp: r.f64() };
+	return { version, ...stamp, ...readMessage(r) };
 }
 
-export { MessageType, TransformType, messageToBytes, messageFromBytes };
-export type { Message, Handshake, Sync, Transform, Rotate, Translate, Scale };
+export { messageToBytes, messageFromBytes };
@@ -5,13 +5,14 @@ use web_sys::{HtmlCanvasElement, WebGl2RenderingContext};
 
 use crate::{
     camera::Camera,
+    console,
     model::{DrawableContext, EntityBuffer},
     resources::Assets,
     utils::Instant,
     HANDLE,
 };
 
-use super::{AppState, Viewport};
+use super::{overlay::StatsOverlay, AppState, Config, Viewport};
 
 pub struct App {
     pub canvas: HtmlCanvasElement,
@@ -21,18 +22,16 @@ pub struct App {
     pub assets: Assets,
     now: Instant,
     camera: Camera,
+    overlay: Option<StatsOverlay>,
 }
 
 impl App {
-    pub fn new(host: String) -> Result<App, JsValue> {
+    pub fn new(config: Config) -> Result<App, JsValue> {
         let document = web_sys::window().unwrap().document().unwrap();
         let canvas = document.get_element_by_id("canvas").unwrap();
         let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<HtmlCanvasElement>()?;
 
-        {
-            let mut state = HANDLE.lock().unwrap();
-            state.config.host = host;
-        }
+        HANDLE.lock().unwrap().config = config;
 
         let gl = canvas
             .get_context("webgl2")?
@@ -48,6 +47,7 @@ impl App {
             assets: Assets::new(),
             now: Instant::now(),
             camera: Camera::new(glm::vec3(0., 2.5, 5.), glm::vec3(0., 1., 0.), -90., -25.),
+            overlay: None,
         };
 
         Ok(app)
@@ -72,6 +72,7 @@ impl App {
     pub fn update(&mut self, dt: f32, mut state: MutexGuard<AppState>) {
         self.sync_state(&mut state);
         self.entities.update(dt, &mut state);
+        self.sync_overlay(&mut state);
     }
     pub fn draw(&mut self, dt: f32) {
         let viewport = Viewport {
@@ -90,6 +91,24 @@ impl App {
         }
     }
 
+    /// Adds or removes the stats overlay as configured, and draws the latest stats in it.
+    fn sync_overlay(&mut self, state: &mut MutexGuard<AppState>) {
+        match (&self.overlay, state.config.stats_overlay) {
+            (None, true) => match StatsOverlay::new(&self.canvas) {
+                Ok(overlay) => self.overlay = Some(overlay),
+                Err(e) => {
+                    console::error!("Stats overlay unavailable: {:?}", e);
+                    state.config.stats_overlay = false;
+                }
+            },
+            (Some(_), false) => self.overlay = None,
+            _ => {}
+        }
+        if let Some(overlay) = &self.overlay {
+            overlay.draw(&state.stats, &state.latency);
+        }
+    }
+
     fn sync_viewport(&self, viewport: &Viewport) {
         self.gl
             .viewport(0, 0, viewport.width as i32, viewport.height as i32);
@@ -1,5 +1,10 @@
 mod app;
+mod overlay;
 mod state;
 
 pub use app::App;
-pub use state::{from_key_code, modifiers, AppState, Key, Viewport};
+#[allow(unused_imports)]
+pub use state::{
+    from_key_code, modifiers, AppState, Config, ConfigError, Endpoint, EndpointError, Key, Scheme,
+    TransportKind, Viewport,
+};
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+use crate::network::{Latency, NetworkStats, Overflow};
+
+const FONT: &str = "12px monospace";
+const LINE_HEIGHT: f64 = 14.0;
+const PADDING: f64 = 6.0;
+const WIDTH: u32 = 300;
+
+/// Network stats of every pool, drawn in a 2D canvas laid over the bottom left corner of the
+/// renderer's canvas. The overlay is removed when dropped.
+pub struct StatsOverlay {
+    canvas: HtmlCanvasElement,
+    ctx: CanvasRenderingContext2d,
+}
+
+impl StatsOverlay {
+    /// Adds the overlay next to `over`, in the same positioned parent.
+    pub fn new(over: &HtmlCanvasElement) -> Result<StatsOverlay, JsValue> {
+        let document = web_sys::window().unwrap().document().unwrap();
+        let canvas = document
+            .create_element("canvas")?
+            .dyn_into::<HtmlCanvasElement>()?;
+        canvas.set_attribute(
+            "style",
+            "position: absolute; left: 0; bottom: 0; z-index: 10; pointer-events: none",
+        )?;
+        canvas.set_width(WIDTH);
+        over.parent_node()
+            .ok_or_else(|| JsValue::from_str("Canvas isn't in the document"))?
+            .append_child(&canvas)?;
+
+        let ctx = canvas
+            .get_context("2d")?
+            .ok_or_else(|| JsValue::from_str("No 2D context"))?
+            .dyn_into::<CanvasRenderingContext2d>()?;
+        Ok(StatsOverlay { canvas, ctx })
+    }
+
+    pub fn draw(
+        &self,
+        stats: &HashMap<&'static str, NetworkStats>,
+        latency: &HashMap<&'static str, Latency>,
+    ) {
+        let mut pools: Vec<_> = stats.iter().collect();
+        pools.sort_by_key(|(pool, _)| **pool);
+        let lines: Vec<String> = pools
+            .into_iter()
+            .flat_map(|(pool, stats)| describe(pool, stats, latency.get(pool)))
+            .collect();
+
+        let height = (lines.len() as f64 * LINE_HEIGHT + 2.0 * PADDING) as u32;
+        if self.canvas.height() != height {
+            // resizing clears the canvas and its state
+            self.canvas.set_height(height);
+        }
+        let (width, height) = (self.canvas.width() as f64, height as f64);
+        self.ctx.clear_rect(0.0, 0.0, width, height);
+        self.ctx
+            .set_fill_style(&JsValue::from_str("rgba(0, 0, 0, 0.6)"));
+        self.ctx.fill_rect(0.0, 0.0, width, height);
+        self.ctx.set_fill_style(&JsValue::from_str("#fff"));
+        self.ctx.set_font(FONT);
+        self.ctx.set_text_baseline("top");
+        for (i, line) in lines.iter().enumerate() {
+            let _ = self
+                .ctx
+                .fill_text(line, PADDING, PADDING + i as f64 * LINE_HEIGHT);
+        }
+    }
+}
+
+impl Drop for StatsOverlay {
+    fn drop(&mut self) {
+        self.canvas.remove();
+    }
+}
+
+/// The overlay's lines for `pool`.
+fn describe(pool: &str, stats: &NetworkStats, latency: Option<&Latency>) -> Vec<String> {
+    let last = match stats.last_message_age {
+        Some(age) if age < 10_000.0 => format!("{:.0}ms ago", age),
+        Some(age) => format!("{:.0}s ago", age / 1000.0),
+        None => "never".to_string(),
+    };
+    let mut lines = vec![
+        format!(
+            "{}: {:.0} msg/s, {}/s",
+            pool,
+            stats.messages_per_second,
+            format_bytes(stats.bytes_per_second)
+        ),
+        format!(
+            "  {} msgs, {}, last {}",
+            stats.messages,
+            format_bytes(stats.bytes as f64),
+            last
+        ),
+        format!(
+            "  {} decode errors, {} dropped",
+            stats.decode_errors, stats.dropped
+        ),
+    ];
+    let overflow = stats.overflow;
+    if overflow != Overflow::default() {
+        lines.push(format!(
+            "  overflow: {} oldest, {} newest, {} coalesced",
+            overflow.dropped_oldest, overflow.dropped_newest, overflow.coalesced
+        ));
+    }
+    if let Some(Latency {
+        rtt: Some(rtt),
+        jitter,
+        ..
+    }) = latency
+    {
+        lines.push(format!("  rtt {:.0}ms ± {:.0}ms", rtt, jitter));
+    }
+    lines
+}
+
+fn format_bytes(bytes: f64) -> String {
+    match bytes {
+        bytes if bytes < 1024.0 => format!("{:.0} B", bytes),
+        bytes if bytes < 1024.0 * 1024.0 => format!("{:.1} kB", bytes / 1024.0),
+        bytes => format!("{:.1} MB", bytes / 1024.0 / 1024.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_a_pool() {
+        let stats = NetworkStats {
+            messages: 1200,
+            bytes: 48 * 1024,
+            messages_per_second: 30.0,
+            bytes_per_second: 600.0,
+            decode_errors: 1,
+            dropped: 2,
+            overflow: Overflow {
+                dropped_oldest: 0,
+                dropped_newest: 4,
+                coalesced: 12,
+            },
+            last_message_age: Some(16.0),
+        };
+        let latency = Latency {
+            rtt: Some(24.0),
+            jitter: 3.0,
+            ..Latency::default()
+        };
+        assert_eq!(
+            describe("cube", &stats, Some(&latency)),
+            [
+                "cube: 30 msg/s, 600 B/s",
+                "  1200 msgs, 48.0 kB, last 16ms ago",
+                "  1 decode errors, 2 dropped",
+                "  overflow: 0 oldest, 4 newest, 12 coalesced",
+                "  rtt 24ms ± 3ms",
+            ]
+        );
+        assert_eq!(describe("cube", &NetworkStats::default(), None).len(), 3);
+    }
+}
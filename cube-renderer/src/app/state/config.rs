@@ -1,10 +1,347 @@
-#[derive(Debug)]
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+use super::endpoint::{parse_port, Endpoint, EndpointError, Scheme};
+use crate::{
+    network::{Format, OverflowPolicy, Reconnect},
+    utils::Limits,
+};
+
+/// How behaviours reach the controllers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransportKind {
+    /// Through the relay, over a WebSocket.
+    #[default]
+    Relay,
+    /// Through a `BroadcastChannel`, to controllers in other tabs of the same browser.
+    Broadcast,
+}
+
+impl TransportKind {
+    pub fn from_name(name: &str) -> Option<TransportKind> {
+        match name {
+            "relay" | "websocket" => Some(TransportKind::Relay),
+            "broadcast" => Some(TransportKind::Broadcast),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    Endpoint(EndpointError),
+    /// The option is set, but not to a value of the expected type.
+    InvalidOption {
+        name: &'static str,
+        expected: &'static str,
+    },
+    UnknownTransport(String),
+    UnknownFormat(String),
+    UnknownOverflowPolicy(String),
+}
+
+impl From<EndpointError> for ConfigError {
+    fn from(e: EndpointError) -> Self {
+        ConfigError::Endpoint(e)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Endpoint(e) => write!(f, "Invalid endpoint: {:?}", e),
+            ConfigError::InvalidOption { name, expected } => {
+                write!(f, "Option {:?} should be {}", name, expected)
+            }
+            ConfigError::UnknownTransport(name) => write!(f, "Unknown transport {:?}", name),
+            ConfigError::UnknownFormat(name) => write!(f, "Unknown format {:?}", name),
+            ConfigError::UnknownOverflowPolicy(name) => {
+                write!(f, "Unknown overflow policy {:?}", name)
+            }
+        }
+    }
+}
+
+impl From<ConfigError> for JsValue {
+    fn from(e: ConfigError) -> Self {
+        JsValue::from_str(&e.to_string())
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct Config {
-    pub host: String,
+    /// Where the relay listens.
+    pub endpoint: Endpoint,
+    pub transport: TransportKind,
+    /// How messages are sent to the relay.
+    pub format: Format,
+    /// When to reconnect to the relay after the connection closed.
+    pub reconnect: Reconnect,
+    /// What frames received from the controllers may contain.
+    pub limits: Limits,
+    /// How many received messages are kept until they are polled, unless the transport's
+    /// default.
+    pub capacity: Option<usize>,
+    /// What happens to received messages beyond `capacity`, unless the behaviour's default.
+    pub overflow_policy: Option<OverflowPolicy>,
+    /// How far behind their sender remote transforms are rendered, in ms, unless the
+    /// entity's default.
+    pub interpolation_delay: Option<f64>,
+    /// Whether network stats are drawn over the canvas.
+    pub stats_overlay: bool,
 }
 
 impl Config {
-    pub fn new(host: String) -> Config {
-        Config { host }
+    /// Reads the options passed to `run`. A string is the relay's host, as before options
+    /// were structured. Otherwise every field is optional:
+    ///
+    /// - `url`: a complete `ws://` or `wss://` URL, which the other fields then amend
+    /// - `scheme`: `"ws"` or `"wss"`, which also switches the default port to 80 or 443
+    /// - `host`, `port` and `path`
+    /// - `query`: an object of query parameters, such as `{ room: "demo" }`
+    /// - `transport`: `"relay"`, the default, or `"broadcast"`
+    /// - `format`: `"binary"`, the default, or `"json"` to send text frames to the relay
+    /// - `reconnect`: `false` to never reconnect to the relay, or an object amending the
+    ///   default `Reconnect` with `initialDelay`, `maxDelay`, `multiplier`, `jitter` and
+    ///   `maxAttempts`
+    /// - `limits`: an object amending the default `Limits` with `maxFrameLen`, `maxNameLen`,
+    ///   `maxItems`, `maxAngle`, `maxTranslation`, `maxScale`, `quatTolerance` and
+    ///   `finiteOnly`
+    /// - `capacity`: how many received messages are kept until they are polled
+    /// - `overflowPolicy`: what happens to received messages beyond `capacity`,
+    ///   `"drop_oldest"`, `"drop_newest"` or `"coalesce"`
+    /// - `interpolationDelay`: how far behind their sender remote transforms are rendered,
+    ///   in ms
+    /// - `statsOverlay`: whether to draw network stats over the canvas
+    pub fn from_js(options: &JsValue) -> Result<Config, ConfigError> {
+        if let Some(host) = options.as_string() {
+            let endpoint = Endpoint::on_host(&host);
+            endpoint.validate()?;
+            return Ok(Config {
+                endpoint,
+                ..Config::default()
+            });
+        }
+        if !options.is_object() {
+            return Err(ConfigError::InvalidOption {
+                name: "options",
+                expected: "an object or a host",
+            });
+        }
+
+        let mut endpoint = match string(options, "url")? {
+            Some(url) => Endpoint::parse(&url)?,
+            None => Endpoint::default(),
+        };
+        if let Some(scheme) = string(options, "scheme")? {
+            endpoint.scheme = Scheme::from_name(&scheme)?;
+            endpoint.port = endpoint.scheme.default_port();
+        }
+        if let Some(host) = string(options, "host")? {
+            endpoint.host = host;
+        }
+        match field(options, "port") {
+            None => {}
+            Some(port) => match (port.as_f64(), port.as_string()) {
+                (Some(port), _) if port.fract() == 0.0 && (1.0..=65535.0).contains(&port) => {
+                    endpoint.port = port as u16
+                }
+                (_, Some(port)) => endpoint.port = parse_port(&port)?,
+                _ => {
+                    return Err(ConfigError::InvalidOption {
+                        name: "port",
+                        expected: "a port number",
+                    })
+                }
+            },
+        }
+        if let Some(path) = string(options, "path")? {
+            endpoint.path = path;
+        }
+        if let Some(query) = field(options, "query") {
+            let invalid = ConfigError::InvalidOption {
+                name: "query",
+                expected: "an object of strings",
+            };
+            if !query.is_object() {
+                return Err(invalid);
+            }
+            for entry in js_sys::Object::entries(&query.into()).iter() {
+                let entry: js_sys::Array = entry.into();
+                let (Some(key), Some(value)) = (entry.get(0).as_string(), entry.get(1).as_string())
+                else {
+                    return Err(invalid);
+                };
+                endpoint.set_query(&key, &value);
+            }
+        }
+        endpoint.validate()?;
+
+        let transport = match string(options, "transport")? {
+            Some(name) => {
+                TransportKind::from_name(&name).ok_or(ConfigError::UnknownTransport(name))?
+            }
+            None => TransportKind::default(),
+        };
+        let format = match string(options, "format")? {
+            Some(name) => Format::from_name(&name).ok_or(ConfigError::UnknownFormat(name))?,
+            None => Format::default(),
+        };
+        let overflow_policy = match string(options, "overflowPolicy")? {
+            Some(name) => Some(
+                OverflowPolicy::from_name(&name).ok_or(ConfigError::UnknownOverflowPolicy(name))?,
+            ),
+            None => None,
+        };
+        let stats_overlay = match field(options, "statsOverlay") {
+            Some(value) => value.as_bool().ok_or(ConfigError::InvalidOption {
+                name: "statsOverlay",
+                expected: "a boolean",
+            })?,
+            None => false,
+        };
+        Ok(Config {
+            endpoint,
+            transport,
+            format,
+            reconnect: reconnect(options)?,
+            limits: limits(options)?,
+            capacity: count(options, "capacity")?,
+            overflow_policy,
+            interpolation_delay: number(options, "interpolationDelay")?,
+            stats_overlay,
+        })
+    }
+}
+
+fn reconnect(options: &JsValue) -> Result<Reconnect, ConfigError> {
+    let Some(value) = field(options, "reconnect") else {
+        return Ok(Reconnect::default());
+    };
+    match value.as_bool() {
+        Some(true) => return Ok(Reconnect::default()),
+        Some(false) => return Ok(Reconnect::never()),
+        None if !value.is_object() => {
+            return Err(ConfigError::InvalidOption {
+                name: "reconnect",
+                expected: "a boolean or an object",
+            })
+        }
+        None => {}
+    }
+
+    let mut reconnect = Reconnect::default();
+    if let Some(delay) = number(&value, "reconnect.initialDelay")? {
+        reconnect.initial_delay = delay;
+    }
+    if let Some(delay) = number(&value, "reconnect.maxDelay")? {
+        reconnect.max_delay = delay;
+    }
+    if let Some(multiplier) = number(&value, "reconnect.multiplier")? {
+        reconnect.multiplier = multiplier;
+    }
+    if let Some(jitter) = number(&value, "reconnect.jitter")? {
+        if jitter > 1.0 {
+            return Err(ConfigError::InvalidOption {
+                name: "reconnect.jitter",
+                expected: "a number between 0 and 1",
+            });
+        }
+        reconnect.jitter = jitter;
+    }
+    if let Some(attempts) = count(&value, "reconnect.maxAttempts")? {
+        reconnect.max_attempts = Some(attempts.min(u32::MAX as usize) as u32);
+    }
+    Ok(reconnect)
+}
+
+fn limits(options: &JsValue) -> Result<Limits, ConfigError> {
+    let Some(value) = field(options, "limits") else {
+        return Ok(Limits::default());
+    };
+    if !value.is_object() {
+        return Err(ConfigError::InvalidOption {
+            name: "limits",
+            expected: "an object",
+        });
+    }
+
+    let mut limits = Limits::default();
+    if let Some(len) = count(&value, "limits.maxFrameLen")? {
+        limits.max_frame_len = len;
+    }
+    if let Some(len) = count(&value, "limits.maxNameLen")? {
+        limits.max_name_len = len;
+    }
+    if let Some(items) = count(&value, "limits.maxItems")? {
+        limits.max_items = items;
+    }
+    if let Some(angle) = number(&value, "limits.maxAngle")? {
+        limits.max_angle = angle as f32;
+    }
+    if let Some(translation) = number(&value, "limits.maxTranslation")? {
+        limits.max_translation = translation as f32;
+    }
+    if let Some(scale) = number(&value, "limits.maxScale")? {
+        limits.max_scale = scale as f32;
+    }
+    if let Some(tolerance) = number(&value, "limits.quatTolerance")? {
+        limits.quat_tolerance = tolerance as f32;
+    }
+    if let Some(value) = field(&value, "finiteOnly") {
+        limits.finite_only = value.as_bool().ok_or(ConfigError::InvalidOption {
+            name: "limits.finiteOnly",
+            expected: "a boolean",
+        })?;
+    }
+    Ok(limits)
+}
+
+/// The field `name` of `options`, unless it is missing, `undefined` or `null`.
+fn field(options: &JsValue, name: &str) -> Option<JsValue> {
+    js_sys::Reflect::get(options, &name.into())
+        .ok()
+        .filter(|value| !value.is_undefined() && !value.is_null())
+}
+
+/// The field named by the last part of `name`, which must be a finite number, zero or more.
+/// The whole `name` is reported if it isn't.
+fn number(options: &JsValue, name: &'static str) -> Result<Option<f64>, ConfigError> {
+    let key = name.rsplit('.').next().unwrap_or(name);
+    match field(options, key) {
+        Some(value) => match value.as_f64() {
+            Some(number) if number.is_finite() && number >= 0.0 => Ok(Some(number)),
+            _ => Err(ConfigError::InvalidOption {
+                name,
+                expected: "a number, zero or more",
+            }),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Like `number`, for whole numbers.
+fn count(options: &JsValue, name: &'static str) -> Result<Option<usize>, ConfigError> {
+    match number(options, name)? {
+        Some(count) if count.fract() == 0.0 => Ok(Some(count as usize)),
+        Some(_) => Err(ConfigError::InvalidOption {
+            name,
+            expected: "a whole number, zero or more",
+        }),
+        None => Ok(None),
+    }
+}
+
+fn string(options: &JsValue, name: &'static str) -> Result<Option<String>, ConfigError> {
+    match field(options, name) {
+        Some(value) => value
+            .as_string()
+            .map(Some)
+            .ok_or(ConfigError::InvalidOption {
+                name,
+                expected: "a string",
+            }),
+        None => Ok(None),
     }
 }
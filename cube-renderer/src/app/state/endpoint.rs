@@ -0,0 +1,291 @@
+use std::fmt;
+
+/// Whether the connection to the relay is encrypted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scheme {
+    #[default]
+    Ws,
+    Wss,
+}
+
+impl Scheme {
+    pub fn from_name(name: &str) -> Result<Scheme, EndpointError> {
+        match name {
+            "ws" => Ok(Scheme::Ws),
+            "wss" => Ok(Scheme::Wss),
+            _ => Err(EndpointError::InvalidScheme(name.to_string())),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scheme::Ws => "ws",
+            Scheme::Wss => "wss",
+        }
+    }
+
+    pub fn default_port(&self) -> u16 {
+        match self {
+            Scheme::Ws => 80,
+            Scheme::Wss => 443,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointError {
+    InvalidScheme(String),
+    MissingHost,
+    InvalidHost(String),
+    InvalidPort(String),
+    InvalidPath(String),
+    InvalidQuery(String),
+}
+
+/// Where the relay listens, e.g. `wss://cube.example.com:443/relay/ws?room=demo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub scheme: Scheme,
+    pub host: String,
+    pub port: u16,
+    /// Starts with `/`.
+    pub path: String,
+    /// Query parameters, such as the room, in order. Encoded when the URL is built.
+    pub query: Vec<(String, String)>,
+}
+
+impl Default for Endpoint {
+    /// The relay as started locally.
+    fn default() -> Self {
+        Endpoint {
+            scheme: Scheme::Ws,
+            host: "localhost".to_string(),
+            port: 8080,
+            path: "/ws".to_string(),
+            query: Vec::new(),
+        }
+    }
+}
+
+impl Endpoint {
+    /// The default endpoint on `host`.
+    pub fn on_host(host: &str) -> Endpoint {
+        Endpoint {
+            host: host.to_string(),
+            ..Endpoint::default()
+        }
+    }
+
+    /// Parses a `ws://` or `wss://` URL. The port defaults to the scheme's, and the path to
+    /// `/`. Query values are expected to be percent-encoded already.
+    pub fn parse(url: &str) -> Result<Endpoint, EndpointError> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .ok_or_else(|| EndpointError::InvalidScheme(url.to_string()))?;
+        let scheme = Scheme::from_name(scheme)?;
+        let (rest, query) = match rest.split_once('?') {
+            Some((rest, query)) => (rest, Some(query)),
+            None => (rest, None),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            // an IPv6 address without a port
+            Some((_, port)) if port.ends_with(']') => (authority, None),
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        };
+        let port = match port {
+            Some(port) => parse_port(port)?,
+            None => scheme.default_port(),
+        };
+        let query = query
+            .into_iter()
+            .flat_map(|query| query.split('&'))
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Ok((decode(key)?, decode(value)?))
+            })
+            .collect::<Result<Vec<_>, EndpointError>>()?;
+
+        let endpoint = Endpoint {
+            scheme,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+            query,
+        };
+        endpoint.validate()?;
+        Ok(endpoint)
+    }
+
+    pub fn validate(&self) -> Result<(), EndpointError> {
+        if self.host.is_empty() {
+            return Err(EndpointError::MissingHost);
+        }
+        let is_ipv6 = self.host.starts_with('[') && self.host.ends_with(']');
+        let is_valid_host = self.host.chars().all(|c| {
+            c.is_ascii_alphanumeric() || c == '-' || c == '.' || (is_ipv6 && ":[]".contains(c))
+        });
+        if !is_valid_host {
+            return Err(EndpointError::InvalidHost(self.host.clone()));
+        }
+        if self.port == 0 {
+            return Err(EndpointError::InvalidPort(self.port.to_string()));
+        }
+        let is_valid_path = self.path.starts_with('/')
+            && !self
+                .path
+                .chars()
+                .any(|c| c.is_whitespace() || c == '?' || c == '#');
+        if !is_valid_path {
+            return Err(EndpointError::InvalidPath(self.path.clone()));
+        }
+        if let Some((key, _)) = self.query.iter().find(|(key, _)| key.is_empty()) {
+            return Err(EndpointError::InvalidQuery(key.clone()));
+        }
+        Ok(())
+    }
+
+    /// Sets the query parameter `key`, replacing its value if it is already set.
+    pub fn set_query(&mut self, key: &str, value: &str) {
+        match self.query.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.query.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}://{}:{}{}",
+            self.scheme.name(),
+            self.host,
+            self.port,
+            self.path
+        )?;
+        for (i, (key, value)) in self.query.iter().enumerate() {
+            let separator = if i == 0 { '?' } else { '&' };
+            write!(f, "{}{}={}", separator, encode(key), encode(value))?;
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_port(port: &str) -> Result<u16, EndpointError> {
+    match port.parse() {
+        Ok(port) if port != 0 => Ok(port),
+        _ => Err(EndpointError::InvalidPort(port.to_string())),
+    }
+}
+
+fn encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn decode(text: &str) -> Result<String, EndpointError> {
+    let invalid = || EndpointError::InvalidQuery(text.to_string());
+    let mut bytes = Vec::new();
+    let mut rest = text.bytes();
+    while let Some(byte) = rest.next() {
+        match byte {
+            b'%' => {
+                let hex = [
+                    rest.next().ok_or_else(invalid)?,
+                    rest.next().ok_or_else(invalid)?,
+                ];
+                let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            }
+            b'+' => bytes.push(b' '),
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_urls_with_encoded_queries() {
+        let mut endpoint = Endpoint::on_host("192.168.0.10");
+        assert_eq!(endpoint.url(), "ws://192.168.0.10:8080/ws");
+
+        endpoint.scheme = Scheme::Wss;
+        endpoint.port = 443;
+        endpoint.path = "/relay/ws".to_string();
+        endpoint.set_query("room", "demo day");
+        endpoint.set_query("room", "demo & co");
+        assert_eq!(
+            endpoint.url(),
+            "wss://192.168.0.10:443/relay/ws?room=demo%20%26%20co"
+        );
+    }
+
+    #[test]
+    fn parses_what_it_builds() {
+        let endpoint = Endpoint::parse("wss://cube.example.com/relay/ws?room=a%20b&x").unwrap();
+        assert_eq!(endpoint.scheme, Scheme::Wss);
+        assert_eq!(endpoint.port, 443);
+        assert_eq!(endpoint.path, "/relay/ws");
+        assert_eq!(
+            endpoint.query,
+            [
+                ("room".to_string(), "a b".to_string()),
+                ("x".to_string(), String::new())
+            ]
+        );
+        assert_eq!(Endpoint::parse(&endpoint.url()), Ok(endpoint));
+        assert_eq!(Endpoint::parse("ws://[::1]").unwrap().host, "[::1]");
+    }
+
+    #[test]
+    fn rejects_invalid_endpoints() {
+        let parse = |url| Endpoint::parse(url).unwrap_err();
+        assert_eq!(
+            parse("http://cube/ws"),
+            EndpointError::InvalidScheme("http".to_string())
+        );
+        assert_eq!(parse("ws://:8080/ws"), EndpointError::MissingHost);
+        assert_eq!(
+            parse("ws://cube:80a/ws"),
+            EndpointError::InvalidPort("80a".to_string())
+        );
+        assert_eq!(
+            parse("ws://cu be/ws"),
+            EndpointError::InvalidHost("cu be".to_string())
+        );
+        assert_eq!(
+            parse("ws://cube/ws?room=%zz"),
+            EndpointError::InvalidQuery("%zz".to_string())
+        );
+        let endpoint = Endpoint {
+            path: "ws".to_string(),
+            ..Endpoint::default()
+        };
+        assert_eq!(
+            endpoint.validate(),
+            Err(EndpointError::InvalidPath("ws".to_string()))
+        );
+    }
+}
@@ -1,10 +1,15 @@
-use self::config::Config;
+use std::collections::HashMap;
+
 pub use self::{
+    config::{Config, ConfigError, TransportKind},
+    endpoint::{Endpoint, EndpointError, Scheme},
     keyboard::{from_key_code, modifiers, Key, Keyboard},
     viewport::Viewport,
 };
+use crate::network::{ConnectionState, Latency, NetworkStats};
 
 mod config;
+mod endpoint;
 mod keyboard;
 mod viewport;
 
@@ -13,6 +18,12 @@ pub struct AppState {
     pub viewport: Option<Viewport>,
     pub keyboard: Keyboard,
     pub config: Config,
+    /// Latency of every connection, by pool name.
+    pub latency: HashMap<&'static str, Latency>,
+    /// State of every connection, by pool name.
+    pub connections: HashMap<&'static str, ConnectionState>,
+    /// Traffic received on every connection, by pool name.
+    pub stats: HashMap<&'static str, NetworkStats>,
 }
 
 impl AppState {
@@ -20,7 +31,10 @@ impl AppState {
         AppState {
             viewport: None,
             keyboard: Keyboard::new(),
-            config: Config::new("".to_string()),
+            config: Config::default(),
+            latency: HashMap::new(),
+            connections: HashMap::new(),
+            stats: HashMap::new(),
         }
     }
 }
@@ -0,0 +1,352 @@
+//! Native controller for the relay, to drive the renderer without a phone.
+//!
+//! It speaks the same binary frames as the renderer and the phone, through
+//! `cube_renderer::utils`, so what it sends is exactly what a controller would.
+
+mod motion;
+mod socket;
+
+extern crate nalgebra_glm as glm;
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    process::ExitCode,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use cube_renderer::utils::{
+    Codec, Encoding, EntityTransform, Envelope, Handshake, Message, Role, Stamp, Target, Transform,
+};
+use motion::Pattern;
+use socket::{Endpoint, Frame, Writer};
+
+const USAGE: &str = "\
+usage: cube-cli [--url URL] [--name NAME] <command>
+
+  --url URL        relay endpoint (default ws://localhost:8080/ws)
+  --name NAME      name sent in the handshake (default cli)
+
+commands:
+  rotate           reads `x y z` angles in degrees from stdin, one rotation per line
+  synth [PATTERN]  sends synthetic motion, `spin` (default) or `wobble`
+      --rate HZ        rotations per second (default 30)
+      --duration SECS  stops after this long (default never)
+  record FILE      writes every message received to FILE, one JSON envelope per line
+  replay FILE      sends the messages recorded in FILE with their original timing
+      --speed X        plays X times faster (default 1)
+      --loop           starts over at the end
+";
+
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+}
+
+/// A connection to the relay, introduced to every peer with a handshake.
+struct Session {
+    writer: Arc<Mutex<Writer>>,
+    sender: u32,
+    sequence: u32,
+    reader: thread::JoinHandle<()>,
+}
+
+impl Session {
+    /// Connects to `endpoint` and hands every message other than handshakes and heartbeats
+    /// to `on_message`, on a separate thread.
+    fn connect(
+        endpoint: &Endpoint,
+        handshake: Handshake,
+        mut on_message: impl FnMut(Envelope) + Send + 'static,
+    ) -> io::Result<Session> {
+        let (mut reader, writer) = socket::connect(endpoint)?;
+        let writer = Arc::new(Mutex::new(writer));
+        let sender = (now() as u64 ^ std::process::id() as u64).wrapping_mul(0x9E37_79B9) as u32;
+        send_unsequenced(&writer, sender, Message::Handshake(handshake.clone()))?;
+
+        let replies = writer.clone();
+        let reader = thread::spawn(move || {
+            let mut codec = Codec::new(Encoding::Full);
+            let mut peers = HashSet::new();
+            loop {
+                let envelope = match reader.read() {
+                    Ok(Frame::Binary(bytes)) => codec.decode(&bytes),
+                    Ok(Frame::Text(text)) => codec.decode_json(&text),
+                    Ok(Frame::Close) => {
+                        eprintln!("relay closed the connection");
+                        return;
+                    }
+                    Err(e) => {
+                        eprintln!("connection lost: {}", e);
+                        return;
+                    }
+                };
+                let envelope = match envelope {
+                    Ok(envelope) => envelope,
+                    Err(e) => {
+                        eprintln!("undecodable frame: {:?}", e);
+                        continue;
+                    }
+                };
+                let from = envelope.stamp.sender;
+                let reply = match &envelope.message {
+                    Message::Handshake(peer) => {
+                        eprintln!(
+                            "peer {:?} ({}) joined as {}",
+                            peer.name,
+                            from,
+                            peer.role.name()
+                        );
+                        peers
+                            .insert(from)
+                            .then(|| Message::Handshake(handshake.clone()))
+                    }
+                    Message::Ping(id) => Some(Message::Pong { to: from, id: *id }),
+                    Message::Pong { .. } | Message::Ack { .. } => None,
+                    Message::Error {
+                        to,
+                        sequence,
+                        error,
+                    } => {
+                        if *to == sender {
+                            eprintln!("peer {} rejected frame {}: {:?}", from, sequence, error);
+                        }
+                        None
+                    }
+                    _ => {
+                        on_message(envelope);
+                        None
+                    }
+                };
+                if let Some(reply) = reply {
+                    if send_unsequenced(&replies, sender, reply).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Session {
+            writer,
+            sender,
+            sequence: 0,
+            reader,
+        })
+    }
+
+    fn send(&mut self, message: Message) -> io::Result<()> {
+        self.sequence += 1;
+        let stamp = Stamp {
+            sender: self.sender,
+            sequence: self.sequence,
+            timestamp: now(),
+        };
+        let bytes = encode(Envelope::new(stamp, message))?;
+        self.writer.lock().unwrap().send_binary(&bytes)
+    }
+
+    fn rotate(&mut self, euler: glm::Vec3) -> io::Result<()> {
+        self.send(Message::Transform(EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Rotate(euler),
+        }))
+    }
+
+    fn close(self) -> io::Result<()> {
+        self.writer.lock().unwrap().close()
+    }
+}
+
+fn send_unsequenced(writer: &Mutex<Writer>, sender: u32, message: Message) -> io::Result<()> {
+    let stamp = Stamp {
+        sender,
+        sequence: 0,
+        timestamp: now(),
+    };
+    let bytes = encode(Envelope::new(stamp, message))?;
+    writer.lock().unwrap().send_binary(&bytes)
+}
+
+fn encode(envelope: Envelope) -> io::Result<Vec<u8>> {
+    envelope
+        .encode()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", e)))
+}
+
+/// Command line arguments, with options taken out as they are read.
+struct Args(Vec<String>);
+
+impl Args {
+    fn option(&mut self, name: &str) -> Result<Option<String>, String> {
+        let Some(i) = self.0.iter().position(|arg| arg == name) else {
+            return Ok(None);
+        };
+        if i + 1 >= self.0.len() {
+            return Err(format!("{} needs a value", name));
+        }
+        self.0.remove(i);
+        Ok(Some(self.0.remove(i)))
+    }
+
+    fn parsed<T: std::str::FromStr>(&mut self, name: &str) -> Result<Option<T>, String> {
+        match self.option(name)? {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid {} {:?}", name, value)),
+            None => Ok(None),
+        }
+    }
+
+    fn flag(&mut self, name: &str) -> bool {
+        let position = self.0.iter().position(|arg| arg == name);
+        position.map(|i| self.0.remove(i)).is_some()
+    }
+
+    fn next(&mut self) -> Option<String> {
+        (!self.0.is_empty()).then(|| self.0.remove(0))
+    }
+}
+
+fn rotate(session: &mut Session) -> Result<(), String> {
+    eprintln!("enter `x y z` in degrees, one rotation per line");
+    for line in io::stdin().lock().lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let angles: Result<Vec<f32>, _> = line.split_whitespace().map(str::parse).collect();
+        match angles.as_deref() {
+            Ok(&[x, y, z]) => {
+                let euler = glm::vec3(x.to_radians(), y.to_radians(), z.to_radians());
+                session.rotate(euler).map_err(|e| e.to_string())?;
+            }
+            Ok([]) => {}
+            _ => eprintln!("expected three angles, got {:?}", line),
+        }
+    }
+    Ok(())
+}
+
+fn synth(session: &mut Session, pattern: Pattern, rate: f32, duration: Option<f32>) {
+    let period = Duration::from_secs_f32(1.0 / rate.max(0.1));
+    let start = Instant::now();
+    loop {
+        let t = start.elapsed().as_secs_f32();
+        if duration.is_some_and(|duration| t >= duration) {
+            return;
+        }
+        if let Err(e) = session.rotate(pattern.sample(t)) {
+            eprintln!("send failed: {}", e);
+            return;
+        }
+        thread::sleep(period);
+    }
+}
+
+fn replay(session: &mut Session, path: &str, speed: f64, repeat: bool) -> Result<(), String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut recording = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let envelope =
+            Envelope::from_json(&line).map_err(|e| format!("{}:{}: {:?}", path, i + 1, e))?;
+        recording.push(envelope);
+    }
+    eprintln!("replaying {} messages from {}", recording.len(), path);
+
+    loop {
+        let mut previous = recording.first().map(|envelope| envelope.stamp.timestamp);
+        for envelope in &recording {
+            if let Some(previous) = previous {
+                let wait = (envelope.stamp.timestamp - previous).max(0.0) / speed;
+                thread::sleep(Duration::from_secs_f64(wait / 1000.0));
+            }
+            previous = Some(envelope.stamp.timestamp);
+            session
+                .send(envelope.message.clone())
+                .map_err(|e| e.to_string())?;
+        }
+        if !repeat {
+            return Ok(());
+        }
+    }
+}
+
+fn run(mut args: Args) -> Result<(), String> {
+    let url = args
+        .option("--url")?
+        .unwrap_or_else(|| "ws://localhost:8080/ws".to_string());
+    let name = args.option("--name")?.unwrap_or_else(|| "cli".to_string());
+    let endpoint = Endpoint::parse(&url)?;
+    let command = args.next().ok_or_else(|| USAGE.to_string())?;
+
+    let controller = Handshake::new(&name, Role::Controller, vec![Target::Owner]);
+    let connect = |handshake: Handshake, on_message: Box<dyn FnMut(Envelope) + Send>| {
+        Session::connect(&endpoint, handshake, on_message)
+            .map_err(|e| format!("can't connect to {}: {}", url, e))
+    };
+
+    match command.as_str() {
+        "rotate" => {
+            let mut session = connect(controller, Box::new(|_| {}))?;
+            rotate(&mut session)?;
+            let _ = session.close();
+        }
+        "synth" => {
+            let rate = args.parsed("--rate")?.unwrap_or(30.0);
+            let duration = args.parsed("--duration")?;
+            let pattern = match args.next() {
+                Some(name) => Pattern::from_name(&name)
+                    .ok_or_else(|| format!("unknown pattern {:?}", name))?,
+                None => Pattern::Spin,
+            };
+            let mut session = connect(controller, Box::new(|_| {}))?;
+            synth(&mut session, pattern, rate, duration);
+            let _ = session.close();
+        }
+        "record" => {
+            let path = args.next().ok_or("record needs a file")?;
+            let file = File::create(&path).map_err(|e| format!("{}: {}", path, e))?;
+            let mut file = BufWriter::new(file);
+            let recorder = Handshake::new(&name, Role::Recorder, Vec::new());
+            let session = connect(
+                recorder,
+                Box::new(move |envelope: Envelope| {
+                    let _ = writeln!(file, "{}", envelope.to_json());
+                    let _ = file.flush();
+                }),
+            )?;
+            eprintln!("recording to {}, stop with Ctrl-C", path);
+            let _ = session.reader.join();
+        }
+        "replay" => {
+            let speed: f64 = args.parsed("--speed")?.unwrap_or(1.0);
+            if !(speed.is_finite() && speed > 0.0) {
+                return Err(format!("--speed must be a positive number\n\n{}", USAGE));
+            }
+            let repeat = args.flag("--loop");
+            let path = args.next().ok_or("replay needs a file")?;
+            let mut session = connect(controller, Box::new(|_| {}))?;
+            replay(&mut session, &path, speed, repeat)?;
+            let _ = session.close();
+        }
+        _ => return Err(USAGE.to_string()),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = Args(std::env::args().skip(1).collect());
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e.trim_end());
+            ExitCode::FAILURE
+        }
+    }
+}
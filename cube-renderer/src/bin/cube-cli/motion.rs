@@ -0,0 +1,32 @@
+use std::f32::consts::TAU;
+
+/// Synthetic motion, as Euler angles over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+    /// A full turn around the vertical axis every 4 seconds.
+    Spin,
+    /// Rocks back and forth on two axes at different rates, like a phone held in a hand.
+    Wobble,
+}
+
+impl Pattern {
+    pub fn from_name(name: &str) -> Option<Pattern> {
+        match name {
+            "spin" => Some(Pattern::Spin),
+            "wobble" => Some(Pattern::Wobble),
+            _ => None,
+        }
+    }
+
+    /// The orientation at `t` seconds, in radians.
+    pub fn sample(&self, t: f32) -> glm::Vec3 {
+        match self {
+            Pattern::Spin => glm::vec3(0.0, (t / 4.0 * TAU) % TAU, 0.0),
+            Pattern::Wobble => glm::vec3(
+                0.4 * (t * TAU / 3.0).sin(),
+                0.0,
+                0.25 * (t * TAU / 1.7).sin(),
+            ),
+        }
+    }
+}
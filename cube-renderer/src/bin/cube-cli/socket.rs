@@ -0,0 +1,342 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use cube_renderer::utils::Limits;
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+/// A complete message received from the relay.
+#[derive(Debug, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+}
+
+/// Where to connect, from a `ws://host:port/path` URL.
+#[derive(Debug, PartialEq)]
+pub struct Endpoint {
+    pub host: String,
+    pub port: u16,
+    /// Path and query, starting with `/`.
+    pub path: String,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Endpoint, String> {
+        let rest = url
+            .strip_prefix("ws://")
+            .ok_or_else(|| format!("only ws:// URLs are supported, got {:?}", url))?;
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('/') => (&rest[..i], rest[i..].to_string()),
+            Some(i) => (&rest[..i], format!("/{}", &rest[i..])),
+            None => (rest, "/".to_string()),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("invalid port {:?}", port))?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in {:?}", url));
+        }
+        Ok(Endpoint {
+            host: host.to_string(),
+            port,
+            path,
+        })
+    }
+}
+
+/// Reading half of a WebSocket client connection.
+pub struct Reader {
+    stream: BufReader<TcpStream>,
+    writer: Writer,
+}
+
+/// Writing half of a WebSocket client connection.
+pub struct Writer {
+    stream: TcpStream,
+    seed: u64,
+}
+
+/// Opens a WebSocket to `endpoint`, returning both halves of the connection.
+pub fn connect(endpoint: &Endpoint) -> io::Result<(Reader, Writer)> {
+    let mut stream = TcpStream::connect((endpoint.host.as_str(), endpoint.port))?;
+    stream.set_nodelay(true)?;
+    let mut seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0x2545_f491_4f6c_dd1d, |d| d.as_nanos() as u64)
+        | 1;
+
+    let mut key = [0u8; 16];
+    for chunk in key.chunks_mut(8) {
+        chunk.copy_from_slice(&next_random(&mut seed).to_le_bytes());
+    }
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\n\
+         Host: {}:{}\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\n\
+         Sec-WebSocket-Version: 13\r\n\r\n",
+        endpoint.path,
+        endpoint.host,
+        endpoint.port,
+        base64(&key)
+    )?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    if status.split_whitespace().nth(1) != Some("101") {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("upgrade refused: {}", status.trim()),
+        ));
+    }
+    // the rest of the response headers aren't needed
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+            break;
+        }
+    }
+
+    let writer = Writer { stream, seed };
+    let reader = Reader {
+        stream: reader,
+        writer: writer.try_clone()?,
+    };
+    Ok((reader, writer))
+}
+
+impl Writer {
+    pub fn try_clone(&self) -> io::Result<Writer> {
+        Ok(Writer {
+            stream: self.stream.try_clone()?,
+            seed: self.seed.rotate_left(17) | 1,
+        })
+    }
+
+    pub fn send_binary(&mut self, payload: &[u8]) -> io::Result<()> {
+        self.send(OP_BINARY, payload)
+    }
+
+    pub fn close(&mut self) -> io::Result<()> {
+        self.send(OP_CLOSE, &1000u16.to_be_bytes())
+    }
+
+    /// Writes a single masked frame, as clients must.
+    fn send(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mask = (next_random(&mut self.seed) as u32).to_be_bytes();
+        let frame = encode_frame(opcode, payload, mask);
+        self.stream.write_all(&frame)
+    }
+}
+
+impl Reader {
+    /// Blocks until the next complete message. Pings are answered on the way.
+    pub fn read(&mut self) -> io::Result<Frame> {
+        let mut message = Vec::new();
+        let mut message_opcode = None;
+        loop {
+            let (fin, opcode, payload) = read_frame(&mut self.stream)?;
+            match opcode {
+                OP_PING => self.writer.send(OP_PONG, &payload)?,
+                OP_PONG => {}
+                OP_CLOSE => return Ok(Frame::Close),
+                OP_TEXT | OP_BINARY | OP_CONTINUATION => {
+                    if opcode != OP_CONTINUATION {
+                        message_opcode = Some(opcode);
+                    }
+                    check_len((message.len() + payload.len()) as u64)?;
+                    message.extend_from_slice(&payload);
+                    if fin {
+                        return match message_opcode {
+                            Some(OP_TEXT) => String::from_utf8(message)
+                                .map(Frame::Text)
+                                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+                            _ => Ok(Frame::Binary(message)),
+                        };
+                    }
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown opcode {:#x}", opcode),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+/// Reads one frame from `stream`. Frames longer than `Limits::max_frame_len` are refused
+/// before their payload is read.
+fn read_frame(stream: &mut impl Read) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head)?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0F;
+    let masked = head[1] & 0x80 != 0;
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as u64
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            stream.read_exact(&mut len)?;
+            u64::from_be_bytes(len)
+        }
+        len => len as u64,
+    };
+    let len = check_len(len)?;
+    let mut mask = [0u8; 4];
+    if masked {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    if masked {
+        apply_mask(&mut payload, mask);
+    }
+    Ok((fin, opcode, payload))
+}
+
+/// Refuses frames, and messages split over several of them, longer than
+/// `Limits::max_frame_len`.
+fn check_len(len: u64) -> io::Result<usize> {
+    let max = Limits::default().max_frame_len;
+    match usize::try_from(len) {
+        Ok(len) if len <= max => Ok(len),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds {} bytes", len, max),
+        )),
+    }
+}
+
+fn encode_frame(opcode: u8, payload: &[u8], mask: [u8; 4]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len if len < 126 => frame.push(0x80 | len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    let start = frame.len();
+    frame.extend_from_slice(payload);
+    apply_mask(&mut frame[start..], mask);
+    frame
+}
+
+fn apply_mask(payload: &mut [u8], mask: [u8; 4]) {
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+}
+
+/// xorshift64, good enough for masks and handshake keys.
+fn next_random(seed: &mut u64) -> u64 {
+    *seed ^= *seed << 13;
+    *seed ^= *seed >> 7;
+    *seed ^= *seed << 17;
+    *seed
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_endpoints() {
+        let endpoint = Endpoint::parse("ws://localhost:8080/ws?room=demo").unwrap();
+        assert_eq!(endpoint.host, "localhost");
+        assert_eq!(endpoint.port, 8080);
+        assert_eq!(endpoint.path, "/ws?room=demo");
+        assert_eq!(Endpoint::parse("ws://relay").unwrap().path, "/");
+        assert!(Endpoint::parse("http://relay/ws").is_err());
+    }
+
+    #[test]
+    fn frames_are_masked() {
+        let frame = encode_frame(OP_BINARY, b"cube", [1, 2, 3, 4]);
+        assert_eq!(frame[..2], [0x82, 0x84]);
+        let mut payload = frame[6..].to_vec();
+        apply_mask(&mut payload, [1, 2, 3, 4]);
+        assert_eq!(payload, b"cube");
+
+        let long = encode_frame(OP_BINARY, &[0; 300], [0; 4]);
+        assert_eq!(long[1], 0x80 | 126);
+        assert_eq!(u16::from_be_bytes([long[2], long[3]]), 300);
+    }
+
+    #[test]
+    fn refuses_oversized_frames() {
+        let max = Limits::default().max_frame_len;
+        let mut frame = vec![0x82, 127];
+        frame.extend_from_slice(&(max as u64 + 1).to_be_bytes());
+        let error = read_frame(&mut io::Cursor::new(frame)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut frame = vec![0x82, 127];
+        frame.extend_from_slice(&u64::MAX.to_be_bytes());
+        let error = read_frame(&mut io::Cursor::new(frame)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let frame = encode_frame(OP_BINARY, b"cube", [1, 2, 3, 4]);
+        let (fin, opcode, payload) = read_frame(&mut io::Cursor::new(frame)).unwrap();
+        assert_eq!(
+            (fin, opcode, payload.as_slice()),
+            (true, OP_BINARY, &b"cube"[..])
+        );
+    }
+
+    #[test]
+    fn encodes_base64() {
+        assert_eq!(base64(b"cube"), "Y3ViZQ==");
+        assert_eq!(base64(b"the cube"), "dGhlIGN1YmU=");
+    }
+}
@@ -4,11 +4,11 @@ mod model;
 mod network;
 mod resources;
 mod sandbox;
-mod utils;
+pub mod utils;
 
 use std::{cell::RefCell, rc::Rc, sync::Mutex};
 
-use app::{from_key_code, App, AppState};
+use app::{from_key_code, App, AppState, Config};
 use sandbox::{load_shaders, make_cube, make_lights};
 use utils::window;
 use wasm_bindgen::prelude::*;
@@ -43,9 +43,56 @@ fn start_loop(mut app: App) {
     request_animation_frame(g.borrow().as_ref().unwrap());
 }
 
+#[wasm_bindgen(typescript_custom_section)]
+const RUN_OPTIONS: &str = r#"
+export interface RunOptions {
+    url?: string;
+    scheme?: "ws" | "wss";
+    host?: string;
+    port?: number | string;
+    path?: string;
+    query?: Record<string, string>;
+    transport?: "relay" | "broadcast";
+    format?: "binary" | "json";
+    reconnect?: boolean | {
+        initialDelay?: number;
+        maxDelay?: number;
+        multiplier?: number;
+        jitter?: number;
+        maxAttempts?: number;
+    };
+    limits?: {
+        maxFrameLen?: number;
+        maxNameLen?: number;
+        maxItems?: number;
+        maxAngle?: number;
+        maxTranslation?: number;
+        maxScale?: number;
+        quatTolerance?: number;
+        finiteOnly?: boolean;
+    };
+    capacity?: number;
+    overflowPolicy?: "drop_oldest" | "drop_newest" | "coalesce";
+    interpolationDelay?: number;
+    statsOverlay?: boolean;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "RunOptions | string")]
+    pub type RunOptions;
+}
+
+/// Starts the renderer. `options` is either the relay's host or `RunOptions`, with the
+/// relay's endpoint and how to reach the controllers: `"relay"`, the default, goes through
+/// the relay, and `"broadcast"` talks to controllers in other tabs of this browser without
+/// it. See `Config::from_js` for every option.
 #[wasm_bindgen]
-pub async fn run(host: String) -> Result<(), JsValue> {
-    let mut app = App::new(host)?;
+pub async fn run(options: RunOptions) -> Result<(), JsValue> {
+    let config = Config::from_js(&options)?;
+    console::info!("Relay endpoint {}", config.endpoint);
+    let mut app = App::new(config)?;
     init_events()?;
 
     load_shaders(&mut app)
@@ -112,11 +159,67 @@ fn on_keyup(e: web_sys::KeyboardEvent) {
     state.keyboard.on_keyup(key);
 }
 
+/// The state of the connection in `pool`, as `{ state, reason }`, where `state` is one of
+/// `"connecting"`, `"open"`, `"closing"`, `"closed"` or `"error"` and `reason` is only set for
+/// errors. `undefined` until the pool's connection is created.
+#[wasm_bindgen(js_name = connectionState)]
+pub fn connection_state(pool: &str) -> JsValue {
+    let state = HANDLE.lock().unwrap();
+    let Some(connection) = state.connections.get(pool) else {
+        return JsValue::UNDEFINED;
+    };
+    let object = js_sys::Object::new();
+    let _ = js_sys::Reflect::set(&object, &"state".into(), &connection.name().into());
+    let reason = connection.reason().map_or(JsValue::NULL, JsValue::from);
+    let _ = js_sys::Reflect::set(&object, &"reason".into(), &reason);
+    object.into()
+}
+
+/// Traffic received in `pool`, as `{ messages, bytes, messagesPerSecond, bytesPerSecond,
+/// decodeErrors, dropped, droppedOldest, droppedNewest, coalesced, lastMessageAge }`, where
+/// `dropped` counts stale and duplicate messages, the next three the messages lost to the
+/// pool's capacity, and `lastMessageAge` is in milliseconds and `null` until a message
+/// arrives. `undefined` until the pool's connection is created.
+#[wasm_bindgen(js_name = networkStats)]
+pub fn network_stats(pool: &str) -> JsValue {
+    let state = HANDLE.lock().unwrap();
+    let Some(stats) = state.stats.get(pool) else {
+        return JsValue::UNDEFINED;
+    };
+    let object = js_sys::Object::new();
+    let fields = [
+        ("messages", JsValue::from(stats.messages as f64)),
+        ("bytes", JsValue::from(stats.bytes as f64)),
+        ("messagesPerSecond", stats.messages_per_second.into()),
+        ("bytesPerSecond", stats.bytes_per_second.into()),
+        ("decodeErrors", stats.decode_errors.into()),
+        ("dropped", stats.dropped.into()),
+        ("droppedOldest", stats.overflow.dropped_oldest.into()),
+        ("droppedNewest", stats.overflow.dropped_newest.into()),
+        ("coalesced", stats.overflow.coalesced.into()),
+        (
+            "lastMessageAge",
+            stats.last_message_age.map_or(JsValue::NULL, JsValue::from),
+        ),
+    ];
+    for (name, value) in fields {
+        let _ = js_sys::Reflect::set(&object, &name.into(), &value);
+    }
+    object.into()
+}
+
+/// Shows or hides the network stats drawn over the canvas.
+#[wasm_bindgen(js_name = setStatsOverlay)]
+pub fn set_stats_overlay(enabled: bool) {
+    HANDLE.lock().unwrap().config.stats_overlay = enabled;
+}
+
 #[wasm_bindgen]
 pub fn greet() {
     console::log!("Cube initialized");
 }
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
@@ -135,6 +238,35 @@ extern "C" {
     fn clear();
 }
 
+/// Outside of the browser, as in native tests, the console is stderr.
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(dead_code)]
+mod native_console {
+    pub fn log(a: &str) {
+        eprintln!("{}", a);
+    }
+    pub fn info(a: &str) {
+        eprintln!("{}", a);
+    }
+    pub fn error(a: &str) {
+        eprintln!("error: {}", a);
+    }
+    pub fn warn(a: &str) {
+        eprintln!("warning: {}", a);
+    }
+    pub fn debug(a: &str) {
+        eprintln!("{}", a);
+    }
+    pub fn trace(a: &str) {
+        eprintln!("{}", a);
+    }
+    pub fn clear() {}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[allow(unused_imports)]
+use native_console::{clear, debug, error, info, log, trace, warn};
+
 #[allow(unused_macros, unused_imports)]
 pub mod console {
     macro_rules! log {
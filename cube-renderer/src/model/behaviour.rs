@@ -6,4 +6,8 @@ use super::entity::EntityState;
 
 pub trait Behaviour {
     fn update(&mut self, dt: f32, entity: &mut EntityState, state: &mut MutexGuard<AppState>);
+
+    /// Called when the entity is removed, to let go of what the behaviour holds outside of
+    /// it, such as connections and the entries it keeps in `state`.
+    fn release(&mut self, _state: &mut MutexGuard<AppState>) {}
 }
@@ -2,7 +2,11 @@ use std::sync::MutexGuard;
 
 use web_sys::WebGl2RenderingContext;
 
-use crate::app::AppState;
+use crate::{
+    app::AppState,
+    console,
+    utils::{EntitySnapshot, EntityTransform, Message, Target},
+};
 
 use super::{DrawableContext, Entity, Light};
 
@@ -34,8 +38,26 @@ impl EntityBuffer {
         self.entities.iter_mut().find(|e| e.id == id)
     }
 
-    pub fn remove(&mut self, id: u32) {
-        self.entities.retain(|e| e.id != id);
+    pub fn get_by_name_mut(&mut self, name: &str) -> Option<&mut Entity> {
+        self.entities
+            .iter_mut()
+            .find(|e| e.name.as_deref() == Some(name))
+    }
+
+    /// Resolves `target` as seen from the entity with id `owner`.
+    pub fn find_mut(&mut self, owner: u32, target: &Target) -> Option<&mut Entity> {
+        match target {
+            Target::Owner => self.get_mut(owner),
+            Target::Id(id) => self.get_mut(*id),
+            Target::Name(name) => self.get_by_name_mut(name),
+        }
+    }
+
+    /// Removes the entity with `id`, releasing its behaviour's connections and state.
+    pub fn remove(&mut self, id: u32, state: &mut MutexGuard<AppState>) {
+        if let Some(i) = self.entities.iter().position(|e| e.id == id) {
+            self.entities.remove(i).release(state);
+        }
     }
 
     pub fn get_renderables(&self) -> Vec<&Entity> {
@@ -71,8 +93,251 @@ impl EntityBuffer {
     }
 
     pub fn update(&mut self, dt: f32, state: &mut MutexGuard<AppState>) {
+        let mut posted = Vec::new();
         for entity in self.entities.iter_mut() {
             entity.update(dt, state);
+            let owner = entity.id;
+            posted.extend(entity.take_posted().into_iter().map(|p| (owner, p)));
+        }
+
+        for (owner, (message, sent_at)) in posted {
+            self.dispatch(owner, message, sent_at);
+        }
+    }
+
+    fn dispatch(&mut self, owner: u32, message: Message, sent_at: Option<f64>) {
+        match message {
+            Message::Transform(transform) => self.route(owner, transform, sent_at),
+            Message::Batch(transforms) => self.route_batch(owner, transforms, sent_at),
+            Message::Sync => {
+                let snapshot = Message::Snapshot(self.snapshot());
+                if let Some(entity) = self.get_mut(owner) {
+                    entity.deliver(snapshot);
+                }
+            }
+            Message::Snapshot(entities) => self.apply_snapshot(&entities),
+            message => console::warn!("Unhandled message from entity {}: {:?}", owner, message),
+        }
+    }
+
+    /// Applies `transform` to the entity it targets, through its jitter buffer if the
+    /// transform came from a peer that stamped it `sent_at`.
+    pub fn route(&mut self, owner: u32, transform: EntityTransform, sent_at: Option<f64>) {
+        match (self.find_mut(owner, &transform.target), sent_at) {
+            (Some(entity), Some(sent_at)) => entity.apply_remote(&transform.transform, sent_at),
+            (Some(entity), None) => entity.apply(&transform.transform),
+            (None, _) => console::warn!("No entity matches {:?}", transform.target),
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<EntitySnapshot> {
+        self.entities.iter().map(|e| e.snapshot()).collect()
+    }
+
+    /// Applies a scene snapshot, matching entities by name, or by id when unnamed.
+    pub fn apply_snapshot(&mut self, entities: &[EntitySnapshot]) {
+        for snapshot in entities {
+            let entity = match &snapshot.name {
+                Some(name) => self.get_by_name_mut(name),
+                None => self.get_mut(snapshot.id),
+            };
+            match entity {
+                Some(entity) => entity.apply_snapshot(snapshot),
+                None => console::warn!("Snapshot entity {} not found", snapshot.id),
+            }
+        }
+    }
+
+    /// Routes every transform of a batch, or none of them if any target can't be resolved.
+    pub fn route_batch(
+        &mut self,
+        owner: u32,
+        transforms: Vec<EntityTransform>,
+        sent_at: Option<f64>,
+    ) {
+        if let Some(t) = transforms
+            .iter()
+            .find(|t| self.find_mut(owner, &t.target).is_none())
+        {
+            console::warn!("Dropping batch, no entity matches {:?}", t.target);
+            return;
+        }
+        for transform in transforms {
+            self.route(owner, transform, sent_at);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, sync::Mutex};
+
+    use super::*;
+    use crate::{
+        model::{Behaviour, EntityState},
+        utils::{Envelope, Stamp, Transform},
+    };
+
+    /// Posts `outgoing` on its first update, and keeps what the buffer delivers back.
+    struct Probe {
+        outgoing: Vec<Message>,
+        delivered: Rc<RefCell<Vec<Message>>>,
+    }
+
+    impl Behaviour for Probe {
+        fn update(&mut self, _dt: f32, entity: &mut EntityState, _: &mut MutexGuard<AppState>) {
+            self.delivered.borrow_mut().extend(entity.take_delivered());
+            for message in self.outgoing.drain(..) {
+                entity.post_remote(Envelope::new(Stamp::default(), message));
+            }
+        }
+    }
+
+    fn entity(name: Option<&str>, position: glm::Vec3) -> Entity {
+        let mut entity = Entity::new(position);
+        if let Some(name) = name {
+            entity.set_name(name);
+        }
+        entity
+    }
+
+    fn position(entities: &EntityBuffer, id: u32) -> glm::Vec3 {
+        entities.get(id).unwrap().snapshot().position
+    }
+
+    #[test]
+    fn answers_sync_with_a_snapshot_of_every_entity() {
+        let mut entities = EntityBuffer::new();
+        entities.add(entity(Some("cube"), glm::vec3(1.0, 2.0, 3.0)));
+        entities.add(entity(None, glm::vec3(4.0, 5.0, 6.0)));
+        let delivered = Rc::new(RefCell::new(Vec::new()));
+        let mut probe = entity(Some("probe"), glm::vec3(0.0, 0.0, 0.0));
+        probe.add_behaviour(Box::new(Probe {
+            outgoing: vec![Message::Sync],
+            delivered: delivered.clone(),
+        }));
+        entities.add(probe);
+
+        let handle = Mutex::new(AppState::new());
+        let mut state = handle.lock().unwrap();
+        entities.update(16.0, &mut state);
+        entities.update(16.0, &mut state);
+
+        let delivered = delivered.borrow();
+        let [Message::Snapshot(snapshot)] = delivered.as_slice() else {
+            panic!("expected one snapshot, got {:?}", delivered);
+        };
+        let described: Vec<_> = snapshot
+            .iter()
+            .map(|e| (e.id, e.name.as_deref(), e.position))
+            .collect();
+        assert_eq!(
+            described,
+            [
+                (1, Some("cube"), glm::vec3(1.0, 2.0, 3.0)),
+                (2, None, glm::vec3(4.0, 5.0, 6.0)),
+                (3, Some("probe"), glm::vec3(0.0, 0.0, 0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_snapshots_by_name_or_id() {
+        let mut entities = EntityBuffer::new();
+        entities.add(entity(Some("cube"), glm::vec3(0.0, 0.0, 0.0)));
+        entities.add(entity(None, glm::vec3(0.0, 0.0, 0.0)));
+        // remote translations still to be played by both entities
+        for id in [1, 2] {
+            let translate = EntityTransform {
+                target: Target::Id(id),
+                transform: Transform::Translate(glm::vec3(10.0, 0.0, 0.0)),
+            };
+            entities.route(id, translate, Some(0.0));
+        }
+
+        let snapshot = |id, name: Option<&str>, x| EntitySnapshot {
+            id,
+            name: name.map(str::to_string),
+            position: glm::vec3(x, 0.0, 0.0),
+            rotation: glm::quat_identity(),
+            scale: glm::vec3(2.0, 2.0, 2.0),
+        };
+        entities.apply_snapshot(&[
+            // the name wins over the id
+            snapshot(2, Some("cube"), 1.0),
+            snapshot(2, None, 2.0),
+            snapshot(9, None, 9.0),
+        ]);
+        assert_eq!(position(&entities, 1), glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!(position(&entities, 2), glm::vec3(2.0, 0.0, 0.0));
+        assert_eq!(
+            entities.get(2).unwrap().snapshot().scale,
+            glm::vec3(2.0, 2.0, 2.0)
+        );
+
+        // the snapshot discarded the pending translations
+        let handle = Mutex::new(AppState::new());
+        let mut state = handle.lock().unwrap();
+        entities.update(500.0, &mut state);
+        assert_eq!(position(&entities, 1), glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!(position(&entities, 2), glm::vec3(2.0, 0.0, 0.0));
+    }
+
+    fn translate(target: Target, x: f32) -> EntityTransform {
+        EntityTransform {
+            target,
+            transform: Transform::Translate(glm::vec3(x, 0.0, 0.0)),
         }
     }
+
+    #[test]
+    fn routes_transforms_by_owner_id_and_name() {
+        let mut entities = EntityBuffer::new();
+        entities.add(entity(Some("a"), glm::vec3(0.0, 0.0, 0.0)));
+        entities.add(entity(Some("b"), glm::vec3(0.0, 0.0, 0.0)));
+        entities.add(entity(None, glm::vec3(0.0, 0.0, 0.0)));
+
+        assert_eq!(entities.find_mut(3, &Target::Owner).unwrap().id, 3);
+        assert_eq!(entities.find_mut(3, &Target::Id(1)).unwrap().id, 1);
+        let name = Target::Name("b".to_string());
+        assert_eq!(entities.find_mut(3, &name).unwrap().id, 2);
+        assert!(entities.find_mut(3, &Target::Id(7)).is_none());
+        assert!(entities
+            .find_mut(3, &Target::Name("c".to_string()))
+            .is_none());
+
+        entities.route(3, translate(Target::Owner, 3.0), None);
+        entities.route(3, translate(Target::Id(1), 1.0), None);
+        entities.route(3, translate(name, 2.0), None);
+        entities.route(3, translate(Target::Id(7), 7.0), None);
+        for id in 1..=3 {
+            assert_eq!(position(&entities, id), glm::vec3(id as f32, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn routes_batches_whole_or_not_at_all() {
+        let mut entities = EntityBuffer::new();
+        entities.add(entity(Some("a"), glm::vec3(0.0, 0.0, 0.0)));
+        entities.add(entity(None, glm::vec3(0.0, 0.0, 0.0)));
+
+        let unresolved = vec![
+            translate(Target::Id(1), 1.0),
+            translate(Target::Owner, 2.0),
+            translate(Target::Name("c".to_string()), 3.0),
+        ];
+        entities.route_batch(2, unresolved, None);
+        assert_eq!(position(&entities, 1), glm::vec3(0.0, 0.0, 0.0));
+        assert_eq!(position(&entities, 2), glm::vec3(0.0, 0.0, 0.0));
+
+        let batch = vec![
+            translate(Target::Name("a".to_string()), 1.0),
+            translate(Target::Owner, 2.0),
+            // applied in order, so the last transform of an entity wins
+            translate(Target::Id(1), 4.0),
+        ];
+        entities.route_batch(2, batch, None);
+        assert_eq!(position(&entities, 1), glm::vec3(4.0, 0.0, 0.0));
+        assert_eq!(position(&entities, 2), glm::vec3(2.0, 0.0, 0.0));
+    }
 }
@@ -2,17 +2,23 @@ use std::sync::MutexGuard;
 
 use web_sys::WebGl2RenderingContext;
 
-use crate::app::AppState;
+use crate::{
+    app::AppState,
+    utils::{quat_from_euler, EntitySnapshot, Envelope, Message, Transform},
+};
 
 use super::{
-    behaviour::Behaviour,
-    renderable::Renderable,
-    transition::{self},
-    DrawableContext, Light,
+    behaviour::Behaviour, interpolation::JitterBuffer, renderable::Renderable, DrawableContext,
+    Light,
 };
 
+/// A message posted by a behaviour, with the time its sender stamped it if it came from a
+/// peer.
+pub type Posted = (Message, Option<f64>);
+
 pub struct Entity {
     pub id: u32,
+    pub name: Option<String>,
     renderable: Option<Renderable>,
     behaviour: Option<Box<dyn Behaviour>>,
     state: EntityState,
@@ -22,9 +28,10 @@ impl Entity {
     pub fn new(position: glm::Vec3) -> Self {
         Self {
             id: 0,
+            name: None,
             renderable: None,
             behaviour: None,
-            state: EntityState::new(position, glm::vec3(0.0, 0.0, 0.0)),
+            state: EntityState::new(position, glm::quat_identity()),
         }
     }
 
@@ -32,6 +39,10 @@ impl Entity {
         self.id = id;
     }
 
+    pub fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+
     pub fn add_renderable(&mut self, renderable: Renderable) {
         self.renderable = Some(renderable);
     }
@@ -60,12 +71,62 @@ impl Entity {
         })
     }
 
+    /// Releases the behaviour, which is dropped.
+    pub fn release(&mut self, state: &mut MutexGuard<AppState>) {
+        if let Some(mut behaviour) = self.behaviour.take() {
+            behaviour.release(state);
+        }
+    }
+
     pub fn update(&mut self, dt: f32, state: &mut MutexGuard<AppState>) {
+        self.state.advance(dt);
         if let Some(behaviour) = self.behaviour.as_mut() {
             behaviour.update(dt, &mut self.state, state);
         }
     }
 
+    pub fn apply(&mut self, transform: &Transform) {
+        self.state.apply(transform);
+    }
+
+    /// Plays `transform`, stamped `sent_at` by its sender, back through the entity's jitter
+    /// buffer.
+    pub fn apply_remote(&mut self, transform: &Transform, sent_at: f64) {
+        self.state.apply_remote(transform, sent_at);
+    }
+
+    /// Sets how far behind its sender remote transforms are rendered, in ms.
+    pub fn set_interpolation_delay(&mut self, delay: f64) {
+        self.state.remote.delay = delay;
+    }
+
+    pub fn snapshot(&self) -> EntitySnapshot {
+        EntitySnapshot {
+            id: self.id,
+            name: self.name.clone(),
+            position: self.state.position,
+            rotation: self.state.rotation,
+            scale: self.state.scale,
+        }
+    }
+
+    pub fn apply_snapshot(&mut self, snapshot: &EntitySnapshot) {
+        self.state.set_position(snapshot.position);
+        self.state
+            .set_rotation(glm::quat_normalize(&snapshot.rotation));
+        self.state.set_scale(snapshot.scale);
+    }
+
+    /// Hands a message to this entity's behaviour, to be read on its next update.
+    pub fn deliver(&mut self, message: Message) {
+        self.state.deliver(message);
+    }
+
+    /// Takes the messages posted by this entity's behaviour during the last update.
+    pub fn take_posted(&mut self) -> Vec<Posted> {
+        self.state.take_posted()
+    }
+
     pub fn draw<'a>(
         &'a mut self,
         gl: &WebGl2RenderingContext,
@@ -80,16 +141,27 @@ impl Entity {
 
 pub struct EntityState {
     position: glm::Vec3,
-    rotation: glm::Vec3,
+    rotation: glm::Quat,
+    scale: glm::Vec3,
     is_dirty: bool,
+    /// Milliseconds since the entity was created, advanced on every update.
+    clock: f64,
+    remote: JitterBuffer,
+    posted: Vec<Posted>,
+    delivered: Vec<Message>,
 }
 
 impl EntityState {
-    pub fn new(position: glm::Vec3, rotation: glm::Vec3) -> Self {
+    pub fn new(position: glm::Vec3, rotation: glm::Quat) -> Self {
         Self {
             position,
             rotation,
+            scale: glm::vec3(1.0, 1.0, 1.0),
             is_dirty: true,
+            clock: 0.0,
+            remote: JitterBuffer::new(),
+            posted: Vec::new(),
+            delivered: Vec::new(),
         }
     }
 
@@ -99,27 +171,136 @@ impl EntityState {
         }
 
         renderable.translate(self.position);
-        // renderable.rotate(self.rotation);
-        renderable.smooth_rotate(self.rotation, 100.0, transition::easing::ease_in_out);
+        renderable.scale(self.scale);
+        renderable.rotate(self.rotation);
 
         self.is_dirty = false;
     }
 
+    /// Advances the clock by `dt` ms and moves to where the jitter buffer puts the entity.
+    pub fn advance(&mut self, dt: f32) {
+        self.clock += dt as f64;
+        let sampled = self.remote.sample(self.clock);
+        if let Some(rotation) = sampled.rotation {
+            self.rotation = rotation;
+            self.is_dirty = true;
+        }
+        if let Some(position) = sampled.position {
+            self.position = position;
+            self.is_dirty = true;
+        }
+        if let Some(scale) = sampled.scale {
+            self.scale = scale;
+            self.is_dirty = true;
+        }
+    }
+
+    /// Sets the position right away, discarding remote positions still to be played.
     pub fn set_position(&mut self, position: glm::Vec3) {
         self.position = position;
+        self.remote.position.clear();
         self.is_dirty = true;
     }
 
-    pub fn set_rotation(&mut self, rotation: glm::Vec3) {
+    /// Sets the rotation right away, discarding remote rotations still to be played.
+    pub fn set_rotation(&mut self, rotation: glm::Quat) {
         self.rotation = rotation;
+        self.remote.rotation.clear();
         self.is_dirty = true;
     }
 
+    /// Sets the scale right away, discarding remote scales still to be played.
+    pub fn set_scale(&mut self, scale: glm::Vec3) {
+        self.scale = scale;
+        self.remote.scale.clear();
+        self.is_dirty = true;
+    }
+
+    pub fn apply(&mut self, transform: &Transform) {
+        match transform {
+            Transform::Rotate(euler) => self.set_rotation(quat_from_euler(euler)),
+            Transform::RotateQuat(rotation) => self.set_rotation(glm::quat_normalize(rotation)),
+            Transform::Translate(position) => self.set_position(*position),
+            Transform::Scale(scale) => self.set_scale(*scale),
+        }
+    }
+
+    /// Plays `transform`, stamped `sent_at` by its sender, back through the jitter buffer,
+    /// `JitterBuffer::delay` ms behind the sender.
+    pub fn apply_remote(&mut self, transform: &Transform, sent_at: f64) {
+        self.remote.push(transform, sent_at, self.clock);
+    }
+
+    /// Posts a message received from a peer. Its transforms are interpolated by the entities
+    /// they target instead of applied right away, see `apply_remote`.
+    ///
+    /// Legacy and unstamped frames carry no timestamp, they are played back as of their
+    /// arrival instead.
+    pub fn post_remote(&mut self, envelope: Envelope) {
+        let sent_at = match envelope.stamp.timestamp {
+            0.0 => self.clock,
+            timestamp => timestamp,
+        };
+        self.posted.push((envelope.message, Some(sent_at)));
+    }
+
+    pub fn deliver(&mut self, message: Message) {
+        self.delivered.push(message);
+    }
+
+    pub fn take_posted(&mut self) -> Vec<Posted> {
+        std::mem::take(&mut self.posted)
+    }
+
+    /// Takes the messages the entity buffer delivered in response to posted ones.
+    pub fn take_delivered(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.delivered)
+    }
+
     pub fn get_position(&self) -> glm::Vec3 {
         self.position
     }
 
-    pub fn get_rotation(&self) -> glm::Vec3 {
+    pub fn get_rotation(&self) -> glm::Quat {
         self.rotation
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{EntityTransform, Stamp, Target};
+
+    #[test]
+    fn unstamped_transforms_are_interpolated_as_of_their_arrival() {
+        let mut state = EntityState::new(glm::vec3(0.0, 0.0, 0.0), glm::quat_identity());
+        let mut shown = Vec::new();
+        // 1.5 s of legacy frames, one every 50 ms
+        for i in 0..30 {
+            state.advance(50.0);
+            shown.push(state.position.x);
+            let translate = EntityTransform {
+                target: Target::Owner,
+                transform: Transform::Translate(glm::vec3(i as f32, 0.0, 0.0)),
+            };
+            let envelope = Envelope::new(Stamp::default(), Message::Transform(translate));
+            state.post_remote(envelope);
+            for (message, sent_at) in state.take_posted() {
+                let Message::Transform(transform) = message else {
+                    panic!("expected a transform")
+                };
+                state.apply_remote(&transform.transform, sent_at.unwrap());
+            }
+        }
+
+        // rendered `DEFAULT_DELAY` behind, one step per frame, never reset
+        for (i, pair) in shown[4..].windows(2).enumerate() {
+            assert!(
+                (pair[1] - pair[0] - 1.0).abs() < 1e-4,
+                "frame {}: {:?}",
+                i + 4,
+                pair
+            );
+        }
+    }
+}
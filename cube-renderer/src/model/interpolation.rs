@@ -0,0 +1,357 @@
+use std::collections::VecDeque;
+
+use crate::utils::{quat_from_euler, quat_slerp, Transform};
+
+/// How far behind the newest sample remote transforms are rendered by default, in ms.
+pub const DEFAULT_DELAY: f64 = 100.0;
+/// How far past the newest sample motion is predicted by default, in ms.
+pub const DEFAULT_MAX_EXTRAPOLATION: f64 = 250.0;
+/// Samples kept per track. Older ones are dropped even if they weren't played yet.
+const MAX_SAMPLES: usize = 64;
+/// How much later than the fastest one seen so far a sample may arrive before the sender's
+/// clock is assumed to have changed, in ms.
+const RESYNC_AFTER: f64 = 1000.0;
+/// Weight of the velocity between the two newest samples in the smoothed velocity. A value
+/// that stopped moving stops being predicted right away instead.
+const VELOCITY_GAIN: f32 = 0.5;
+/// How long a prediction error takes to blend out once the late sample arrived, in ms.
+pub const CORRECTION_TIME: f64 = 100.0;
+
+/// A value that moves between samples: it can be interpolated, advanced by a velocity
+/// derived from two samples, and corrected by an error that fades out.
+pub trait Blend: Copy {
+    fn blend(a: &Self, b: &Self, t: f32) -> Self;
+
+    /// The velocity, per ms, of going from `a` to `b` in `dt` ms. Angular velocities are
+    /// given as the rotation axis scaled by the angle.
+    fn velocity(a: &Self, b: &Self, dt: f64) -> glm::Vec3;
+
+    /// Where `value` ends up moving at `velocity` for `dt` ms.
+    fn advance(value: &Self, velocity: &glm::Vec3, dt: f64) -> Self;
+
+    /// What takes `to` to `from`.
+    fn error(from: &Self, to: &Self) -> Self;
+
+    /// `value` moved by `weight` of `error`, between 0 and 1.
+    fn correct(value: &Self, error: &Self, weight: f32) -> Self;
+}
+
+impl Blend for glm::Vec3 {
+    fn blend(a: &Self, b: &Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+
+    fn velocity(a: &Self, b: &Self, dt: f64) -> glm::Vec3 {
+        (b - a) / dt as f32
+    }
+
+    fn advance(value: &Self, velocity: &glm::Vec3, dt: f64) -> Self {
+        value + velocity * dt as f32
+    }
+
+    fn error(from: &Self, to: &Self) -> Self {
+        from - to
+    }
+
+    fn correct(value: &Self, error: &Self, weight: f32) -> Self {
+        value + error * weight
+    }
+}
+
+impl Blend for glm::Quat {
+    fn blend(a: &Self, b: &Self, t: f32) -> Self {
+        quat_slerp(a, b, t)
+    }
+
+    fn velocity(a: &Self, b: &Self, dt: f64) -> glm::Vec3 {
+        let delta = Self::error(b, a);
+        let angle = glm::quat_angle(&delta);
+        if angle < 1e-6 {
+            return glm::Vec3::zeros();
+        }
+        glm::quat_axis(&delta) * (angle / dt as f32)
+    }
+
+    fn advance(value: &Self, velocity: &glm::Vec3, dt: f64) -> Self {
+        let angle = glm::length(velocity) * dt as f32;
+        if angle < 1e-6 {
+            return *value;
+        }
+        let turn = glm::quat_angle_axis(angle, &glm::normalize(velocity));
+        glm::quat_normalize(&(turn * value))
+    }
+
+    fn error(from: &Self, to: &Self) -> Self {
+        // along the shortest arc
+        let from = if glm::quat_dot(from, to) < 0.0 {
+            -from
+        } else {
+            *from
+        };
+        from * glm::quat_inverse(to)
+    }
+
+    fn correct(value: &Self, error: &Self, weight: f32) -> Self {
+        let error = quat_slerp(&glm::quat_identity(), error, weight);
+        glm::quat_normalize(&(error * value))
+    }
+}
+
+/// Timestamped samples of one value, oldest first, and the velocity they move at.
+#[derive(Debug, Clone)]
+pub struct Track<T> {
+    samples: VecDeque<(f64, T)>,
+    /// Smoothed velocity between consecutive samples, per ms.
+    velocity: glm::Vec3,
+    /// The last value sampled, when it was sampled, and whether it was predicted.
+    shown: Option<(f64, T, bool)>,
+    /// Whether a sample arrived since the last one was shown.
+    is_fresh: bool,
+    /// How far the prediction was off when a late sample arrived, and when it did.
+    correction: Option<(f64, T)>,
+}
+
+impl<T: Blend> Track<T> {
+    pub fn new() -> Track<T> {
+        Track {
+            samples: VecDeque::new(),
+            velocity: glm::Vec3::zeros(),
+            shown: None,
+            is_fresh: false,
+            correction: None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Track::new();
+    }
+
+    /// Adds the value at `time`. Samples older than the newest one are dropped, they arrived
+    /// out of order. A sample equal to the previous one stops the prediction.
+    pub fn push(&mut self, time: f64, value: T) {
+        if let Some(&(last_time, last)) = self.samples.back() {
+            if time < last_time {
+                return;
+            }
+            if time > last_time {
+                let velocity = T::velocity(&last, &value, time - last_time);
+                self.velocity = if self.samples.len() == 1 || velocity == glm::Vec3::zeros() {
+                    velocity
+                } else {
+                    self.velocity + (velocity - self.velocity) * VELOCITY_GAIN
+                };
+            }
+        }
+        self.samples.push_back((time, value));
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.is_fresh = true;
+    }
+
+    /// The value at `time`, interpolated between the samples around it. Past the newest one,
+    /// it keeps moving at the track's velocity for at most `max_extrapolation` ms before it
+    /// holds still. Before the oldest one, the oldest value is held.
+    ///
+    /// When a sample arrives while the value was being predicted, the prediction error is
+    /// blended out over `CORRECTION_TIME` instead of snapping to the sample.
+    pub fn sample(&mut self, time: f64, max_extrapolation: f64) -> Option<T> {
+        let (value, is_predicted) = self.raw_sample(time, max_extrapolation)?;
+
+        if std::mem::take(&mut self.is_fresh) {
+            if let Some((_, shown, true)) = self.shown {
+                self.correction = Some((time, T::error(&shown, &value)));
+            }
+        }
+        let value = match self.correction {
+            Some((since, error)) if time - since < CORRECTION_TIME => {
+                let weight = 1.0 - (time - since) / CORRECTION_TIME;
+                T::correct(&value, &error, weight.max(0.0) as f32)
+            }
+            _ => {
+                self.correction = None;
+                value
+            }
+        };
+        self.shown = Some((time, value, is_predicted));
+        Some(value)
+    }
+
+    /// The value on the path through the samples at `time`, and whether it was predicted.
+    fn raw_sample(&mut self, time: f64, max_extrapolation: f64) -> Option<(T, bool)> {
+        // samples before the one preceding `time` won't be needed again
+        while self.samples.len() > 2 && self.samples[1].0 <= time {
+            self.samples.pop_front();
+        }
+
+        let &(a_time, a) = self.samples.front()?;
+        if time <= a_time {
+            return Some((a, false));
+        }
+        let &(b_time, b) = self.samples.back()?;
+        if time > b_time {
+            let ahead = (time - b_time).min(max_extrapolation);
+            return Some((T::advance(&b, &self.velocity, ahead), ahead > 0.0));
+        }
+        let (b_time, b) = self.samples[1];
+        let t = (time - a_time) / (b_time - a_time);
+        Some((T::blend(&a, &b, t as f32), false))
+    }
+}
+
+/// Where remote transforms put an entity, rendered `delay` ms in the past so that samples
+/// arriving at an irregular rate can be interpolated instead of jumped to. When samples are
+/// late anyway, the entity keeps moving at the velocity of the last ones, dead reckoning, for
+/// up to `max_extrapolation` ms.
+///
+/// Samples are stamped by the sender's clock. They are mapped to the local one with the
+/// smallest difference between both seen so far, i.e. the fastest delivery.
+#[derive(Debug, Clone)]
+pub struct JitterBuffer {
+    pub delay: f64,
+    pub max_extrapolation: f64,
+    /// Local time minus sender time for the fastest sample so far.
+    offset: Option<f64>,
+    pub rotation: Track<glm::Quat>,
+    pub position: Track<glm::Vec3>,
+    pub scale: Track<glm::Vec3>,
+}
+
+/// The values a `JitterBuffer` holds at some point in time, for the tracks that have any.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sampled {
+    pub rotation: Option<glm::Quat>,
+    pub position: Option<glm::Vec3>,
+    pub scale: Option<glm::Vec3>,
+}
+
+impl JitterBuffer {
+    pub fn new() -> JitterBuffer {
+        JitterBuffer {
+            delay: DEFAULT_DELAY,
+            max_extrapolation: DEFAULT_MAX_EXTRAPOLATION,
+            offset: None,
+            rotation: Track::new(),
+            position: Track::new(),
+            scale: Track::new(),
+        }
+    }
+
+    /// Adds a transform sent at `sent_at` by the sender's clock, received at `now`.
+    pub fn push(&mut self, transform: &Transform, sent_at: f64, now: f64) {
+        let offset = now - sent_at;
+        let offset = match self.offset {
+            Some(fastest) if offset <= fastest + RESYNC_AFTER => fastest.min(offset),
+            _ => {
+                // a new sender, or one whose clock jumped: start over
+                self.clear();
+                offset
+            }
+        };
+        self.offset = Some(offset);
+
+        let time = sent_at + offset;
+        match transform {
+            Transform::Rotate(euler) => self.rotation.push(time, quat_from_euler(euler)),
+            Transform::RotateQuat(rotation) => {
+                self.rotation.push(time, glm::quat_normalize(rotation))
+            }
+            Transform::Translate(position) => self.position.push(time, *position),
+            Transform::Scale(scale) => self.scale.push(time, *scale),
+        }
+    }
+
+    /// The values to render at `now`.
+    pub fn sample(&mut self, now: f64) -> Sampled {
+        let time = now - self.delay;
+        Sampled {
+            rotation: self.rotation.sample(time, self.max_extrapolation),
+            position: self.position.sample(time, self.max_extrapolation),
+            scale: self.scale.sample(time, self.max_extrapolation),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rotation.clear();
+        self.position.clear();
+        self.scale.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn x(value: glm::Vec3) -> f32 {
+        value.x
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let mut track = Track::new();
+        track.push(0.0, glm::vec3(0.0, 0.0, 0.0));
+        track.push(100.0, glm::vec3(10.0, 0.0, 0.0));
+        track.push(300.0, glm::vec3(30.0, 0.0, 0.0));
+
+        assert_eq!(track.sample(-50.0, 0.0).map(x), Some(0.0));
+        assert_eq!(track.sample(50.0, 0.0).map(x), Some(5.0));
+        assert_eq!(track.sample(200.0, 0.0).map(x), Some(20.0));
+        assert_eq!(track.sample(300.0, 0.0).map(x), Some(30.0));
+    }
+
+    #[test]
+    fn extrapolates_late_samples_up_to_a_limit() {
+        let mut track = Track::new();
+        track.push(0.0, glm::vec3(0.0, 0.0, 0.0));
+        track.push(100.0, glm::vec3(10.0, 0.0, 0.0));
+
+        assert_eq!(track.sample(150.0, 100.0).map(x), Some(15.0));
+        assert_eq!(track.sample(500.0, 100.0).map(x), Some(20.0));
+        // out of order samples don't move it back
+        track.push(50.0, glm::vec3(-100.0, 0.0, 0.0));
+        assert_eq!(track.sample(500.0, 0.0).map(x), Some(10.0));
+    }
+
+    #[test]
+    fn predicted_rotations_keep_turning() {
+        let axis = glm::vec3(0.0, 1.0, 0.0);
+        let mut track = Track::new();
+        track.push(0.0, glm::quat_angle_axis(0.0, &axis));
+        track.push(100.0, glm::quat_angle_axis(0.2, &axis));
+
+        let velocity = track.velocity;
+        assert!((velocity.y - 0.002).abs() < 1e-6, "{:?}", velocity);
+        let predicted = track.sample(200.0, 250.0).unwrap();
+        assert!((glm::quat_angle(&predicted) - 0.4).abs() < 1e-4);
+    }
+
+    #[test]
+    fn blends_out_prediction_errors() {
+        let mut track = Track::new();
+        track.push(0.0, glm::vec3(0.0, 0.0, 0.0));
+        track.push(100.0, glm::vec3(10.0, 0.0, 0.0));
+        assert_eq!(track.sample(200.0, 1000.0).map(x), Some(20.0));
+
+        // it stopped at 10 instead, the velocity drops to zero and the error fades
+        track.push(200.0, glm::vec3(10.0, 0.0, 0.0));
+        assert_eq!(track.velocity, glm::Vec3::zeros());
+        assert_eq!(track.sample(200.0, 1000.0).map(x), Some(20.0));
+        let halfway = track.sample(250.0, 1000.0).map(x).unwrap();
+        assert!((halfway - 15.0).abs() < 1e-4, "{}", halfway);
+        assert_eq!(track.sample(300.0, 1000.0).map(x), Some(10.0));
+        assert_eq!(track.sample(1000.0, 1000.0).map(x), Some(10.0));
+    }
+
+    #[test]
+    fn renders_remote_samples_behind_the_sender() {
+        let mut buffer = JitterBuffer::new();
+        buffer.delay = 50.0;
+        // the sender's clock is far ahead of ours, the second sample arrives late
+        let translate = |x| Transform::Translate(glm::vec3(x, 0.0, 0.0));
+        buffer.push(&translate(0.0), 1_000_000.0, 10.0);
+        buffer.push(&translate(10.0), 1_000_100.0, 140.0);
+
+        assert_eq!(buffer.sample(110.0).position.map(x), Some(5.0));
+        assert_eq!(buffer.sample(110.0).rotation, None);
+    }
+}
@@ -1,6 +1,7 @@
 mod behaviour;
 mod buffer;
 mod entity;
+mod interpolation;
 mod light;
 mod material;
 mod mesh;
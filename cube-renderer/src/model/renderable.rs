@@ -5,6 +5,7 @@ use crate::{
     camera::Camera,
     console,
     resources::{Assets, Shader},
+    utils::model_matrix,
 };
 
 use super::{mesh::Mesh, transition::Transition, Light, Material};
@@ -14,9 +15,10 @@ pub struct Renderable {
     material: Material,
     pub light: Option<Light>,
     pub shader: Option<String>,
-    rotation: glm::Vec3,
+    rotation: glm::Quat,
     position: glm::Vec3,
-    rotation_transition: Option<Transition<glm::Vec3>>,
+    scale: glm::Vec3,
+    rotation_transition: Option<Transition<glm::Quat>>,
     position_transition: Option<Transition<glm::Vec3>>,
 }
 
@@ -28,8 +30,9 @@ impl Renderable {
             material,
             shader: None,
             light: None,
-            rotation: glm::vec3(0.0, 0.0, 0.0),
+            rotation: glm::quat_identity(),
             position: glm::vec3(0.0, 0.0, 0.0),
+            scale: glm::vec3(1.0, 1.0, 1.0),
             rotation_transition: None,
             position_transition: None,
         }
@@ -73,11 +76,16 @@ impl Renderable {
             Some(Transition::new(self.position, position, duration, function));
     }
 
-    pub fn rotate(&mut self, rotation: glm::Vec3) {
+    pub fn rotate(&mut self, rotation: glm::Quat) {
         self.rotation = rotation;
     }
 
-    pub fn smooth_rotate(&mut self, rotation: glm::Vec3, duration: f32, function: fn(f32) -> f32) {
+    pub fn scale(&mut self, scale: glm::Vec3) {
+        self.scale = scale;
+    }
+
+    #[allow(dead_code)]
+    pub fn smooth_rotate(&mut self, rotation: glm::Quat, duration: f32, function: fn(f32) -> f32) {
         self.rotation_transition =
             Some(Transition::new(self.rotation, rotation, duration, function));
     }
@@ -91,6 +99,7 @@ impl Renderable {
         self.apply_transitions(dt);
         ctx.rotation = self.rotation;
         ctx.position = self.position;
+        ctx.scale = self.scale;
         let shader = self.get_shader(ctx.assets);
         ctx.shader = Some(shader);
         ctx.material = Some(&self.material);
@@ -121,8 +130,9 @@ pub struct DrawableContext<'a> {
     pub shader: Option<&'a Shader>,
     pub material: Option<&'a Material>,
     pub lights: Option<Vec<Light>>,
-    pub rotation: glm::Vec3,
+    pub rotation: glm::Quat,
     pub position: glm::Vec3,
+    pub scale: glm::Vec3,
 }
 
 impl<'a> DrawableContext<'a> {
@@ -140,17 +150,13 @@ impl<'a> DrawableContext<'a> {
             shader: None,
             material: None,
             lights: None,
-            rotation: glm::vec3(0.0, 0.0, 0.0),
+            rotation: glm::quat_identity(),
             position: glm::vec3(0.0, 0.0, 0.0),
+            scale: glm::vec3(1.0, 1.0, 1.0),
         }
     }
 
     pub(crate) fn get_model_matrix(&self) -> glm::Mat4 {
-        let mut model = glm::identity();
-        model = glm::translate(&model, &self.position);
-        model = glm::rotate(&model, self.rotation.x, &glm::vec3(1.0, 0.0, 0.0));
-        model = glm::rotate(&model, self.rotation.y, &glm::vec3(0.0, 1.0, 0.0));
-        model = glm::rotate(&model, self.rotation.z, &glm::vec3(0.0, 0.0, 1.0));
-        model
+        model_matrix(&self.position, &self.rotation, &self.scale)
     }
 }
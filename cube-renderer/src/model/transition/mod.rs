@@ -1,3 +1,5 @@
+use crate::utils::quat_slerp;
+
 pub mod easing;
 
 pub struct Transition<T> {
@@ -42,6 +44,15 @@ impl Transition<glm::Vec3> {
     }
 }
 
+impl Transition<glm::Quat> {
+    pub fn update(&mut self, dt: f32) -> glm::Quat {
+        self.elapsed += dt;
+        let time = self.get_time();
+        let c = (self.function)(time);
+        quat_slerp(&self.start, &self.end, c)
+    }
+}
+
 pub trait TransitionFn {
     fn apply(&mut self, dt: f32) -> f32;
 }
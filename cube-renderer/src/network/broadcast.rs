@@ -0,0 +1,367 @@
+use std::{cell::Cell, rc::Rc};
+
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+use web_sys::MessageEvent;
+
+use super::{
+    connection::ConnectionState,
+    inbox::OverflowPolicy,
+    outbox::Delivery,
+    pool::{acquire_pool, get_pool, release_pool, Pool, POOLS},
+    stats::NetworkStats,
+    transport::Transport,
+};
+use crate::{
+    console,
+    utils::{Encoding, Envelope, Handshake, Limits, Message, Stamp},
+};
+
+/// This side of a channel, shared with its listener.
+struct Local {
+    pool: &'static str,
+    channel: web_sys::BroadcastChannel,
+    sender: u32,
+    handshake: Handshake,
+    closed: Cell<bool>,
+}
+
+impl Local {
+    fn post(&self, bytes: &[u8]) -> Result<(), JsValue> {
+        self.channel
+            .post_message(&js_sys::Uint8Array::from(bytes).into())
+    }
+
+    /// Sends `message` unsequenced and in the full binary encoding, like
+    /// `WebSocket` does for the handshake.
+    fn send_unsequenced(&self, message: Message) {
+        let stamp = Stamp {
+            sender: self.sender,
+            sequence: 0,
+            timestamp: js_sys::Date::now(),
+        };
+        let bytes = match Envelope::new(stamp, message).encode() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                console::error!("BroadcastChannel encode error: {:?}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.post(&bytes) {
+            console::error!("BroadcastChannel send error: {:?}", e);
+        }
+    }
+}
+
+/// A connection to the other tabs of this browser, through a `BroadcastChannel` named after
+/// the pool. It carries the same binary frames as `WebSocket`, so a controller page in
+/// another tab can drive the renderer without the relay.
+///
+/// A channel has no peer on the other end until a tab joins it, so it is open as soon as it
+/// is created, and stays open until `close` is called.
+pub struct Broadcast {
+    local: Rc<Local>,
+    sequence: u32,
+    /// Owned so that it lives exactly as long as the channel it listens to.
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+#[allow(dead_code)]
+impl Broadcast {
+    /// Joins the channel named `pool`, introducing this side to every tab with `handshake`.
+    pub fn new(pool: &'static str, handshake: Handshake) -> Result<Self, JsValue> {
+        let channel = web_sys::BroadcastChannel::new(pool)?;
+        acquire_pool(pool);
+        let local = Rc::new(Local {
+            pool,
+            channel,
+            sender: (js_sys::Math::random() * u32::MAX as f64) as u32,
+            handshake,
+            closed: Cell::new(false),
+        });
+
+        let on_message = {
+            let local = local.clone();
+            Closure::wrap(Box::new(move |e: MessageEvent| {
+                Self::on_message(&local, e);
+            }) as Box<dyn FnMut(MessageEvent)>)
+        };
+        local
+            .channel
+            .set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        local.send_unsequenced(Message::Handshake(local.handshake.clone()));
+        Ok(Self {
+            local,
+            sequence: 0,
+            _on_message: on_message,
+        })
+    }
+
+    fn on_message(local: &Local, e: MessageEvent) {
+        let Ok(data) = e.data().dyn_into::<js_sys::Uint8Array>() else {
+            console::warn!("BroadcastChannel ignored {:?}", e.data());
+            return;
+        };
+        let data = data.to_vec();
+        let mut pools = POOLS.lock().unwrap();
+        let pool = get_pool!(pools, local.pool);
+        let reply = receive_frame(
+            pool,
+            local.sender,
+            &local.handshake,
+            &data,
+            js_sys::Date::now(),
+        );
+        drop(pools);
+        if let Some(reply) = reply {
+            local.send_unsequenced(reply);
+        }
+    }
+
+    /// Sets the encoding this channel offers for outgoing binary transforms.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool)
+            .codec
+            .set_preferred(encoding);
+    }
+
+    /// Sets what incoming frames may contain, see `WebSocket::set_limits`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool).codec.set_limits(limits);
+    }
+
+    /// Sets how many received messages are kept until they are polled.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool)
+            .messages
+            .set_capacity(capacity);
+    }
+
+    /// Sets what happens to received messages once the pool is full.
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool)
+            .messages
+            .set_policy(policy);
+    }
+}
+
+impl Transport for Broadcast {
+    /// Takes the messages received since the last call, and acks them to their senders.
+    fn poll(&mut self) -> Vec<Envelope> {
+        let (messages, unacked) = {
+            let mut pools = POOLS.lock().unwrap();
+            let pool = get_pool!(pools, self.local.pool);
+            let messages = pool.messages.drain().collect();
+            (messages, pool.unacked.drain().collect::<Vec<_>>())
+        };
+        if !self.local.closed.get() {
+            for (to, sequence) in unacked {
+                self.local.send_unsequenced(Message::Ack { to, sequence });
+            }
+        }
+        messages
+    }
+
+    fn send(&mut self, message: &Message) -> Delivery {
+        if self.local.closed.get() {
+            return Delivery::Failed;
+        }
+        self.sequence += 1;
+        let stamp = Stamp {
+            sender: self.local.sender,
+            sequence: self.sequence,
+            timestamp: js_sys::Date::now(),
+        };
+        let bytes = {
+            let mut pools = POOLS.lock().unwrap();
+            get_pool!(pools, self.local.pool)
+                .codec
+                .encode(&Envelope::new(stamp, message.clone()))
+        };
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                console::error!("BroadcastChannel encode error: {:?}", e);
+                return Delivery::Failed;
+            }
+        };
+        match self.local.post(&bytes) {
+            Ok(()) => Delivery::Sent,
+            Err(e) => {
+                console::error!("BroadcastChannel send error: {:?}", e);
+                Delivery::Failed
+            }
+        }
+    }
+
+    /// Leaves the channel. Messages already received can still be polled.
+    fn close(&mut self) {
+        self.local.channel.set_onmessage(None);
+        self.local.channel.close();
+        self.local.closed.set(true);
+    }
+
+    fn state(&self) -> ConnectionState {
+        if self.local.closed.get() {
+            ConnectionState::Closed
+        } else {
+            ConnectionState::Open
+        }
+    }
+
+    fn stats(&self) -> NetworkStats {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool).stats(js_sys::Date::now())
+    }
+}
+
+/// Handles a frame of `data` posted by another tab at `now`, returning the message to answer
+/// it with, if any: see `Pool::receive`. Frames that can't be decoded are reported to their
+/// sender, when the frame names one.
+fn receive_frame(
+    pool: &mut Pool,
+    sender: u32,
+    handshake: &Handshake,
+    data: &[u8],
+    now: f64,
+) -> Option<Message> {
+    pool.stats.receive(data.len(), now);
+    match pool.codec.decode(data) {
+        Ok(envelope) => pool.receive(sender, handshake, envelope, now),
+        Err(e) => {
+            console::error!("BroadcastChannel message error: {:?}", e);
+            pool.stats.decode_error();
+            Stamp::peek(data)
+                .filter(|stamp| stamp.sender != 0)
+                .map(|stamp| Message::Error {
+                    to: stamp.sender,
+                    sequence: stamp.sequence,
+                    error: e,
+                })
+        }
+    }
+}
+
+impl Drop for Broadcast {
+    /// Leaves the channel before its listener is dropped, and removes the pool unless another
+    /// connection shares it.
+    fn drop(&mut self) {
+        self.close();
+        release_pool(self.local.pool);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{
+        Codec, EntityTransform, Header, MessageError, Role, Serializable, Target, Transform,
+    };
+
+    const LOCAL: u32 = 100;
+    const TAB: u32 = 7;
+
+    fn frame(codec: &mut Codec, sequence: u32, message: Message) -> Vec<u8> {
+        let stamp = Stamp {
+            sender: TAB,
+            sequence,
+            timestamp: 0.0,
+        };
+        codec.encode(&Envelope::new(stamp, message)).unwrap()
+    }
+
+    #[test]
+    fn answers_new_tabs_and_queues_their_transforms() {
+        let local = Handshake::new("renderer", Role::Viewer, Vec::new());
+        let mut pool = Pool::new();
+        let mut tab = Codec::new(Encoding::Compact);
+        let receive = |pool: &mut Pool, data: &[u8]| receive_frame(pool, LOCAL, &local, data, 0.0);
+
+        let hello = Message::Handshake(Handshake::new("phone", Role::Controller, Vec::new()));
+        let reply = receive(&mut pool, &frame(&mut tab, 0, hello.clone()));
+        assert!(matches!(reply, Some(Message::Handshake(ref h)) if h.name == "renderer"));
+        // the tab is only answered the first time it introduces itself
+        assert!(receive(&mut pool, &frame(&mut tab, 0, hello)).is_none());
+        assert!(pool.peers.contains_key(&TAB));
+
+        let rotate = Message::Transform(EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Rotate(glm::vec3(0.5, 0.0, 0.0)),
+        });
+        assert!(receive(&mut pool, &frame(&mut tab, 1, rotate.clone())).is_none());
+        let reply = receive(&mut pool, &frame(&mut tab, 2, Message::Ping(3)));
+        assert!(matches!(reply, Some(Message::Pong { to: TAB, id: 3 })));
+
+        let received: Vec<_> = pool.messages.drain().collect();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].message.to_bytes(), rotate.to_bytes());
+        assert_eq!(pool.unacked.get(&TAB), Some(&1));
+    }
+
+    #[test]
+    fn reports_undecodable_frames_to_their_sender() {
+        let local = Handshake::new("renderer", Role::Viewer, Vec::new());
+        let mut pool = Pool::new();
+        let stamp = Stamp {
+            sender: TAB,
+            sequence: 4,
+            timestamp: 0.0,
+        };
+        let mut data = Header::new(1, stamp).to_bytes();
+        data.push(0xEE);
+
+        let reply = receive_frame(&mut pool, LOCAL, &local, &data, 0.0);
+        assert!(matches!(
+            reply,
+            Some(Message::Error {
+                to: TAB,
+                sequence: 4,
+                error: MessageError::InvalidMessageType,
+            })
+        ));
+        assert_eq!(pool.stats(0.0).decode_errors, 1);
+        assert_eq!(pool.messages.drain().count(), 0);
+    }
+
+    #[test]
+    fn reports_unsupported_headers_to_their_sender() {
+        let local = Handshake::new("renderer", Role::Viewer, Vec::new());
+        let mut pool = Pool::new();
+        let stamp = Stamp {
+            sender: TAB,
+            sequence: 9,
+            timestamp: 0.0,
+        };
+        let mut data = Header::new(1, stamp).to_bytes();
+        data.push(0xEE);
+
+        let mut future = data.clone();
+        future[2] = 0xFE;
+        let reply = receive_frame(&mut pool, LOCAL, &local, &future, 0.0);
+        assert!(matches!(
+            reply,
+            Some(Message::Error {
+                to: TAB,
+                sequence: 9,
+                error: MessageError::UnsupportedVersion(0xFE),
+            })
+        ));
+
+        let mut flagged = data;
+        flagged[3] = 0x80;
+        let reply = receive_frame(&mut pool, LOCAL, &local, &flagged, 0.0);
+        assert!(matches!(
+            reply,
+            Some(Message::Error {
+                to: TAB,
+                sequence: 9,
+                error: MessageError::UnsupportedFlags(0x80),
+            })
+        ));
+        assert_eq!(pool.stats(0.0).decode_errors, 2);
+    }
+}
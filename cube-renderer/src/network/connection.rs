@@ -0,0 +1,71 @@
+/// Where a connection is in its lifecycle.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Connecting,
+    Open,
+    Closing,
+    /// Closed cleanly, or waiting to reconnect after a clean close.
+    Closed,
+    /// Closed because something went wrong, or couldn't be opened at all.
+    Error(String),
+}
+
+#[allow(dead_code)]
+impl ConnectionState {
+    /// The state of a socket in `ready_state`, as defined by `web_sys::WebSocket`. `error` is
+    /// why it last closed, if it didn't close cleanly.
+    pub fn from_ready_state(ready_state: u16, error: Option<&str>) -> ConnectionState {
+        match ready_state {
+            web_sys::WebSocket::CONNECTING => ConnectionState::Connecting,
+            web_sys::WebSocket::OPEN => ConnectionState::Open,
+            web_sys::WebSocket::CLOSING => ConnectionState::Closing,
+            _ => match error {
+                Some(reason) => ConnectionState::Error(reason.to_string()),
+                None => ConnectionState::Closed,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Open => "open",
+            ConnectionState::Closing => "closing",
+            ConnectionState::Closed => "closed",
+            ConnectionState::Error(_) => "error",
+        }
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            ConnectionState::Error(reason) => Some(reason),
+            _ => None,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        *self == ConnectionState::Open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closed_sockets_keep_the_error() {
+        let closed = web_sys::WebSocket::CLOSED;
+        assert_eq!(
+            ConnectionState::from_ready_state(closed, None),
+            ConnectionState::Closed
+        );
+        let state = ConnectionState::from_ready_state(closed, Some("code 1006"));
+        assert_eq!(state.name(), "error");
+        assert_eq!(state.reason(), Some("code 1006"));
+
+        let open = ConnectionState::from_ready_state(web_sys::WebSocket::OPEN, Some("code 1006"));
+        assert!(open.is_open());
+        assert_eq!(open.reason(), None);
+    }
+}
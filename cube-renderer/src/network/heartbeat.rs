@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+
+/// How often a ping is sent, in milliseconds.
+pub const PING_INTERVAL: f64 = 1000.0;
+/// Number of pings in a row without a pong after which the connection is stale.
+pub const STALE_AFTER: u32 = 3;
+
+/// Weight of a new round-trip sample in the smoothed RTT, as in TCP's estimator.
+const RTT_GAIN: f64 = 1.0 / 8.0;
+/// Weight of a new deviation sample in the smoothed jitter.
+const JITTER_GAIN: f64 = 1.0 / 4.0;
+
+/// Liveness and round-trip latency of a connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Latency {
+    /// Smoothed round-trip time in milliseconds, once a pong has arrived.
+    pub rtt: Option<f64>,
+    /// Smoothed deviation of the round-trip time from `rtt`, in milliseconds.
+    pub jitter: f64,
+    /// Pings sent since the last pong.
+    pub missed: u32,
+    /// Whether `STALE_AFTER` pings in a row went unanswered.
+    pub is_stale: bool,
+}
+
+/// Pings sent on a connection and the latency measured from their pongs.
+#[derive(Debug, Default)]
+pub struct Heartbeat {
+    next_id: u32,
+    /// Pings still waiting for a pong, oldest first, with the time they were sent.
+    pending: VecDeque<(u32, f64)>,
+    latency: Latency,
+}
+
+impl Heartbeat {
+    pub fn new() -> Heartbeat {
+        Heartbeat::default()
+    }
+
+    pub fn latency(&self) -> Latency {
+        self.latency
+    }
+
+    /// Records a ping sent at `now` and returns its id.
+    pub fn ping(&mut self, now: f64) -> u32 {
+        if !self.pending.is_empty() {
+            self.latency.missed += 1;
+            self.latency.is_stale = self.latency.missed >= STALE_AFTER;
+        }
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.push_back((self.next_id, now));
+        if self.pending.len() > STALE_AFTER as usize + 1 {
+            self.pending.pop_front();
+        }
+        self.next_id
+    }
+
+    /// Records the pong for ping `id`, received at `now`. Pongs for unknown or already
+    /// answered pings, e.g. from a second peer, are ignored.
+    pub fn pong(&mut self, id: u32, now: f64) {
+        let Some(index) = self.pending.iter().position(|&(pending, _)| pending == id) else {
+            return;
+        };
+        let (_, sent) = self.pending[index];
+        // older pings can't be answered anymore, they were sent before this one
+        self.pending.drain(..=index);
+
+        let sample = (now - sent).max(0.0);
+        self.latency.rtt = Some(match self.latency.rtt {
+            None => {
+                self.latency.jitter = sample / 2.0;
+                sample
+            }
+            Some(rtt) => {
+                let deviation = (sample - rtt).abs();
+                self.latency.jitter += JITTER_GAIN * (deviation - self.latency.jitter);
+                rtt + RTT_GAIN * (sample - rtt)
+            }
+        });
+        self.latency.missed = 0;
+        self.latency.is_stale = false;
+    }
+
+    /// Forgets every pending ping and measurement, e.g. when the connection closed.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.latency = Latency::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_pong_sets_the_rtt() {
+        let mut heartbeat = Heartbeat::new();
+        let id = heartbeat.ping(1000.0);
+        heartbeat.pong(id, 1040.0);
+        let latency = heartbeat.latency();
+        assert_eq!(latency.rtt, Some(40.0));
+        assert_eq!(latency.jitter, 20.0);
+        assert_eq!(latency.missed, 0);
+    }
+
+    #[test]
+    fn rtt_and_jitter_are_smoothed() {
+        let mut heartbeat = Heartbeat::new();
+        let mut now = 0.0;
+        for sample in [40.0, 40.0, 40.0, 120.0] {
+            let id = heartbeat.ping(now);
+            heartbeat.pong(id, now + sample);
+            now += PING_INTERVAL;
+        }
+        let latency = heartbeat.latency();
+        let rtt = latency.rtt.unwrap();
+        assert!(rtt > 40.0 && rtt < 120.0, "rtt {}", rtt);
+        assert!(latency.jitter > 5.0 && latency.jitter < 80.0);
+    }
+
+    #[test]
+    fn duplicate_and_unknown_pongs_are_ignored() {
+        let mut heartbeat = Heartbeat::new();
+        let id = heartbeat.ping(0.0);
+        heartbeat.pong(id, 30.0);
+        heartbeat.pong(id, 500.0);
+        heartbeat.pong(id + 10, 500.0);
+        assert_eq!(heartbeat.latency().rtt, Some(30.0));
+    }
+
+    #[test]
+    fn goes_stale_after_missed_pongs_and_recovers() {
+        let mut heartbeat = Heartbeat::new();
+        let mut ids = Vec::new();
+        for i in 0..=STALE_AFTER {
+            ids.push(heartbeat.ping(i as f64 * PING_INTERVAL));
+        }
+        assert_eq!(heartbeat.latency().missed, STALE_AFTER);
+        assert!(heartbeat.latency().is_stale);
+
+        let last = *ids.last().unwrap();
+        heartbeat.pong(last, STALE_AFTER as f64 * PING_INTERVAL + 50.0);
+        assert!(!heartbeat.latency().is_stale);
+        assert_eq!(heartbeat.latency().rtt, Some(50.0));
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+use crate::utils::{Envelope, Message};
+
+/// Messages an inbox keeps by default before its overflow policy applies.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// What an inbox does with messages that arrive faster than they are polled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Once full, every new message pushes out the oldest one.
+    #[default]
+    DropOldest,
+    /// Once full, new messages are dropped.
+    DropNewest,
+    /// A transform replaces the pending one for the same target and kind, so only the latest
+    /// of each is kept. Batches and other messages aren't coalesced, and push out the oldest
+    /// message once full.
+    Coalesce,
+}
+
+impl OverflowPolicy {
+    pub fn from_name(name: &str) -> Option<OverflowPolicy> {
+        match name {
+            "drop_oldest" => Some(OverflowPolicy::DropOldest),
+            "drop_newest" => Some(OverflowPolicy::DropNewest),
+            "coalesce" => Some(OverflowPolicy::Coalesce),
+            _ => None,
+        }
+    }
+}
+
+/// Messages an inbox lost to its capacity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Overflow {
+    /// Pending messages dropped to make room for newer ones.
+    pub dropped_oldest: u32,
+    /// New messages dropped because the inbox was full.
+    pub dropped_newest: u32,
+    /// Pending transforms replaced by a newer one.
+    pub coalesced: u32,
+}
+
+/// Received messages waiting to be polled, bounded by `capacity`.
+#[derive(Debug)]
+pub struct Inbox {
+    messages: VecDeque<Envelope>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    overflow: Overflow,
+}
+
+impl Inbox {
+    pub fn new() -> Inbox {
+        Inbox {
+            messages: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+            policy: OverflowPolicy::default(),
+            overflow: Overflow::default(),
+        }
+    }
+
+    pub fn overflow(&self) -> Overflow {
+        self.overflow
+    }
+
+    /// Sets how many messages are kept, dropping the oldest ones if there are more already.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.messages.len() > self.capacity {
+            self.messages.pop_front();
+            self.overflow.dropped_oldest += 1;
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn push(&mut self, envelope: Envelope) {
+        if self.policy == OverflowPolicy::Coalesce {
+            if let Some(index) = self.pending(&envelope) {
+                self.messages.remove(index);
+                self.overflow.coalesced += 1;
+            }
+        }
+
+        if self.messages.len() >= self.capacity {
+            if self.policy == OverflowPolicy::DropNewest {
+                self.overflow.dropped_newest += 1;
+                return;
+            }
+            self.messages.pop_front();
+            self.overflow.dropped_oldest += 1;
+        }
+        self.messages.push_back(envelope);
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Envelope> + '_ {
+        self.messages.drain(..)
+    }
+
+    /// The index of the pending transform `envelope` would replace, if any.
+    fn pending(&self, envelope: &Envelope) -> Option<usize> {
+        let Message::Transform(transform) = &envelope.message else {
+            return None;
+        };
+        let key = (&transform.target, transform.transform.kind());
+        self.messages
+            .iter()
+            .position(|pending| match &pending.message {
+                Message::Transform(pending) => (&pending.target, pending.transform.kind()) == key,
+                _ => false,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{EntityTransform, Stamp, Target, Transform};
+
+    fn rotate(target: Target, sequence: u32, x: f32) -> Envelope {
+        let stamp = Stamp {
+            sender: 1,
+            sequence,
+            timestamp: 0.0,
+        };
+        let transform = EntityTransform {
+            target,
+            transform: Transform::Rotate(glm::vec3(x, 0.0, 0.0)),
+        };
+        Envelope::new(stamp, Message::Transform(transform))
+    }
+
+    fn sequences(inbox: &mut Inbox) -> Vec<u32> {
+        inbox
+            .drain()
+            .map(|envelope| envelope.stamp.sequence)
+            .collect()
+    }
+
+    #[test]
+    fn drops_oldest_or_newest_once_full() {
+        let mut inbox = Inbox::new();
+        inbox.set_capacity(2);
+        for sequence in 1..=4 {
+            inbox.push(rotate(Target::Owner, sequence, 0.0));
+        }
+        assert_eq!(sequences(&mut inbox), [3, 4]);
+        assert_eq!(inbox.overflow().dropped_oldest, 2);
+
+        inbox.set_policy(OverflowPolicy::DropNewest);
+        for sequence in 5..=8 {
+            inbox.push(rotate(Target::Owner, sequence, 0.0));
+        }
+        assert_eq!(sequences(&mut inbox), [5, 6]);
+        assert_eq!(inbox.overflow().dropped_newest, 2);
+    }
+
+    #[test]
+    fn coalesces_the_latest_transform_per_target_and_kind() {
+        let mut inbox = Inbox::new();
+        inbox.set_policy(OverflowPolicy::Coalesce);
+        inbox.push(rotate(Target::Owner, 1, 0.0));
+        inbox.push(rotate(Target::Id(7), 2, 0.0));
+        inbox.push(Envelope::new(Stamp::default(), Message::Sync));
+        inbox.push(rotate(Target::Owner, 3, 1.0));
+
+        assert_eq!(sequences(&mut inbox), [2, 0, 3]);
+        assert_eq!(inbox.overflow().coalesced, 1);
+        assert_eq!(inbox.overflow().dropped_oldest, 0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
+
+use super::{connection::ConnectionState, outbox::Delivery, transport::Transport};
+use crate::utils::{Envelope, Message, Serializable, Stamp};
+
+/// One end of an in-memory connection. Whatever one end sends, the other polls.
+///
+/// Messages go through the binary encoding on the way, as they would through a socket.
+/// Nothing reads a clock, so stamps carry no timestamp.
+pub struct Loopback {
+    sender: u32,
+    sequence: u32,
+    received: Rc<RefCell<VecDeque<Envelope>>>,
+    peer: Rc<RefCell<VecDeque<Envelope>>>,
+    closed: Rc<Cell<bool>>,
+}
+
+#[allow(dead_code)]
+impl Loopback {
+    /// Both ends of a new connection, sending as senders 1 and 2.
+    pub fn pair() -> (Loopback, Loopback) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        let closed = Rc::new(Cell::new(false));
+        let left = Loopback {
+            sender: 1,
+            sequence: 0,
+            received: a.clone(),
+            peer: b.clone(),
+            closed: closed.clone(),
+        };
+        let right = Loopback {
+            sender: 2,
+            sequence: 0,
+            received: b,
+            peer: a,
+            closed,
+        };
+        (left, right)
+    }
+
+    pub fn sender(&self) -> u32 {
+        self.sender
+    }
+
+    /// Closes both ends. Messages already sent can still be polled.
+    pub fn close(&self) {
+        self.closed.set(true);
+    }
+}
+
+impl Transport for Loopback {
+    fn poll(&mut self) -> Vec<Envelope> {
+        self.received.borrow_mut().drain(..).collect()
+    }
+
+    fn send(&mut self, message: &Message) -> Delivery {
+        if self.closed.get() {
+            return Delivery::Failed;
+        }
+        self.sequence += 1;
+        let stamp = Stamp {
+            sender: self.sender,
+            sequence: self.sequence,
+            timestamp: 0.0,
+        };
+        let Ok(bytes) = Envelope::new(stamp, message.clone()).encode() else {
+            return Delivery::Failed;
+        };
+        match Envelope::from_bytes(&bytes) {
+            Ok(envelope) => {
+                self.peer.borrow_mut().push_back(envelope);
+                Delivery::Sent
+            }
+            Err(_) => Delivery::Failed,
+        }
+    }
+
+    fn close(&mut self) {
+        Loopback::close(self);
+    }
+
+    fn state(&self) -> ConnectionState {
+        if self.closed.get() {
+            ConnectionState::Closed
+        } else {
+            ConnectionState::Open
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_end_polls_what_the_other_sent() {
+        let (mut left, mut right) = Loopback::pair();
+        assert_eq!(left.send(&Message::Sync), Delivery::Sent);
+        assert_eq!(left.send(&Message::Ping(7)), Delivery::Sent);
+        assert!(left.poll().is_empty());
+
+        let received = right.poll();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].stamp.sender, left.sender());
+        assert_eq!(received[1].stamp.sequence, 2);
+        assert!(matches!(received[1].message, Message::Ping(7)));
+
+        right.close();
+        assert_eq!(left.state(), ConnectionState::Closed);
+        assert_eq!(left.send(&Message::Sync), Delivery::Failed);
+    }
+}
@@ -1,3 +1,30 @@
+mod broadcast;
+mod connection;
+mod heartbeat;
+mod inbox;
+mod loopback;
+mod outbox;
+mod pool;
+mod reconnect;
+mod stats;
+mod transport;
 mod websocket;
 
-pub use websocket::WebSocket;
+pub use broadcast::Broadcast;
+pub use connection::ConnectionState;
+#[allow(unused_imports)]
+pub use heartbeat::{Latency, PING_INTERVAL, STALE_AFTER};
+#[allow(unused_imports)]
+pub use inbox::{Overflow, OverflowPolicy, DEFAULT_CAPACITY};
+#[allow(unused_imports)]
+pub use loopback::Loopback;
+#[allow(unused_imports)]
+pub use outbox::{Delivery, HIGH_WATER_MARK, MAX_QUEUED};
+#[allow(unused_imports)]
+pub use pool::Peer;
+#[allow(unused_imports)]
+pub use reconnect::Reconnect;
+#[allow(unused_imports)]
+pub use stats::{NetworkStats, RATE_WINDOW};
+pub use transport::Transport;
+pub use websocket::{Format, WebSocket};
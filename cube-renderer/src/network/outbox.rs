@@ -0,0 +1,127 @@
+use std::{collections::VecDeque, fmt::Debug};
+
+use crate::{console, utils::Envelope};
+
+/// Bytes waiting in the socket above which `send` reports congestion.
+pub const HIGH_WATER_MARK: u32 = 64 * 1024;
+/// Messages kept while the socket isn't open. Older ones are dropped first.
+pub const MAX_QUEUED: usize = 256;
+
+/// What happened to a message handed to `WebSocket::send`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// Handed to the socket.
+    Sent,
+    /// Handed to the socket, but more than `HIGH_WATER_MARK` bytes, given here, are still
+    /// waiting to go out. Senders should hold off until it drains.
+    Congested(u32),
+    /// Kept until the socket opens.
+    Queued,
+    /// The socket refused it.
+    Failed,
+}
+
+/// Messages sent while the socket wasn't open, oldest first, up to `MAX_QUEUED`.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    queued: VecDeque<Envelope>,
+}
+
+impl Outbox {
+    pub fn new() -> Outbox {
+        Outbox::default()
+    }
+
+    /// Sends `envelope` through `transmit` if the socket is `open`, or keeps it until it
+    /// opens. `transmit` hands the envelope to the socket and returns the bytes the socket
+    /// still has to send.
+    pub fn send<E: Debug>(
+        &mut self,
+        envelope: Envelope,
+        open: bool,
+        transmit: impl FnOnce(&Envelope) -> Result<u32, E>,
+    ) -> Delivery {
+        if !open {
+            if self.queued.len() >= MAX_QUEUED {
+                let dropped = self.queued.pop_front();
+                console::warn!("WebSocket outbox full, dropping {:?}", dropped);
+            }
+            self.queued.push_back(envelope);
+            return Delivery::Queued;
+        }
+
+        match transmit(&envelope) {
+            Ok(buffered) if buffered > HIGH_WATER_MARK => Delivery::Congested(buffered),
+            Ok(_) => Delivery::Sent,
+            Err(e) => {
+                console::error!("WebSocket send error: {:?}", e);
+                Delivery::Failed
+            }
+        }
+    }
+
+    /// Takes the queued messages, to send once the socket opened.
+    pub fn take(&mut self) -> VecDeque<Envelope> {
+        std::mem::take(&mut self.queued)
+    }
+
+    pub fn clear(&mut self) {
+        self.queued.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{Message, Stamp};
+
+    fn envelope(sequence: u32) -> Envelope {
+        let stamp = Stamp {
+            sender: 1,
+            sequence,
+            timestamp: 0.0,
+        };
+        Envelope::new(stamp, Message::Ping(sequence))
+    }
+
+    fn unreachable(_: &Envelope) -> Result<u32, ()> {
+        panic!("nothing is sent while the socket is connecting")
+    }
+
+    #[test]
+    fn queues_while_connecting_and_keeps_the_newest() {
+        let mut outbox = Outbox::new();
+        for sequence in 1..=MAX_QUEUED as u32 + 2 {
+            let delivery = outbox.send(envelope(sequence), false, unreachable);
+            assert_eq!(delivery, Delivery::Queued);
+        }
+
+        // the two oldest were dropped
+        let queued: Vec<_> = outbox.take().iter().map(|e| e.stamp.sequence).collect();
+        let expected: Vec<_> = (3..=MAX_QUEUED as u32 + 2).collect();
+        assert_eq!(queued, expected);
+        assert!(outbox.take().is_empty());
+    }
+
+    #[test]
+    fn reports_congestion_above_the_high_water_mark() {
+        let mut outbox = Outbox::new();
+        let mut sent = Vec::new();
+        let mut send = |sequence, result: Result<u32, ()>| {
+            outbox.send(envelope(sequence), true, |envelope: &Envelope| {
+                sent.push(envelope.stamp.sequence);
+                result
+            })
+        };
+
+        assert_eq!(send(1, Ok(0)), Delivery::Sent);
+        assert_eq!(send(2, Ok(HIGH_WATER_MARK)), Delivery::Sent);
+        assert_eq!(
+            send(3, Ok(HIGH_WATER_MARK + 1)),
+            Delivery::Congested(HIGH_WATER_MARK + 1)
+        );
+        assert_eq!(send(4, Err(())), Delivery::Failed);
+        assert_eq!(sent, [1, 2, 3, 4]);
+        assert!(outbox.take().is_empty());
+    }
+}
@@ -0,0 +1,405 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use super::{
+    heartbeat::Heartbeat,
+    inbox::Inbox,
+    stats::{NetworkStats, Stats},
+};
+use crate::{
+    console,
+    utils::{codecs, Codec, Encoding, Envelope, Handshake, Message, MessageError, Target},
+};
+
+lazy_static::lazy_static! {
+    pub(super) static ref POOLS: Mutex<HashMap<&'static str, Pool>> = Mutex::new(HashMap::new());
+}
+
+pub(super) struct Pool {
+    pub(super) messages: Inbox,
+    pub(super) codec: Codec,
+    /// Last sequence number accepted per sender and target, as addressed: see `accept`.
+    latest: HashMap<(u32, Target), u32>,
+    /// Handshakes received on this connection, by sender.
+    pub(super) peers: HashMap<u32, Peer>,
+    pub(super) heartbeat: Heartbeat,
+    /// Highest sequence number accepted per sender since the last acks were sent.
+    pub(super) unacked: HashMap<u32, u32>,
+    /// Messages dropped by `push`, reported in `NetworkStats::dropped`.
+    pub(super) dropped: u32,
+    pub(super) stats: Stats,
+    /// Connections sharing this pool, see `acquire_pool`.
+    users: u32,
+}
+
+/// A sender seen on a connection, as described by its handshake.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub handshake: Handshake,
+    /// Why this side can't read the peer's frames, if it can't.
+    pub incompatible: Option<MessageError>,
+}
+
+impl Pool {
+    pub(super) fn new() -> Self {
+        Self {
+            messages: Inbox::new(),
+            codec: Codec::new(Encoding::Full),
+            latest: HashMap::new(),
+            peers: HashMap::new(),
+            heartbeat: Heartbeat::new(),
+            unacked: HashMap::new(),
+            dropped: 0,
+            stats: Stats::new(),
+            users: 0,
+        }
+    }
+
+    /// Traffic received in this pool, as of `now`.
+    pub(super) fn stats(&mut self, now: f64) -> NetworkStats {
+        self.stats.read(now, self.dropped, self.messages.overflow())
+    }
+
+    /// Handles a frame decoded at `now`, returning the message to answer it with, if any.
+    /// Handshakes from senders that haven't been seen before are answered with `local`,
+    /// and pings with a pong.
+    pub(super) fn receive(
+        &mut self,
+        local_sender: u32,
+        local: &Handshake,
+        envelope: Envelope,
+        now: f64,
+    ) -> Option<Message> {
+        let sender = envelope.stamp.sender;
+        match envelope.message {
+            Message::Handshake(handshake) => self
+                .handshake(local, sender, handshake)
+                .then(|| Message::Handshake(local.clone())),
+            Message::Ping(id) => Some(Message::Pong { to: sender, id }),
+            Message::Pong { to, id } => {
+                if to == local_sender {
+                    self.heartbeat.pong(id, now);
+                }
+                None
+            }
+            Message::Ack { .. } => None,
+            Message::Error {
+                to,
+                sequence,
+                error,
+            } => {
+                if to == local_sender {
+                    console::warn!("Peer {} rejected frame {}: {:?}", sender, sequence, error);
+                    if error == MessageError::MissingBaseline {
+                        self.codec.resend_baselines();
+                    }
+                }
+                None
+            }
+            _ => {
+                self.push(envelope);
+                None
+            }
+        }
+    }
+
+    /// Records a peer's capabilities, and only uses the codecs every compatible peer reads.
+    /// New peers have no delta baseline, so the next transforms are sent whole.
+    fn handshake(&mut self, local: &Handshake, sender: u32, handshake: Handshake) -> bool {
+        let incompatible = local.check(&handshake).err();
+        match incompatible {
+            Some(e) => console::error!(
+                "Incompatible peer {:?} ({}): {:?}",
+                handshake.name,
+                sender,
+                e
+            ),
+            None => console::log!(
+                "Peer {:?} ({}) joined as {}",
+                handshake.name,
+                sender,
+                handshake.role.name()
+            ),
+        }
+        if incompatible.is_none() && !handshake.reads(local.max_version) {
+            console::warn!(
+                "Peer {:?} ({}) only reads up to version {}, it won't understand our frames",
+                handshake.name,
+                sender,
+                handshake.max_version
+            );
+        }
+        let peer = Peer {
+            handshake,
+            incompatible,
+        };
+        let is_new = self.peers.insert(sender, peer).is_none();
+        if is_new {
+            self.codec.resend_baselines();
+        }
+
+        let peer_codecs = self
+            .peers
+            .values()
+            .filter(|peer| peer.incompatible.is_none())
+            .map(|peer| peer.handshake.codecs)
+            .reduce(|a, b| a & b)
+            .unwrap_or(codecs::NONE);
+        self.codec.set_peer_codecs(peer_codecs);
+        is_new
+    }
+
+    /// Queues a decoded message unless it is stale, see `accept`. Frames that couldn't be
+    /// decoded never get here, so peers are not checked again.
+    fn push(&mut self, envelope: Envelope) {
+        let sender = envelope.stamp.sender;
+        if self.accept(&envelope) {
+            let sequence = envelope.stamp.sequence;
+            if sequence != 0 {
+                let unacked = self.unacked.entry(sender).or_default();
+                *unacked = sequence.max(*unacked);
+            }
+            self.messages.push(envelope);
+        } else {
+            self.dropped += 1;
+        }
+    }
+
+    /// Rejects transforms older than, or as old as, the last one accepted from the same sender
+    /// for any of their targets. Unsequenced frames are always accepted.
+    ///
+    /// Targets are compared as addressed, since the pool doesn't know the entities: a sender
+    /// addressing the same entity as `Owner` in one frame and by name or id in another has
+    /// them checked separately. Senders should stick to one way of addressing an entity.
+    fn accept(&mut self, envelope: &Envelope) -> bool {
+        let sender = envelope.stamp.sender;
+        let sequence = envelope.stamp.sequence;
+        if sequence == 0 {
+            return true;
+        }
+
+        let targets = envelope.targets();
+        let is_stale = targets.iter().any(|&target| {
+            self.latest
+                .get(&(sender, target.clone()))
+                .is_some_and(|&latest| sequence <= latest)
+        });
+        if is_stale {
+            return false;
+        }
+
+        for target in targets {
+            self.latest.insert((sender, target.clone()), sequence);
+        }
+        true
+    }
+}
+
+macro_rules! get_pool {
+    ($lock:expr, $pool:expr) => {{
+        let pool = if let Some(pool) = $lock.get_mut($pool) {
+            pool
+        } else {
+            $lock.insert($pool, Pool::new());
+            $lock.get_mut($pool).unwrap()
+        };
+        pool
+    }};
+}
+
+pub(super) use get_pool;
+
+/// Registers a connection using the pool named `name`, creating the pool if needed.
+pub(super) fn acquire_pool(name: &'static str) {
+    let mut pools = POOLS.lock().unwrap();
+    get_pool!(pools, name).users += 1;
+}
+
+/// Unregisters a connection from the pool named `name`. The pool is removed along with its
+/// last connection, so that the next one starts afresh.
+pub(super) fn release_pool(name: &'static str) {
+    let mut pools = POOLS.lock().unwrap();
+    if let Some(pool) = pools.get_mut(name) {
+        pool.users = pool.users.saturating_sub(1);
+        if pool.users == 0 {
+            pools.remove(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{
+        message_types, EntityTransform, Role, Serializable, Stamp, Transform, MAGIC,
+    };
+
+    const LOCAL: u32 = 100;
+
+    fn rotate(sender: u32, sequence: u32, targets: &[&Target]) -> Envelope {
+        let transforms: Vec<_> = targets
+            .iter()
+            .map(|target| EntityTransform {
+                target: (*target).clone(),
+                transform: Transform::Rotate(glm::vec3(0.0, 0.0, sequence as f32)),
+            })
+            .collect();
+        let stamp = Stamp {
+            sender,
+            sequence,
+            timestamp: 0.0,
+        };
+        Envelope::new(stamp, Message::Batch(transforms))
+    }
+
+    fn handshake(sender: u32, handshake: Handshake) -> Envelope {
+        let stamp = Stamp {
+            sender,
+            ..Stamp::default()
+        };
+        Envelope::new(stamp, Message::Handshake(handshake))
+    }
+
+    fn sequences(pool: &mut Pool) -> Vec<u32> {
+        pool.messages.drain().map(|e| e.stamp.sequence).collect()
+    }
+
+    #[test]
+    fn drops_stale_and_duplicate_transforms() {
+        let mut pool = Pool::new();
+        let cube = Target::Name("cube".to_string());
+        let light = Target::Name("light".to_string());
+
+        pool.push(rotate(1, 2, &[&cube]));
+        // stale, then duplicate
+        pool.push(rotate(1, 1, &[&cube]));
+        pool.push(rotate(1, 2, &[&cube]));
+        // other targets and senders are sequenced on their own
+        pool.push(rotate(1, 1, &[&light]));
+        pool.push(rotate(2, 1, &[&cube]));
+        pool.push(rotate(1, 3, &[&cube]));
+        assert_eq!(sequences(&mut pool), [2, 1, 1, 3]);
+        assert_eq!(pool.dropped, 2);
+
+        // a batch is dropped whole if it is stale for any of its targets
+        pool.push(rotate(1, 2, &[&light, &cube]));
+        assert_eq!(sequences(&mut pool), [] as [u32; 0]);
+        assert_eq!(pool.dropped, 3);
+        assert_eq!(pool.stats(0.0).dropped, 3);
+    }
+
+    #[test]
+    fn unsequenced_frames_are_always_accepted() {
+        let mut pool = Pool::new();
+        pool.push(rotate(1, 5, &[&Target::Owner]));
+        pool.push(rotate(1, 0, &[&Target::Owner]));
+        pool.push(rotate(1, 0, &[&Target::Owner]));
+        assert_eq!(sequences(&mut pool), [5, 0, 0]);
+        assert_eq!(pool.dropped, 0);
+        assert_eq!(pool.unacked.get(&1), Some(&5));
+    }
+
+    #[test]
+    fn late_peers_get_a_new_baseline() {
+        let local = Handshake::new("renderer", Role::Viewer, Vec::new());
+        let phone = Handshake::new("phone", Role::Controller, Vec::new());
+        let mut pool = Pool::new();
+        pool.codec.set_preferred(Encoding::Compact);
+        let receive = |pool: &mut Pool, envelope| pool.receive(LOCAL, &local, envelope, 0.0);
+        let reply = receive(&mut pool, handshake(1, phone.clone()));
+        assert!(matches!(reply, Some(Message::Handshake(_))));
+
+        let mut sequence = 0;
+        let mut send = |pool: &mut Pool, x: f32| {
+            sequence += 1;
+            let stamp = Stamp {
+                sender: LOCAL,
+                sequence,
+                timestamp: 0.0,
+            };
+            let rotate = EntityTransform {
+                target: Target::Owner,
+                transform: Transform::Rotate(glm::vec3(x, 0.0, 0.0)),
+            };
+            let envelope = Envelope::new(stamp, Message::Transform(rotate));
+            pool.codec.encode(&envelope).unwrap()
+        };
+        let mut phone_codec = Codec::new(Encoding::Compact);
+        phone_codec.decode(&send(&mut pool, 0.5)).unwrap();
+        phone_codec.decode(&send(&mut pool, 0.501)).unwrap();
+
+        // a second controller joins through the relay
+        receive(&mut pool, handshake(2, phone.clone()));
+        let mut late_codec = Codec::new(Encoding::Compact);
+        let frame = send(&mut pool, 0.502);
+        late_codec.decode(&frame).unwrap();
+        phone_codec.decode(&frame).unwrap();
+
+        // one that missed the handshake asks for a baseline
+        let delta = send(&mut pool, 0.503);
+        let mut missed_codec = Codec::new(Encoding::Compact);
+        let error = missed_codec.decode(&delta).unwrap_err();
+        assert_eq!(error, MessageError::MissingBaseline);
+        let stamp = Stamp {
+            sender: 3,
+            ..Stamp::default()
+        };
+        let error = Message::Error {
+            to: LOCAL,
+            sequence: Stamp::peek(&delta).unwrap().sequence,
+            error,
+        };
+        receive(&mut pool, Envelope::new(stamp, error));
+        let frame = send(&mut pool, 0.504);
+        missed_codec.decode(&frame).unwrap();
+        late_codec.decode(&delta).unwrap();
+        late_codec.decode(&frame).unwrap();
+    }
+
+    #[test]
+    fn legacy_peers_are_accepted() {
+        // a version 1 frame: the header stops after the length, and is unsequenced
+        let v1_frame = |payload: &[u8]| {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&[1, 0]);
+            bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(payload);
+            Envelope::from_bytes(&bytes).unwrap()
+        };
+        let local = Handshake::new("renderer", Role::Viewer, Vec::new());
+        let mut pool = Pool::new();
+
+        let mut payload = vec![message_types::HANDSHAKE];
+        payload.extend_from_slice(b"phone");
+        let reply = pool.receive(LOCAL, &local, v1_frame(&payload), 0.0);
+        assert!(matches!(reply, Some(Message::Handshake(_))));
+        let peer = pool.peers.values().next().unwrap();
+        assert_eq!(peer.handshake, Handshake::legacy("phone".to_string(), 1));
+        assert_eq!(peer.incompatible, None);
+
+        let rotate = Message::Transform(EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Rotate(glm::vec3(0.0, 1.0, 0.0)),
+        });
+        pool.receive(LOCAL, &local, v1_frame(&rotate.to_bytes()), 0.0);
+        let received: Vec<_> = pool.messages.drain().collect();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].message.to_bytes(), rotate.to_bytes());
+        assert_eq!(pool.dropped, 0);
+    }
+
+    #[test]
+    fn pools_outlive_all_but_their_last_connection() {
+        const NAME: &str = "shared";
+        acquire_pool(NAME);
+        acquire_pool(NAME);
+        POOLS.lock().unwrap().get_mut(NAME).unwrap().dropped = 1;
+
+        release_pool(NAME);
+        assert_eq!(
+            POOLS.lock().unwrap().get(NAME).map(|pool| pool.dropped),
+            Some(1)
+        );
+        release_pool(NAME);
+        assert!(POOLS.lock().unwrap().get(NAME).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+/// When to try again after a connection closed.
+///
+/// Every failed attempt multiplies the delay by `multiplier`, up to `max_delay`. A random part
+/// of it is taken off, so that many tabs dropped by the same relay restart don't all come back
+/// at the same moment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reconnect {
+    /// Delay before the first attempt, in milliseconds.
+    pub initial_delay: f64,
+    /// Upper bound of the delay, in milliseconds.
+    pub max_delay: f64,
+    /// Factor the delay grows by after every failed attempt.
+    pub multiplier: f64,
+    /// Fraction of the delay that is randomized, between 0 and 1.
+    pub jitter: f64,
+    /// Attempts in a row before giving up, or `None` to retry forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for Reconnect {
+    fn default() -> Self {
+        Reconnect {
+            initial_delay: 500.0,
+            max_delay: 30_000.0,
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_attempts: None,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Reconnect {
+    /// Never reconnects.
+    pub fn never() -> Reconnect {
+        Reconnect {
+            max_attempts: Some(0),
+            ..Reconnect::default()
+        }
+    }
+
+    /// The delay before attempt number `attempt`, counting from zero since the connection was
+    /// last open, or `None` once the attempts are exhausted. `random` is in `[0, 1)`.
+    pub fn delay(&self, attempt: u32, random: f64) -> Option<f64> {
+        if self.max_attempts.is_some_and(|max| attempt >= max) {
+            return None;
+        }
+        let exponent = attempt.min(64) as i32;
+        let delay = (self.initial_delay * self.multiplier.powi(exponent)).min(self.max_delay);
+        Some(delay * (1.0 - self.jitter * random))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_grow_exponentially_up_to_the_maximum() {
+        let reconnect = Reconnect::default();
+        let delays: Vec<f64> = (0..10).map(|i| reconnect.delay(i, 0.0).unwrap()).collect();
+        assert_eq!(&delays[..4], &[500.0, 1000.0, 2000.0, 4000.0]);
+        assert_eq!(delays[9], 30_000.0);
+        assert_eq!(reconnect.delay(u32::MAX, 0.0), Some(30_000.0));
+    }
+
+    #[test]
+    fn jitter_only_shortens_the_delay() {
+        let reconnect = Reconnect::default();
+        assert_eq!(reconnect.delay(2, 0.0), Some(2000.0));
+        assert_eq!(reconnect.delay(2, 0.5), Some(1500.0));
+        let shortest = reconnect.delay(2, 0.999).unwrap();
+        assert!(shortest > 1000.0 && shortest < 2000.0);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let reconnect = Reconnect {
+            max_attempts: Some(3),
+            ..Reconnect::default()
+        };
+        assert!(reconnect.delay(2, 0.0).is_some());
+        assert_eq!(reconnect.delay(3, 0.0), None);
+        assert_eq!(Reconnect::never().delay(0, 0.0), None);
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+use super::inbox::Overflow;
+
+/// Span of the rolling rates, in milliseconds.
+pub const RATE_WINDOW: f64 = 1000.0;
+
+/// Traffic received on a connection, as read at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct NetworkStats {
+    /// Frames received since the connection was created, handshakes and heartbeats included.
+    pub messages: u64,
+    /// Bytes of those frames.
+    pub bytes: u64,
+    /// Frames received per second over the last `RATE_WINDOW`.
+    pub messages_per_second: f64,
+    /// Bytes received per second over the last `RATE_WINDOW`.
+    pub bytes_per_second: f64,
+    /// Frames that couldn't be decoded.
+    pub decode_errors: u32,
+    /// Messages the pool dropped as stale or duplicate.
+    pub dropped: u32,
+    /// Messages lost to the pool's capacity, by overflow policy.
+    pub overflow: Overflow,
+    /// Milliseconds since the last frame arrived, once one has.
+    pub last_message_age: Option<f64>,
+}
+
+/// Counts the frames received on a connection.
+#[derive(Debug, Default)]
+pub struct Stats {
+    messages: u64,
+    bytes: u64,
+    decode_errors: u32,
+    /// Arrival time and size of the frames received within the last `RATE_WINDOW`.
+    recent: VecDeque<(f64, usize)>,
+    last_message_at: Option<f64>,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    /// Records a frame of `bytes` received at `now`, whether it decodes or not.
+    pub fn receive(&mut self, bytes: usize, now: f64) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+        self.last_message_at = Some(now);
+        self.recent.push_back((now, bytes));
+        self.expire(now);
+    }
+
+    pub fn decode_error(&mut self) {
+        self.decode_errors += 1;
+    }
+
+    fn expire(&mut self, now: f64) {
+        while let Some(&(at, _)) = self.recent.front() {
+            if now - at < RATE_WINDOW {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+
+    /// The stats at `now`, counting the messages the pool `dropped` and lost to `overflow`.
+    pub fn read(&mut self, now: f64, dropped: u32, overflow: Overflow) -> NetworkStats {
+        self.expire(now);
+        let bytes: usize = self.recent.iter().map(|&(_, bytes)| bytes).sum();
+        let seconds = RATE_WINDOW / 1000.0;
+        NetworkStats {
+            messages: self.messages,
+            bytes: self.bytes,
+            messages_per_second: self.recent.len() as f64 / seconds,
+            bytes_per_second: bytes as f64 / seconds,
+            decode_errors: self.decode_errors,
+            dropped,
+            overflow,
+            last_message_age: self.last_message_at.map(|at| (now - at).max(0.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_cover_the_last_window() {
+        let mut stats = Stats::new();
+        assert_eq!(
+            stats.read(0.0, 0, Overflow::default()).last_message_age,
+            None
+        );
+
+        for i in 0..10 {
+            stats.receive(100, i as f64 * 200.0);
+        }
+        stats.decode_error();
+        let overflow = Overflow {
+            dropped_oldest: 1,
+            dropped_newest: 2,
+            coalesced: 5,
+        };
+        let read = stats.read(1900.0, 3, overflow);
+        assert_eq!(read.messages, 10);
+        assert_eq!(read.bytes, 1000);
+        // frames at 1000..=1800ms
+        assert_eq!(read.messages_per_second, 5.0);
+        assert_eq!(read.bytes_per_second, 500.0);
+        assert_eq!(read.decode_errors, 1);
+        assert_eq!(read.dropped, 3);
+        assert_eq!(read.overflow, overflow);
+        assert_eq!(read.last_message_age, Some(100.0));
+
+        let idle = stats.read(5000.0, 3, overflow);
+        assert_eq!(idle.messages_per_second, 0.0);
+        assert_eq!(idle.messages, 10);
+    }
+}
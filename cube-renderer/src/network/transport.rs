@@ -0,0 +1,28 @@
+use super::{
+    connection::ConnectionState, heartbeat::Latency, outbox::Delivery, stats::NetworkStats,
+};
+use crate::utils::{Envelope, Message};
+
+/// A connection behaviours exchange messages through.
+pub trait Transport {
+    /// Takes the messages received since the last poll, oldest first.
+    fn poll(&mut self) -> Vec<Envelope>;
+
+    /// Sends `message` to every peer on the connection.
+    fn send(&mut self, message: &Message) -> Delivery;
+
+    fn state(&self) -> ConnectionState;
+
+    /// Closes the connection for good. Messages already received can still be polled.
+    fn close(&mut self);
+
+    /// Round-trip latency to the peers, for transports that measure it.
+    fn latency(&self) -> Latency {
+        Latency::default()
+    }
+
+    /// Traffic received so far, for transports that count it.
+    fn stats(&self) -> NetworkStats {
+        NetworkStats::default()
+    }
+}
@@ -1,97 +1,526 @@
-use std::{collections::HashMap, sync::Mutex};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    rc::{Rc, Weak},
+};
 
-use wasm_bindgen::{prelude::Closure, JsCast};
-use web_sys::MessageEvent;
+use wasm_bindgen::{prelude::Closure, JsCast, JsValue};
+use web_sys::{BinaryType, CloseEvent, MessageEvent};
 
+use super::{
+    connection::ConnectionState,
+    heartbeat::{Latency, PING_INTERVAL},
+    inbox::OverflowPolicy,
+    outbox::{Delivery, Outbox},
+    pool::{acquire_pool, get_pool, release_pool, Peer, Pool, POOLS},
+    reconnect::Reconnect,
+    stats::NetworkStats,
+    transport::Transport,
+};
 use crate::{
     console,
-    utils::{Message, Serializable},
+    utils::{window, Encoding, Envelope, Handshake, Limits, Message, MessageError, Stamp},
 };
 
-lazy_static::lazy_static! {
-    static ref POOLS: Mutex<HashMap<&'static str, Vec<Message>>> = Mutex::new(HashMap::new());
+/// How outgoing messages are sent. Incoming ones are decoded according to the frame type.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Binary frames, see `utils::message`.
+    #[default]
+    Binary,
+    /// Text frames holding the JSON encoding.
+    Json,
 }
 
-pub struct WebSocket {
-    inner: web_sys::WebSocket,
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "binary" => Some(Format::Binary),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// The listeners of a connection, created once and attached to every socket it opens. They
+/// only hold a weak reference to `Local`, which owns them.
+struct Handlers {
+    on_open: Closure<dyn FnMut()>,
+    on_message: Closure<dyn FnMut(MessageEvent)>,
+    on_close: Closure<dyn FnMut(CloseEvent)>,
+    on_reconnect: Closure<dyn FnMut()>,
+    on_heartbeat: Closure<dyn FnMut()>,
+}
+
+impl Handlers {
+    fn new(local: &Weak<Local>) -> Handlers {
+        let on_open = {
+            let local = local.clone();
+            Closure::wrap(Box::new(move || {
+                if let Some(local) = local.upgrade() {
+                    WebSocket::on_open(&local);
+                }
+            }) as Box<dyn FnMut()>)
+        };
+        let on_message = {
+            let local = local.clone();
+            Closure::wrap(Box::new(move |e: MessageEvent| {
+                if let Some(local) = local.upgrade() {
+                    WebSocket::read_message(&local, e);
+                }
+            }) as Box<dyn FnMut(MessageEvent)>)
+        };
+        let on_close = {
+            let local = local.clone();
+            Closure::wrap(Box::new(move |e: CloseEvent| {
+                if let Some(local) = local.upgrade() {
+                    WebSocket::on_close(&local, e);
+                }
+            }) as Box<dyn FnMut(CloseEvent)>)
+        };
+        let on_reconnect = {
+            let local = local.clone();
+            Closure::wrap(Box::new(move || {
+                if let Some(local) = local.upgrade() {
+                    WebSocket::reconnect(&local);
+                }
+            }) as Box<dyn FnMut()>)
+        };
+        let on_heartbeat = {
+            let local = local.clone();
+            Closure::wrap(Box::new(move || {
+                if let Some(local) = local.upgrade() {
+                    WebSocket::heartbeat(&local);
+                }
+            }) as Box<dyn FnMut()>)
+        };
+        Handlers {
+            on_open,
+            on_message,
+            on_close,
+            on_reconnect,
+            on_heartbeat,
+        }
+    }
+}
+
+/// This side of a connection, shared with the socket's listeners.
+struct Local {
+    url: String,
     pool: &'static str,
+    /// The current socket, replaced on every reconnection.
+    socket: RefCell<web_sys::WebSocket>,
+    sender: u32,
+    handshake: Handshake,
+    reconnect: Cell<Reconnect>,
+    /// Reconnection attempts since the socket was last open.
+    attempts: Cell<u32>,
+    format: Cell<Format>,
+    /// Why the socket last closed, if it didn't close cleanly. Cleared once it opens again.
+    error: RefCell<Option<String>>,
+    /// Messages sent while the socket wasn't open.
+    outbox: RefCell<Outbox>,
+    handlers: Handlers,
+    /// The pending reconnection and the heartbeat, as ids of their timers.
+    reconnect_timer: Cell<Option<i32>>,
+    heartbeat_timer: Cell<Option<i32>>,
+    /// Whether `close` was called. A closed connection is never reopened.
+    closed: Cell<bool>,
 }
 
-macro_rules! get_pool {
-    ($lock:expr, $pool:expr) => {{
-        let pool = if let Some(pool) = $lock.get_mut($pool) {
-            pool
-        } else {
-            $lock.insert($pool, Vec::new());
-            $lock.get_mut($pool).unwrap()
+impl Local {
+    /// Sends `message` unsequenced and in the full binary encoding, which every peer reads
+    /// before negotiating anything. Used for the handshake and heartbeat.
+    fn send_unsequenced(&self, message: Message) {
+        let stamp = Stamp {
+            sender: self.sender,
+            sequence: 0,
+            timestamp: js_sys::Date::now(),
+        };
+        let bytes = match Envelope::new(stamp, message).encode() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                console::error!("WebSocket encode error: {:?}", e);
+                return;
+            }
         };
-        pool
-    }};
+        if let Err(e) = self.socket.borrow().send_with_u8_array(&bytes) {
+            console::error!("WebSocket send error: {:?}", e);
+        }
+    }
+
+    /// Hands `envelope` to the socket in the current format.
+    fn transmit(&self, envelope: &Envelope) -> Result<(), JsValue> {
+        let socket = self.socket.borrow();
+        match self.format.get() {
+            Format::Binary => {
+                let bytes = {
+                    let mut pools = POOLS.lock().unwrap();
+                    get_pool!(pools, self.pool).codec.encode(envelope)
+                };
+                let bytes = bytes.map_err(|e| JsValue::from_str(&format!("{:?}", e)))?;
+                socket.send_with_u8_array(&bytes)
+            }
+            Format::Json => socket.send_with_str(&envelope.to_json()),
+        }
+    }
+
+    /// Sends the messages queued while the socket wasn't open.
+    fn flush(&self) {
+        let queued = self.outbox.borrow_mut().take();
+        for envelope in queued {
+            if let Err(e) = self.transmit(&envelope) {
+                console::error!("WebSocket send error: {:?}", e);
+            }
+        }
+    }
+}
+
+pub struct WebSocket {
+    local: Rc<Local>,
+    sequence: u32,
 }
 
 impl WebSocket {
-    pub fn new(url: String, pool: &'static str) -> Self {
-        let inner = web_sys::WebSocket::new(url.as_str()).unwrap();
-
-        let on_open = Closure::wrap(Box::new(move || {
-            console::log!("WebSocket opened");
-        }) as Box<dyn FnMut()>);
-        inner.set_onopen(Some(on_open.as_ref().unchecked_ref()));
-        on_open.forget();
-
-        let mut this = Self { inner, pool };
-
-        this.setup_listeners();
-        this
-    }
-
-    fn read_message(pool: &'static str, e: MessageEvent) {
-        let on_load_cb = move |buffer: wasm_bindgen::JsValue| {
-            let data = js_sys::Uint8Array::new(&buffer);
-            let data = data.to_vec();
-            let bytes = data.as_slice();
-            let message = Message::from_bytes(bytes);
-            console::log!("WebSocket message: {:?}", message);
-            match message {
-                Ok(message) => {
-                    let mut pools = POOLS.lock().unwrap();
-                    let pool = get_pool!(pools, pool);
-                    pool.push(message)
+    /// Connects to `url`, introducing this side to every peer with `handshake`. The
+    /// connection is reopened with the default `Reconnect` policy whenever it closes, until
+    /// `close` is called or it is dropped.
+    pub fn new(url: String, pool: &'static str, handshake: Handshake) -> Self {
+        let socket = web_sys::WebSocket::new(url.as_str()).unwrap();
+        let sender = (js_sys::Math::random() * u32::MAX as f64) as u32;
+        let local = Rc::new_cyclic(|weak| Local {
+            url,
+            pool,
+            socket: RefCell::new(socket),
+            sender,
+            handshake,
+            reconnect: Cell::new(Reconnect::default()),
+            attempts: Cell::new(0),
+            format: Cell::new(Format::Binary),
+            error: RefCell::new(None),
+            outbox: RefCell::new(Outbox::new()),
+            handlers: Handlers::new(weak),
+            reconnect_timer: Cell::new(None),
+            heartbeat_timer: Cell::new(None),
+            closed: Cell::new(false),
+        });
+        acquire_pool(pool);
+        Self::attach(&local);
+
+        let heartbeat = window().set_interval_with_callback_and_timeout_and_arguments_0(
+            local.handlers.on_heartbeat.as_ref().unchecked_ref(),
+            PING_INTERVAL as i32,
+        );
+        local.heartbeat_timer.set(heartbeat.ok());
+
+        Self { local, sequence: 0 }
+    }
+
+    /// Handles a decoded frame of `bytes`, or reports why it couldn't be decoded to its sender
+    /// when the frame's `stamp` could be read and names one.
+    fn on_decoded(
+        local: &Local,
+        bytes: usize,
+        stamp: Option<Stamp>,
+        envelope: Result<Envelope, MessageError>,
+    ) {
+        console::log!("WebSocket message: {:?}", envelope);
+        let now = js_sys::Date::now();
+        let reply = {
+            let mut pools = POOLS.lock().unwrap();
+            let pool = get_pool!(pools, local.pool);
+            pool.stats.receive(bytes, now);
+            match envelope {
+                Ok(envelope) => pool.receive(local.sender, &local.handshake, envelope, now),
+                Err(e) => {
+                    console::error!("WebSocket message error: {:?}", e);
+                    pool.stats.decode_error();
+                    stamp
+                        .filter(|stamp| stamp.sender != 0)
+                        .map(|stamp| Message::Error {
+                            to: stamp.sender,
+                            sequence: stamp.sequence,
+                            error: e,
+                        })
                 }
-                Err(e) => console::error!("WebSocket message error: {:?}", e),
             }
         };
+        if let Some(reply) = reply {
+            local.send_unsequenced(reply);
+        }
+    }
 
-        if let Ok(blob) = e.data().dyn_into::<web_sys::Blob>() {
-            let on_load = Closure::wrap(Box::new(on_load_cb) as Box<dyn FnMut(_)>);
-            let _ = blob.array_buffer().then(&on_load);
-            on_load.forget();
+    /// Binary frames arrive as `ArrayBuffer`s, see `attach`.
+    fn read_message(local: &Local, e: MessageEvent) {
+        if let Some(text) = e.data().as_string() {
+            let stamp = Stamp::peek_json(&text);
+            let envelope = {
+                let mut pools = POOLS.lock().unwrap();
+                get_pool!(pools, local.pool).codec.decode_json(&text)
+            };
+            Self::on_decoded(local, text.len(), stamp, envelope);
         } else if let Ok(buffer) = e.data().dyn_into::<js_sys::ArrayBuffer>() {
-            on_load_cb(buffer.into());
+            let data = js_sys::Uint8Array::new(&buffer).to_vec();
+            let envelope = {
+                let mut pools = POOLS.lock().unwrap();
+                get_pool!(pools, local.pool).codec.decode(data.as_slice())
+            };
+            Self::on_decoded(local, data.len(), Stamp::peek(&data), envelope);
+        } else {
+            console::warn!("WebSocket ignored {:?}", e.data());
         }
     }
 
-    fn setup_listeners(&mut self) {
-        let pool = self.pool;
-        let on_message = Closure::wrap(Box::new(move |e: MessageEvent| {
-            Self::read_message(pool, e);
-        }) as Box<dyn FnMut(MessageEvent)>);
-        self.inner
-            .set_onmessage(Some(on_message.as_ref().unchecked_ref()));
-        on_message.forget();
+    /// Attaches the listeners to the current socket of `local`.
+    fn attach(local: &Local) {
+        let socket = local.socket.borrow();
+        socket.set_binary_type(BinaryType::Arraybuffer);
+        socket.set_onopen(Some(local.handlers.on_open.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(local.handlers.on_message.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(local.handlers.on_close.as_ref().unchecked_ref()));
+    }
+
+    /// Resets the reconnection attempts and sends the handshake, then whatever was sent
+    /// while the socket wasn't open.
+    fn on_open(local: &Local) {
+        console::log!("WebSocket opened");
+        local.attempts.set(0);
+        local.error.replace(None);
+        local.send_unsequenced(Message::Handshake(local.handshake.clone()));
+        local.flush();
+    }
 
-        let on_close = Closure::wrap(Box::new(move || {
+    /// Forgets the peers and schedules the next connection attempt.
+    fn on_close(local: &Local, e: CloseEvent) {
+        if e.was_clean() {
             console::log!("WebSocket closed");
-        }) as Box<dyn FnMut()>);
-        self.inner
-            .set_onclose(Some(on_close.as_ref().unchecked_ref()));
-        on_close.forget();
+        } else {
+            let reason = match e.reason() {
+                reason if reason.is_empty() => format!("closed with code {}", e.code()),
+                reason => format!("closed with code {}: {}", e.code(), reason),
+            };
+            console::warn!("WebSocket {}", reason);
+            local.error.replace(Some(reason));
+        }
+        Self::reset_pool(local);
+        Self::schedule_reconnect(local);
+    }
+
+    /// Forgets what was negotiated with the peers of the last socket. Messages already
+    /// received stay in the pool.
+    fn reset_pool(local: &Local) {
+        let mut pools = POOLS.lock().unwrap();
+        let pool = get_pool!(pools, local.pool);
+        pool.codec.reset();
+        pool.peers.clear();
+        pool.heartbeat.reset();
+    }
+
+    /// Opens a new socket after the delay picked by the reconnection policy, unless it gave
+    /// up or the connection was closed.
+    fn schedule_reconnect(local: &Local) {
+        if local.closed.get() {
+            return;
+        }
+        let attempt = local.attempts.get();
+        let random = js_sys::Math::random();
+        let Some(delay) = local.reconnect.get().delay(attempt, random) else {
+            console::error!("WebSocket gave up reconnecting after {} attempts", attempt);
+            return;
+        };
+        local.attempts.set(attempt + 1);
+        console::log!("WebSocket reconnecting in {:.0}ms", delay);
+
+        let timer = window().set_timeout_with_callback_and_timeout_and_arguments_0(
+            local.handlers.on_reconnect.as_ref().unchecked_ref(),
+            delay as i32,
+        );
+        local.reconnect_timer.set(timer.ok());
+    }
+
+    fn reconnect(local: &Local) {
+        local.reconnect_timer.set(None);
+        if local.closed.get() {
+            return;
+        }
+        match web_sys::WebSocket::new(&local.url) {
+            Ok(socket) => {
+                local.socket.replace(socket);
+                Self::attach(local);
+            }
+            Err(e) => {
+                console::error!("WebSocket error: {:?}", e);
+                local.error.replace(Some(format!("{:?}", e)));
+                Self::schedule_reconnect(local);
+            }
+        }
+    }
+
+    /// Pings the peers, every `PING_INTERVAL` while the socket is open.
+    fn heartbeat(local: &Local) {
+        if local.socket.borrow().ready_state() != web_sys::WebSocket::OPEN {
+            return;
+        }
+        let id = {
+            let mut pools = POOLS.lock().unwrap();
+            get_pool!(pools, local.pool)
+                .heartbeat
+                .ping(js_sys::Date::now())
+        };
+        local.send_unsequenced(Message::Ping(id));
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.local.socket.borrow().ready_state() == web_sys::WebSocket::OPEN
+    }
+
+    fn next_stamp(&mut self) -> Stamp {
+        self.sequence += 1;
+        Stamp {
+            sender: self.local.sender,
+            sequence: self.sequence,
+            timestamp: js_sys::Date::now(),
+        }
+    }
+
+    /// Bytes handed to the socket that it hasn't sent yet, as given by `bufferedAmount`.
+    pub fn buffered_amount(&self) -> u32 {
+        self.local.socket.borrow().buffered_amount()
+    }
+
+    /// Sets when to reconnect after the connection closed.
+    pub fn set_reconnect(&mut self, reconnect: Reconnect) {
+        self.local.reconnect.set(reconnect);
+    }
+
+    /// Sets how messages are sent from now on. Handshakes and heartbeats stay binary.
+    pub fn set_format(&mut self, format: Format) {
+        self.local.format.set(format);
+    }
+
+    /// Sets the encoding this connection offers for outgoing binary transforms.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool)
+            .codec
+            .set_preferred(encoding);
+    }
+
+    /// The peers that introduced themselves on this connection, by sender.
+    #[allow(dead_code)]
+    pub fn peers(&self) -> HashMap<u32, Peer> {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool).peers.clone()
+    }
+
+    /// Sets how many received messages are kept until they are polled.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool)
+            .messages
+            .set_capacity(capacity);
+    }
+
+    /// Sets what incoming frames may contain. Frames outside of `limits` are rejected as
+    /// undecodable, and reported to their sender.
+    pub fn set_limits(&mut self, limits: Limits) {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool).codec.set_limits(limits);
+    }
+
+    /// Sets what happens to received messages once the pool is full.
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool)
+            .messages
+            .set_policy(policy);
+    }
+}
+
+impl Transport for WebSocket {
+    /// Takes the messages received since the last call, and acks them to their senders.
+    fn poll(&mut self) -> Vec<Envelope> {
+        let (messages, unacked) = {
+            let mut pools = POOLS.lock().unwrap();
+            let pool = get_pool!(pools, self.local.pool);
+            let messages = pool.messages.drain().collect();
+            (messages, pool.unacked.drain().collect::<Vec<_>>())
+        };
+        if self.is_open() {
+            for (to, sequence) in unacked {
+                self.local.send_unsequenced(Message::Ack { to, sequence });
+            }
+        }
+        messages
+    }
+
+    /// Sends `message`, or queues it until the socket opens if it is still connecting or
+    /// reconnecting. Up to `MAX_QUEUED` messages are kept, dropping the oldest first.
+    fn send(&mut self, message: &Message) -> Delivery {
+        if self.local.closed.get() {
+            return Delivery::Failed;
+        }
+        let envelope = Envelope::new(self.next_stamp(), message.clone());
+        let open = self.is_open();
+        self.local
+            .outbox
+            .borrow_mut()
+            .send(envelope, open, |envelope| {
+                self.local.transmit(envelope)?;
+                Ok::<_, JsValue>(self.buffered_amount())
+            })
+    }
+
+    /// Where the current socket is in its lifecycle. While waiting to reconnect, this is why
+    /// the last one closed.
+    fn state(&self) -> ConnectionState {
+        let ready_state = self.local.socket.borrow().ready_state();
+        ConnectionState::from_ready_state(ready_state, self.local.error.borrow().as_deref())
     }
 
-    pub fn poll(&mut self) -> impl Iterator<Item = Message> {
+    /// Closes the socket for good: the listeners are detached, timers cancelled and nothing
+    /// is reconnected. Messages already received can still be polled, but nothing is sent.
+    fn close(&mut self) {
+        let local = &self.local;
+        if local.closed.replace(true) {
+            return;
+        }
+        if let Some(timer) = local.reconnect_timer.take() {
+            window().clear_timeout_with_handle(timer);
+        }
+        if let Some(timer) = local.heartbeat_timer.take() {
+            window().clear_interval_with_handle(timer);
+        }
+        {
+            let socket = local.socket.borrow();
+            socket.set_onopen(None);
+            socket.set_onmessage(None);
+            socket.set_onclose(None);
+            if let Err(e) = socket.close_with_code(1000) {
+                console::error!("WebSocket close error: {:?}", e);
+            }
+        }
+        local.outbox.borrow_mut().clear();
+        Self::reset_pool(local);
+        console::log!("WebSocket closed by this side");
+    }
+
+    /// Round-trip latency to the peers, and whether they stopped answering pings.
+    fn latency(&self) -> Latency {
         let mut pools = POOLS.lock().unwrap();
-        let pool = get_pool!(pools, self.pool);
+        get_pool!(pools, self.local.pool).heartbeat.latency()
+    }
+
+    fn stats(&self) -> NetworkStats {
+        let mut pools = POOLS.lock().unwrap();
+        get_pool!(pools, self.local.pool).stats(js_sys::Date::now())
+    }
+}
 
-        pool.drain(..).collect::<Vec<_>>().into_iter()
+impl Drop for WebSocket {
+    /// Closes the connection, and removes its pool unless another connection shares it.
+    fn drop(&mut self) {
+        self.close();
+        release_pool(self.local.pool);
     }
 }
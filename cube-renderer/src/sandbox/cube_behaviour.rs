@@ -1,42 +1,168 @@
 use std::sync::MutexGuard;
 
 use crate::{
-    app::AppState,
+    app::{AppState, TransportKind},
+    console,
     model::{Behaviour, EntityState},
-    network::WebSocket,
-    utils::{Message, Transform},
+    network::{Broadcast, Delivery, OverflowPolicy, Transport, WebSocket},
+    utils::{Encoding, Handshake, Message, Role},
     HANDLE,
 };
 
+/// Sets up `conn`, a `WebSocket` or a `Broadcast`, the way the cube needs either of them.
+/// Their setters aren't part of `Transport`.
+macro_rules! configure {
+    ($conn:expr, $limits:expr, $capacity:expr, $policy:expr) => {{
+        $conn.set_encoding(Encoding::Compact);
+        $conn.set_limits($limits);
+        if let Some(capacity) = $capacity {
+            $conn.set_capacity(capacity);
+        }
+        // only the latest rotation matters after the tab was in the background
+        $conn.set_overflow_policy($policy.unwrap_or(OverflowPolicy::Coalesce));
+    }};
+}
+
 pub struct CubeBehaviour {
-    conn: WebSocket,
+    conn: Box<dyn Transport>,
 }
 
 impl CubeBehaviour {
     pub fn new() -> Self {
-        let host;
+        let (url, transport, format, reconnect, limits, capacity, policy);
         {
             let state = HANDLE.lock().unwrap();
-            host = state.config.host.clone();
+            url = state.config.endpoint.url();
+            transport = state.config.transport;
+            format = state.config.format;
+            reconnect = state.config.reconnect;
+            limits = state.config.limits;
+            capacity = state.config.capacity;
+            policy = state.config.overflow_policy;
         }
 
-        let url = format!("ws://{}:8080/ws", host);
-        Self {
-            conn: WebSocket::new(url, "cube"),
+        let handshake = Handshake::new("renderer", Role::Viewer, Vec::new());
+        if transport == TransportKind::Broadcast {
+            match Broadcast::new("cube", handshake.clone()) {
+                Ok(mut conn) => {
+                    configure!(conn, limits, capacity, policy);
+                    return Self::with_transport(Box::new(conn));
+                }
+                Err(e) => console::error!("BroadcastChannel unavailable, using the relay: {:?}", e),
+            }
         }
+
+        let mut conn = WebSocket::new(url, "cube", handshake);
+        conn.set_format(format);
+        conn.set_reconnect(reconnect);
+        configure!(conn, limits, capacity, policy);
+        Self::with_transport(Box::new(conn))
+    }
+
+    /// A behaviour exchanging messages through `conn` instead of the relay.
+    pub fn with_transport(mut conn: Box<dyn Transport>) -> Self {
+        // queued until the connection opens
+        conn.send(&Message::Sync);
+        Self { conn }
     }
 }
 
 impl Behaviour for CubeBehaviour {
-    fn update(&mut self, _dt: f32, entity: &mut EntityState, _state: &mut MutexGuard<AppState>) {
-        for message in self.conn.poll() {
-            match message {
-                Message::Transform(transform) => match transform {
-                    Transform::Rotate(rotation) => entity.set_rotation(rotation),
-                    _ => {}
-                },
+    fn update(&mut self, _dt: f32, entity: &mut EntityState, state: &mut MutexGuard<AppState>) {
+        let latency = self.conn.latency();
+        let was_stale = state.latency.get("cube").is_some_and(|l| l.is_stale);
+        if latency.is_stale && !was_stale {
+            console::warn!("Connection stale, {} pings unanswered", latency.missed);
+        }
+        state.latency.insert("cube", latency);
+        state.stats.insert("cube", self.conn.stats());
+
+        let connection = self.conn.state();
+        if state.connections.get("cube") != Some(&connection) {
+            state.connections.insert("cube", connection);
+        }
+
+        for message in entity.take_delivered() {
+            if let Delivery::Congested(buffered) = self.conn.send(&message) {
+                console::warn!("Connection congested, {} bytes buffered", buffered);
+            }
+        }
+
+        for envelope in self.conn.poll() {
+            match envelope.message {
+                Message::Transform(_)
+                | Message::Batch(_)
+                | Message::Sync
+                | Message::Snapshot(_) => entity.post_remote(envelope),
                 _ => {}
             }
         }
     }
+
+    fn release(&mut self, state: &mut MutexGuard<AppState>) {
+        self.conn.close();
+        state.connections.remove("cube");
+        state.latency.remove("cube");
+        state.stats.remove("cube");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        model::{Entity, EntityBuffer},
+        network::{ConnectionState, Loopback},
+        utils::{EntityTransform, Target, Transform},
+    };
+
+    #[test]
+    fn relays_messages_between_the_entity_and_the_transport() {
+        let (conn, mut remote) = Loopback::pair();
+        let mut behaviour = CubeBehaviour::with_transport(Box::new(conn));
+        let mut entity = EntityState::new(glm::vec3(0.0, 0.0, 0.0), glm::quat_identity());
+        let handle = Mutex::new(AppState::new());
+        let mut state = handle.lock().unwrap();
+
+        let rotate = EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Rotate(glm::vec3(0.5, 0.0, 0.0)),
+        };
+        remote.send(&Message::Transform(rotate));
+        remote.send(&Message::Ping(1));
+        entity.deliver(Message::Snapshot(Vec::new()));
+        behaviour.update(0.016, &mut entity, &mut state);
+
+        let posted = entity.take_posted();
+        assert_eq!(posted.len(), 1);
+        assert!(matches!(posted[0], (Message::Transform(_), Some(_))));
+
+        let sent = remote.poll();
+        assert!(matches!(sent[0].message, Message::Sync));
+        assert!(matches!(sent[1].message, Message::Snapshot(_)));
+        assert_eq!(state.connections.get("cube"), Some(&ConnectionState::Open));
+    }
+
+    #[test]
+    fn removing_the_entity_closes_its_connection() {
+        let (conn, remote) = Loopback::pair();
+        let mut entity = Entity::new(glm::vec3(0.0, 0.0, 0.0));
+        entity.add_behaviour(Box::new(CubeBehaviour::with_transport(Box::new(conn))));
+        let mut entities = EntityBuffer::new();
+        entities.add(entity);
+        let handle = Mutex::new(AppState::new());
+        let mut state = handle.lock().unwrap();
+        entities.update(0.016, &mut state);
+        assert_eq!(remote.state(), ConnectionState::Open);
+        assert!(state.stats.contains_key("cube"));
+
+        entities.remove(1, &mut state);
+        assert!(entities.get(1).is_none());
+        assert_eq!(remote.state(), ConnectionState::Closed);
+        assert!(!state.connections.contains_key("cube"));
+        assert!(!state.latency.contains_key("cube"));
+        assert!(!state.stats.contains_key("cube"));
+    }
 }
@@ -5,6 +5,7 @@ use crate::{
     asset_to_str,
     model::{Entity, Light, Material, Renderable},
     resources::ShaderError,
+    HANDLE,
 };
 
 use self::cube_behaviour::CubeBehaviour;
@@ -76,16 +77,21 @@ fn cube_renderable(app: &mut App, material: Material) -> Renderable {
 
 pub fn make_cube(app: &mut App) {
     let mut cube = Entity::new(glm::vec3(0., 0., 0.));
+    cube.set_name("cube");
     let renderable = cube_renderable(app, cube_material());
     cube.add_renderable(renderable);
+    if let Some(delay) = HANDLE.lock().unwrap().config.interpolation_delay {
+        cube.set_interpolation_delay(delay);
+    }
     cube.add_behaviour(Box::new(CubeBehaviour::new()));
     app.entities.add(cube);
 }
 
 pub fn make_lights(app: &mut App) {
     let positions = vec![glm::vec3(-3., 2., -5.), glm::vec3(3., 2., -5.)];
-    for position in positions {
+    for (i, position) in positions.into_iter().enumerate() {
         let mut light = Entity::new(position);
+        light.set_name(&format!("light-{}", i));
         let mut renderable = cube_renderable(app, light_material());
         renderable.set_light(Some(make_light()));
         light.add_renderable(renderable);
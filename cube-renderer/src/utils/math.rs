@@ -0,0 +1,100 @@
+/// Builds the quaternion for Euler angles (in radians) applied in X, Y, Z order, the order
+/// the renderer used before orientations were stored as quaternions.
+pub fn quat_from_euler(euler: &glm::Vec3) -> glm::Quat {
+    let x = glm::quat_angle_axis(euler.x, &glm::vec3(1.0, 0.0, 0.0));
+    let y = glm::quat_angle_axis(euler.y, &glm::vec3(0.0, 1.0, 0.0));
+    let z = glm::quat_angle_axis(euler.z, &glm::vec3(0.0, 0.0, 1.0));
+    x * y * z
+}
+
+/// Spherical interpolation along the shortest arc. Falls back to a normalized lerp when both
+/// orientations are almost the same, where slerp is numerically unstable.
+pub fn quat_slerp(start: &glm::Quat, end: &glm::Quat, t: f32) -> glm::Quat {
+    let mut end = *end;
+    let mut dot = glm::quat_dot(start, &end);
+    if dot < 0.0 {
+        end = -end;
+        dot = -dot;
+    }
+    if dot > 0.9995 {
+        return glm::quat_normalize(&glm::quat_lerp(start, &end, t));
+    }
+    glm::quat_slerp(start, &end, t)
+}
+
+/// The model matrix of an object at `position`, with `rotation` and `scale`: scaled first,
+/// then rotated, then translated.
+pub fn model_matrix(position: &glm::Vec3, rotation: &glm::Quat, scale: &glm::Vec3) -> glm::Mat4 {
+    let mut model = glm::identity();
+    model = glm::translate(&model, position);
+    model *= glm::quat_to_mat4(rotation);
+    glm::scale(&model, scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    use super::*;
+
+    const EPSILON: f32 = 1e-6;
+
+    fn axis(x: f32, y: f32, z: f32, angle: f32) -> glm::Quat {
+        glm::quat_angle_axis(angle, &glm::vec3(x, y, z))
+    }
+
+    fn assert_close(a: &glm::Quat, b: &glm::Quat) {
+        assert!((a.coords - b.coords).norm() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn euler_angles_compose_x_then_y_then_z() {
+        let euler = glm::vec3(0.3, -0.7, 1.1);
+        let expected =
+            axis(1.0, 0.0, 0.0, 0.3) * axis(0.0, 1.0, 0.0, -0.7) * axis(0.0, 0.0, 1.0, 1.1);
+        assert_close(&quat_from_euler(&euler), &expected);
+
+        // X·Y turns +z to +x, where Y·X would turn it to -y
+        let q = quat_from_euler(&glm::vec3(FRAC_PI_2, FRAC_PI_2, 0.0));
+        let turned = glm::quat_rotate_vec3(&q, &glm::vec3(0.0, 0.0, 1.0));
+        assert!(
+            (turned - glm::vec3(1.0, 0.0, 0.0)).norm() < EPSILON,
+            "{:?}",
+            turned
+        );
+    }
+
+    #[test]
+    fn slerp_takes_the_shortest_arc() {
+        let start = axis(0.0, 0.0, 1.0, 0.2);
+        let end = axis(0.0, 1.0, 0.0, 1.3);
+        assert_eq!(quat_slerp(&start, &end, 0.0), start);
+        assert_eq!(quat_slerp(&start, &end, 1.0), end);
+        // nearly equal orientations go through the normalized lerp
+        let near = axis(0.0, 0.0, 1.0, 0.2001);
+        assert_eq!(quat_slerp(&start, &near, 0.0), start);
+        assert_eq!(quat_slerp(&start, &near, 1.0), near);
+
+        // -q is the same orientation as q: halfway to it is 45°, not 135°
+        let quarter = -axis(0.0, 0.0, 1.0, FRAC_PI_2);
+        let halfway = quat_slerp(&glm::quat_identity(), &quarter, 0.5);
+        assert_close(&halfway, &axis(0.0, 0.0, 1.0, FRAC_PI_4));
+    }
+
+    #[test]
+    fn model_matrix_scales_then_rotates_then_translates() {
+        let position = glm::vec3(1.0, 2.0, 3.0);
+        let rotation = axis(0.0, 1.0, 0.0, 0.8);
+        let scale = glm::vec3(2.0, 0.5, 3.0);
+        let model = model_matrix(&position, &rotation, &scale);
+
+        let expected =
+            glm::translation(&position) * glm::quat_to_mat4(&rotation) * glm::scaling(&scale);
+        assert!((model - expected).norm() < EPSILON, "{:?}", model);
+
+        let point = glm::vec3(1.0, 1.0, 1.0);
+        let moved = model * glm::vec4(point.x, point.y, point.z, 1.0);
+        let by_hand = glm::quat_rotate_vec3(&rotation, &point.component_mul(&scale)) + position;
+        assert!((moved.xyz() - by_hand).norm() < EPSILON, "{:?}", moved);
+    }
+}
@@ -0,0 +1,676 @@
+//! TypeScript bindings generator
+//!
+//! The controller's `message.ts` is generated from this module: the wire constants and
+//! enums, and the encoder and decoder of every message and transform, from `MESSAGES` and
+//! `TRANSFORMS`. Those tables describe each payload field by field, and the tests below
+//! check them against the Rust codec by encoding every golden vector with them. Only the
+//! primitives the generated code is built from, such as integers, strings, targets and the
+//! frame header, are written by hand in the `codegen.ts` template.
+//!
+//! The TypeScript types mirror the JSON encoding, which is also how the golden vectors
+//! describe every message. Both sides are tested against the golden vectors.
+//!
+//! The tests below fail when the checked-in files are out of date. Regenerate them with:
+//!
+//! ```sh
+//! UPDATE_BINDINGS=1 cargo test bindings
+//! ```
+
+use std::{env, fs, path::PathBuf};
+
+use serde_json::{json, Value};
+
+use super::{
+    codecs, flags, message_types, EntitySnapshot, EntityTransform, Envelope, Handshake, Message,
+    MessageError, Role, Serializable, Stamp, Target, Transform, HEADER_LEN, MAGIC, MAX_NAME_LEN,
+    PROTOCOL_VERSION,
+};
+
+const BINDINGS: &str = "../cube-controller/src/socket/message.ts";
+const GOLDEN: &str = "../cube-controller/src/socket/golden.json";
+
+const TEMPLATE: &str = include_str!("codegen.ts");
+
+/// How one field of a payload is laid out, named as in the JSON encoding.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    U8(&'static str),
+    U32(&'static str),
+    /// A UTF-8 string prefixed by its length in one byte.
+    Name(&'static str),
+    /// Like `Name`, with `null` sent as the empty string.
+    OptionalName(&'static str),
+    Vec3(&'static str),
+    Quat(&'static str),
+    Role(&'static str),
+    /// The lowest and highest protocol version, one byte each.
+    Versions(&'static str),
+    /// A bit set of `codecs`, in one byte.
+    Codecs(&'static str),
+    /// The fields of a transform, inline: its target, type and value.
+    Transform,
+    /// The number of items, in one or two bytes, then each item.
+    List(&'static str, usize, Item),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Item {
+    Target,
+    Transform,
+    Fields(&'static [Field]),
+}
+
+/// Payload of every message type: its name, type and fields.
+const MESSAGES: &[(&str, u8, &[Field])] = &[
+    (
+        "handshake",
+        message_types::HANDSHAKE,
+        &[
+            Field::Role("role"),
+            Field::Versions("versions"),
+            Field::Codecs("codecs"),
+            Field::Name("name"),
+            Field::List("bindings", 1, Item::Target),
+        ],
+    ),
+    ("sync", message_types::SYNC, &[]),
+    ("transform", message_types::TRANSFORM, &[Field::Transform]),
+    (
+        "batch",
+        message_types::BATCH,
+        &[Field::List("transforms", 2, Item::Transform)],
+    ),
+    (
+        "snapshot",
+        message_types::SNAPSHOT,
+        &[Field::List(
+            "entities",
+            2,
+            Item::Fields(&[
+                Field::U32("id"),
+                Field::OptionalName("name"),
+                Field::Vec3("position"),
+                Field::Quat("rotation"),
+                Field::Vec3("scale"),
+            ]),
+        )],
+    ),
+    ("ping", message_types::PING, &[Field::U32("id")]),
+    (
+        "pong",
+        message_types::PONG,
+        &[Field::U32("to"), Field::U32("id")],
+    ),
+    (
+        "ack",
+        message_types::ACK,
+        &[Field::U32("to"), Field::U32("acked")],
+    ),
+    (
+        "error",
+        message_types::ERROR,
+        &[
+            Field::U32("to"),
+            Field::U32("offending"),
+            Field::U8("code"),
+            Field::U8("detail"),
+        ],
+    ),
+];
+
+/// Value of every transform type, following its target and type: its kind and layout.
+const TRANSFORMS: &[(&str, u8, Field)] = &[
+    (
+        "rotate",
+        message_types::transform::ROTATE,
+        Field::Vec3("value"),
+    ),
+    (
+        "translate",
+        message_types::transform::TRANSLATE,
+        Field::Vec3("value"),
+    ),
+    (
+        "scale",
+        message_types::transform::SCALE,
+        Field::Vec3("value"),
+    ),
+    (
+        "rotate_quat",
+        message_types::transform::ROTATE_QUAT,
+        Field::Quat("value"),
+    ),
+];
+
+fn enum_members<S: AsRef<str>>(members: &[(S, u8)]) -> String {
+    members
+        .iter()
+        .map(|(name, value)| format!("\t{} = {},", name.as_ref(), value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `MessageType` or `TransformType` member for `name`.
+fn member(name: &str) -> String {
+    name.to_uppercase()
+}
+
+/// Statements writing `fields` of `value`, indented by `indent` tabs.
+fn write_fields(fields: &[Field], value: &str, indent: usize) -> Vec<String> {
+    let tabs = "\t".repeat(indent);
+    let mut lines = Vec::new();
+    for field in fields {
+        match *field {
+            Field::U8(name) => lines.push(format!("{}w.u8({}.{});", tabs, value, name)),
+            Field::U32(name) => lines.push(format!("{}w.u32({}.{});", tabs, value, name)),
+            Field::Name(name) => lines.push(format!("{}w.string({}.{});", tabs, value, name)),
+            Field::OptionalName(name) => {
+                lines.push(format!("{}w.string({}.{} ?? '');", tabs, value, name))
+            }
+            Field::Vec3(name) | Field::Quat(name) => {
+                lines.push(format!("{}w.floats({}.{});", tabs, value, name))
+            }
+            Field::Role(name) => lines.push(format!("{}writeRole(w, {}.{});", tabs, value, name)),
+            Field::Versions(name) => {
+                lines.push(format!("{}w.u8({}.{}[0]);", tabs, value, name));
+                lines.push(format!("{}w.u8({}.{}[1]);", tabs, value, name));
+            }
+            Field::Codecs(name) => {
+                lines.push(format!("{}writeCodecs(w, {}.{});", tabs, value, name))
+            }
+            Field::Transform => lines.push(format!("{}writeTransform(w, {});", tabs, value)),
+            Field::List(name, size, item) => {
+                let list = format!("{}.{}", value, name);
+                lines.push(format!("{}w.count({}.length, {});", tabs, list, size));
+                match item {
+                    Item::Target => lines.push(format!(
+                        "{}{}.forEach((item) => writeTarget(w, item));",
+                        tabs, list
+                    )),
+                    Item::Transform => lines.push(format!(
+                        "{}{}.forEach((item) => writeTransform(w, item));",
+                        tabs, list
+                    )),
+                    Item::Fields(fields) => {
+                        lines.push(format!("{}for (const item of {}) {{", tabs, list));
+                        lines.extend(write_fields(fields, "item", indent + 1));
+                        lines.push(format!("{}}}", tabs));
+                    }
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// Object literal entries reading `fields`, in order, for a literal indented by `indent`
+/// tabs.
+fn read_fields(fields: &[Field], indent: usize) -> Vec<String> {
+    fields
+        .iter()
+        .map(|field| match *field {
+            Field::U8(name) => format!("{}: r.u8()", name),
+            Field::U32(name) => format!("{}: r.u32()", name),
+            Field::Name(name) => format!("{}: r.string()", name),
+            Field::OptionalName(name) => format!("{}: r.string() || null", name),
+            Field::Vec3(name) => format!("{}: r.vec3()", name),
+            Field::Quat(name) => format!("{}: r.quat()", name),
+            Field::Role(name) => format!("{}: readRole(r)", name),
+            Field::Versions(name) => format!("{}: [r.u8(), r.u8()] as [number, number]", name),
+            Field::Codecs(name) => format!("{}: readCodecs(r)", name),
+            Field::Transform => "...readTransform(r)".to_string(),
+            Field::List(name, size, item) => {
+                let count = if size == 1 { "r.u8()" } else { "r.u16()" };
+                let item = match item {
+                    Item::Target => "readTarget(r)".to_string(),
+                    Item::Transform => "readTransform(r)".to_string(),
+                    Item::Fields(fields) => {
+                        format!("({})", object(read_fields(fields, indent + 1), indent + 1))
+                    }
+                };
+                format!("{}: r.items({}, () => {})", name, count, item)
+            }
+        })
+        .collect()
+}
+
+/// An object literal of `entries`, on one line if it fits, or one entry per line indented
+/// by `indent` tabs.
+fn object(entries: Vec<String>, indent: usize) -> String {
+    let inline = format!("{{ {} }}", entries.join(", "));
+    if indent * 4 + inline.len() <= 90 && !inline.contains('\n') {
+        return inline;
+    }
+    let tabs = "\t".repeat(indent);
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| format!("{}\t{},\n", tabs, entry))
+        .collect();
+    format!("{{\n{}{}}}", entries.concat(), tabs)
+}
+
+fn write_transform() -> String {
+    let mut lines = vec![
+        "function writeTransform(w: Writer, transform: Transform) {".to_string(),
+        "\twriteTarget(w, transform.target);".to_string(),
+        "\tswitch (transform.kind) {".to_string(),
+    ];
+    for &(kind, _, value) in TRANSFORMS {
+        lines.push(format!("\t\tcase '{}':", kind));
+        lines.push(format!("\t\t\tw.u8(TransformType.{});", member(kind)));
+        lines.extend(write_fields(&[value], "transform", 3));
+        lines.push("\t\t\tbreak;".to_string());
+    }
+    lines.extend(["\t}".to_string(), "}".to_string()]);
+    lines.join("\n")
+}
+
+fn read_transform() -> String {
+    let mut lines = vec![
+        "function readTransform(r: Reader): Transform {".to_string(),
+        "\tconst target = readTarget(r);".to_string(),
+        "\tconst type = r.u8();".to_string(),
+        "\tswitch (type) {".to_string(),
+    ];
+    for &(kind, _, value) in TRANSFORMS {
+        lines.push(format!("\t\tcase TransformType.{}:", member(kind)));
+        let entries = vec!["target".to_string(), format!("kind: '{}'", kind)];
+        let entries = entries
+            .into_iter()
+            .chain(read_fields(&[value], 3))
+            .collect();
+        lines.push(format!("\t\t\treturn {};", object(entries, 3)));
+    }
+    lines.extend([
+        "\t\tdefault:".to_string(),
+        "\t\t\tthrow new Error(`Invalid transform type: ${type}`);".to_string(),
+        "\t}".to_string(),
+        "}".to_string(),
+    ]);
+    lines.join("\n")
+}
+
+fn write_message() -> String {
+    let mut lines = vec![
+        "function writeMessage(w: Writer, message: Message) {".to_string(),
+        "\tswitch (message.type) {".to_string(),
+    ];
+    for &(name, _, fields) in MESSAGES {
+        lines.push(format!("\t\tcase '{}':", name));
+        lines.push(format!("\t\t\tw.u8(MessageType.{});", member(name)));
+        lines.extend(write_fields(fields, "message", 3));
+        lines.push("\t\t\tbreak;".to_string());
+    }
+    lines.extend(["\t}".to_string(), "}".to_string()]);
+    lines.join("\n")
+}
+
+fn read_message() -> String {
+    let mut lines = vec![
+        "function readMessage(r: Reader): Message {".to_string(),
+        "\tconst type = r.u8();".to_string(),
+        "\tswitch (type) {".to_string(),
+    ];
+    for &(name, _, fields) in MESSAGES {
+        let entries = std::iter::once(format!("type: '{}'", name))
+            .chain(read_fields(fields, 3))
+            .collect();
+        lines.push(format!("\t\tcase MessageType.{}:", member(name)));
+        lines.push(format!("\t\t\treturn {};", object(entries, 3)));
+    }
+    lines.extend([
+        "\t\tdefault:".to_string(),
+        "\t\t\tthrow new Error(`Invalid message type: ${type}`);".to_string(),
+        "\t}".to_string(),
+        "}".to_string(),
+    ]);
+    lines.join("\n")
+}
+
+pub fn typescript() -> String {
+    use message_types::{error, target};
+
+    let message_types: Vec<_> = MESSAGES
+        .iter()
+        .map(|&(name, value, _)| (member(name), value))
+        .collect();
+    let target_types = [
+        ("OWNER", target::OWNER),
+        ("ID", target::ID),
+        ("NAME", target::NAME),
+    ];
+    let transform_types: Vec<_> = TRANSFORMS
+        .iter()
+        .map(|&(kind, value, _)| (member(kind), value))
+        .collect();
+    let roles = [
+        ("CONTROLLER", Role::Controller.to_u8()),
+        ("VIEWER", Role::Viewer.to_u8()),
+        ("RECORDER", Role::Recorder.to_u8()),
+    ];
+    let error_codes = [
+        ("INVALID_MAGIC", error::INVALID_MAGIC),
+        ("UNSUPPORTED_VERSION", error::UNSUPPORTED_VERSION),
+        ("UNSUPPORTED_FLAGS", error::UNSUPPORTED_FLAGS),
+        ("INVALID_MESSAGE_TYPE", error::INVALID_MESSAGE_TYPE),
+        ("INVALID_TRANSFORM_TYPE", error::INVALID_TRANSFORM_TYPE),
+        ("INVALID_TARGET_TYPE", error::INVALID_TARGET_TYPE),
+        ("INVALID_MESSAGE_LENGTH", error::INVALID_MESSAGE_LENGTH),
+        ("INVALID_STRING", error::INVALID_STRING),
+        ("MISSING_BASELINE", error::MISSING_BASELINE),
+        ("INVALID_JSON", error::INVALID_JSON),
+        ("INVALID_ROLE", error::INVALID_ROLE),
+        ("UNSUPPORTED_CODECS", error::UNSUPPORTED_CODECS),
+        ("FRAME_TOO_LARGE", error::FRAME_TOO_LARGE),
+        ("TRAILING_BYTES", error::TRAILING_BYTES),
+        ("NAME_TOO_LONG", error::NAME_TOO_LONG),
+        ("TOO_MANY_ITEMS", error::TOO_MANY_ITEMS),
+        ("NON_FINITE", error::NON_FINITE),
+        ("OUT_OF_RANGE", error::OUT_OF_RANGE),
+    ];
+    let codecs = [
+        ("BINARY", codecs::BINARY),
+        ("JSON", codecs::JSON),
+        ("COMPACT", codecs::COMPACT),
+    ];
+
+    TEMPLATE
+        .replace(
+            "{{MAGIC}}",
+            &format!("[0x{:02x}, 0x{:02x}]", MAGIC[0], MAGIC[1]),
+        )
+        .replace("{{PROTOCOL_VERSION}}", &PROTOCOL_VERSION.to_string())
+        .replace("{{HEADER_LEN}}", &HEADER_LEN.to_string())
+        .replace("{{MAX_NAME_LEN}}", &MAX_NAME_LEN.to_string())
+        .replace("{{FLAGS_NONE}}", &flags::NONE.to_string())
+        .replace("{{MESSAGE_TYPES}}", &enum_members(&message_types))
+        .replace("{{TARGET_TYPES}}", &enum_members(&target_types))
+        .replace("{{TRANSFORM_TYPES}}", &enum_members(&transform_types))
+        .replace("{{ROLES}}", &enum_members(&roles))
+        .replace("{{CODECS}}", &enum_members(&codecs))
+        .replace("{{ERROR_CODES}}", &enum_members(&error_codes))
+        .replace("{{WRITE_TRANSFORM}}", &write_transform())
+        .replace("{{READ_TRANSFORM}}", &read_transform())
+        .replace("{{WRITE_MESSAGE}}", &write_message())
+        .replace("{{READ_MESSAGE}}", &read_message())
+}
+
+/// One envelope per message, transform and target kind.
+pub fn golden_envelopes() -> Vec<(&'static str, Envelope)> {
+    let stamp = |sequence| Stamp {
+        sender: 0xC0FFEE,
+        sequence,
+        timestamp: 1_700_000_000_000.5,
+    };
+    let transform = |target, transform| EntityTransform { target, transform };
+
+    vec![
+        (
+            "handshake",
+            Envelope::new(
+                stamp(1),
+                Message::Handshake(Handshake {
+                    name: "phone".to_string(),
+                    role: Role::Controller,
+                    min_version: PROTOCOL_VERSION,
+                    max_version: PROTOCOL_VERSION,
+                    codecs: codecs::BINARY | codecs::JSON,
+                    bindings: vec![Target::Name("cube".to_string()), Target::Id(1)],
+                }),
+            ),
+        ),
+        ("sync", Envelope::new(stamp(2), Message::Sync)),
+        (
+            "rotate owner",
+            Envelope::new(
+                stamp(3),
+                Message::Transform(transform(
+                    Target::Owner,
+                    Transform::Rotate(glm::vec3(0.5, -1.25, 3.0)),
+                )),
+            ),
+        ),
+        (
+            "rotate quaternion by id",
+            Envelope::new(
+                stamp(4),
+                Message::Transform(transform(
+                    Target::Id(1),
+                    Transform::RotateQuat(glm::quat(0.0, 0.5, 0.5, 0.5)),
+                )),
+            ),
+        ),
+        (
+            "translate by name",
+            Envelope::new(
+                stamp(5),
+                Message::Transform(transform(
+                    Target::Name("light-0".to_string()),
+                    Transform::Translate(glm::vec3(-3.0, 2.0, -5.0)),
+                )),
+            ),
+        ),
+        (
+            "batch",
+            Envelope::new(
+                stamp(6),
+                Message::Batch(vec![
+                    transform(Target::Owner, Transform::Scale(glm::vec3(1.0, 2.0, 0.5))),
+                    transform(
+                        Target::Name("cube".to_string()),
+                        Transform::Rotate(glm::vec3(0.0, 0.25, 0.0)),
+                    ),
+                ]),
+            ),
+        ),
+        (
+            "snapshot",
+            Envelope::new(
+                stamp(7),
+                Message::Snapshot(vec![
+                    EntitySnapshot {
+                        id: 1,
+                        name: Some("cube".to_string()),
+                        position: glm::vec3(0.0, 0.0, 0.0),
+                        rotation: glm::quat(0.0, 0.0, 0.0, 1.0),
+                        scale: glm::vec3(1.0, 1.0, 1.0),
+                    },
+                    EntitySnapshot {
+                        id: 2,
+                        name: None,
+                        position: glm::vec3(-3.0, 2.0, -5.0),
+                        rotation: glm::quat(0.5, 0.5, 0.5, 0.5),
+                        scale: glm::vec3(0.25, 0.25, 0.25),
+                    },
+                ]),
+            ),
+        ),
+        ("ping", Envelope::new(stamp(8), Message::Ping(42))),
+        (
+            "pong",
+            Envelope::new(
+                stamp(9),
+                Message::Pong {
+                    to: 0xBADCAFE,
+                    id: 42,
+                },
+            ),
+        ),
+        (
+            "ack",
+            Envelope::new(
+                stamp(10),
+                Message::Ack {
+                    to: 0xBADCAFE,
+                    sequence: 1234,
+                },
+            ),
+        ),
+        (
+            "error",
+            Envelope::new(
+                stamp(11),
+                Message::Error {
+                    to: 0xBADCAFE,
+                    sequence: 1235,
+                    error: MessageError::UnsupportedFlags(0x80),
+                },
+            ),
+        ),
+    ]
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn golden_vectors() -> String {
+    let vectors: Vec<Value> = golden_envelopes()
+        .iter()
+        .map(|(name, envelope)| {
+            let message: Value = serde_json::from_str(&envelope.to_json()).unwrap();
+            json!({
+                "name": name,
+                "hex": hex(&envelope.to_bytes()),
+                "message": message,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&vectors).unwrap() + "\n"
+}
+
+/// Compares `generated` with the checked-in file at `path`, or overwrites it when
+/// `UPDATE_BINDINGS` is set.
+fn check(path: &str, generated: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(path);
+    if env::var_os("UPDATE_BINDINGS").is_some() {
+        fs::write(&path, generated).unwrap();
+        return;
+    }
+    let current = fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        current == generated,
+        "{} is out of date, run `UPDATE_BINDINGS=1 cargo test bindings`",
+        path.display()
+    );
+}
+
+mod tests {
+    use super::*;
+
+    /// Encodes the JSON form of a payload with the layouts the TypeScript code is generated
+    /// from, for comparison with the Rust codec.
+    fn encode_fields(fields: &[Field], value: &Value, bytes: &mut Vec<u8>) {
+        let name = |bytes: &mut Vec<u8>, name: &str| {
+            bytes.push(name.len() as u8);
+            bytes.extend_from_slice(name.as_bytes());
+        };
+        let floats = |bytes: &mut Vec<u8>, value: &Value| {
+            for float in value.as_array().unwrap() {
+                bytes.extend_from_slice(&(float.as_f64().unwrap() as f32).to_le_bytes());
+            }
+        };
+        for field in fields {
+            match *field {
+                Field::U8(key) => bytes.push(value[key].as_u64().unwrap() as u8),
+                Field::U32(key) => {
+                    bytes.extend_from_slice(&(value[key].as_u64().unwrap() as u32).to_le_bytes())
+                }
+                Field::Name(key) => name(bytes, value[key].as_str().unwrap()),
+                Field::OptionalName(key) => name(bytes, value[key].as_str().unwrap_or("")),
+                Field::Vec3(key) | Field::Quat(key) => floats(bytes, &value[key]),
+                Field::Role(key) => bytes.push(
+                    Role::from_name(value[key].as_str().unwrap())
+                        .unwrap()
+                        .to_u8(),
+                ),
+                Field::Versions(key) => {
+                    bytes.push(value[key][0].as_u64().unwrap() as u8);
+                    bytes.push(value[key][1].as_u64().unwrap() as u8);
+                }
+                Field::Codecs(key) => {
+                    let flags = value[key]
+                        .as_array()
+                        .unwrap()
+                        .iter()
+                        .fold(0, |flags, codec| {
+                            flags
+                                | match codec.as_str().unwrap() {
+                                    "binary" => codecs::BINARY,
+                                    "json" => codecs::JSON,
+                                    _ => codecs::COMPACT,
+                                }
+                        });
+                    bytes.push(flags);
+                }
+                Field::Transform => encode_transform(value, bytes),
+                Field::List(key, size, item) => {
+                    let items = value[key].as_array().unwrap();
+                    bytes.extend_from_slice(&(items.len() as u16).to_le_bytes()[..size]);
+                    for value in items {
+                        match item {
+                            Item::Target => encode_target(value, bytes),
+                            Item::Transform => encode_transform(value, bytes),
+                            Item::Fields(fields) => encode_fields(fields, value, bytes),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode_target(value: &Value, bytes: &mut Vec<u8>) {
+        let target = match (value.as_str(), value["id"].as_u64(), value["name"].as_str()) {
+            (Some("owner"), _, _) => Target::Owner,
+            (_, Some(id), _) => Target::Id(id as u32),
+            (_, _, Some(name)) => Target::Name(name.to_string()),
+            _ => panic!("invalid target {}", value),
+        };
+        bytes.extend_from_slice(&target.to_bytes());
+    }
+
+    fn encode_transform(value: &Value, bytes: &mut Vec<u8>) {
+        encode_target(&value["target"], bytes);
+        let kind = value["kind"].as_str().unwrap();
+        let &(_, type_, field) = TRANSFORMS.iter().find(|(name, ..)| *name == kind).unwrap();
+        bytes.push(type_);
+        encode_fields(&[field], value, bytes);
+    }
+
+    #[test]
+    fn bindings_layouts_match_the_codec() {
+        for (name, envelope) in golden_envelopes() {
+            let value: Value = serde_json::from_str(&envelope.to_json()).unwrap();
+            let &(_, type_, fields) = MESSAGES
+                .iter()
+                .find(|(name, ..)| *name == value["type"])
+                .unwrap();
+            let mut bytes = vec![type_];
+            encode_fields(fields, &value, &mut bytes);
+            assert_eq!(bytes, envelope.message.to_bytes(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn bindings_are_up_to_date() {
+        check(BINDINGS, &typescript());
+    }
+
+    #[test]
+    fn bindings_golden_vectors_are_up_to_date() {
+        check(GOLDEN, &golden_vectors());
+    }
+
+    #[test]
+    fn bindings_golden_vectors_round_trip() {
+        for (name, envelope) in golden_envelopes() {
+            let bytes = envelope.to_bytes();
+            let decoded = Envelope::from_bytes(&bytes).unwrap();
+            assert_eq!(decoded.to_bytes(), bytes, "{}", name);
+            assert_eq!(decoded.to_json(), envelope.to_json(), "{}", name);
+
+            let from_json = Envelope::from_json(&envelope.to_json()).unwrap();
+            assert_eq!(from_json.to_bytes(), bytes, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+// Generated from cube-renderer/src/utils/message, do not edit.
+// Regenerate with `UPDATE_BINDINGS=1 cargo test bindings` in cube-renderer.
+// The message and transform encoders and decoders are generated from the layouts in
+// codegen.rs; the primitives they are built from are written in codegen.ts.
+
+export const MAGIC = {{MAGIC}};
+export const PROTOCOL_VERSION = {{PROTOCOL_VERSION}};
+export const HEADER_LEN = {{HEADER_LEN}};
+export const MAX_NAME_LEN = {{MAX_NAME_LEN}};
+
+export enum MessageType {
+{{MESSAGE_TYPES}}
+}
+
+export enum TargetType {
+{{TARGET_TYPES}}
+}
+
+export enum TransformType {
+{{TRANSFORM_TYPES}}
+}
+
+export enum RoleType {
+{{ROLES}}
+}
+
+export enum CodecFlag {
+{{CODECS}}
+}
+
+export enum ErrorCode {
+{{ERROR_CODES}}
+}
+
+export type Vec3 = [number, number, number];
+export type Quat = [number, number, number, number];
+
+export type Target = 'owner' | { id: number } | { name: string };
+
+export type Transform =
+	| { target: Target; kind: 'rotate'; value: Vec3 }
+	| { target: Target; kind: 'rotate_quat'; value: Quat }
+	| { target: Target; kind: 'translate'; value: Vec3 }
+	| { target: Target; kind: 'scale'; value: Vec3 };
+
+export type Role = 'controller' | 'viewer' | 'recorder';
+export type Codec = 'binary' | 'json' | 'compact';
+
+export type Handshake = {
+	name: string;
+	role: Role;
+	versions: [number, number];
+	codecs: Codec[];
+	bindings: Target[];
+};
+
+export type EntitySnapshot = {
+	id: number;
+	name: string | null;
+	position: Vec3;
+	rotation: Quat;
+	scale: Vec3;
+};
+
+export type Message =
+	| ({ type: 'handshake' } & Handshake)
+	| { type: 'sync' }
+	| { type: 'snapshot'; entities: EntitySnapshot[] }
+	| ({ type: 'transform' } & Transform)
+	| { type: 'batch'; transforms: Transform[] }
+	| { type: 'ping'; id: number }
+	| { type: 'pong'; to: number; id: number }
+	| { type: 'ack'; to: number; acked: number }
+	| { type: 'error'; to: number; offending: number; code: ErrorCode; detail: number };
+
+export type Stamp = { sender: number; sequence: number; timestamp: number };
+
+export type Envelope = Message & Stamp & { version: number };
+
+class Writer {
+	private bytes: number[] = [];
+
+	u8(value: number) {
+		this.bytes.push(value & 0xff);
+	}
+
+	u16(value: number) {
+		this.raw(2, (view) => view.setUint16(0, value, true));
+	}
+
+	u32(value: number) {
+		this.raw(4, (view) => view.setUint32(0, value, true));
+	}
+
+	f32(value: number) {
+		this.raw(4, (view) => view.setFloat32(0, value, true));
+	}
+
+	f64(value: number) {
+		this.raw(8, (view) => view.setFloat64(0, value, true));
+	}
+
+	floats(values: number[]) {
+		values.forEach((value) => this.f32(value));
+	}
+
+	string(value: string) {
+		const bytes = new TextEncoder().encode(value);
+		if (bytes.length > MAX_NAME_LEN) {
+			throw new Error(`Name too long: ${bytes.length} bytes`);
+		}
+		this.u8(bytes.length);
+		bytes.forEach((b) => this.u8(b));
+	}
+
+	/** Writes the number of items that follow, in `size` bytes. */
+	count(value: number, size: 1 | 2) {
+		if (value > (size === 1 ? 0xff : 0xffff)) {
+			throw new Error(`Too many items: ${value}`);
+		}
+		if (size === 1) {
+			this.u8(value);
+		} else {
+			this.u16(value);
+		}
+	}
+
+	finish(): Uint8Array {
+		return new Uint8Array(this.bytes);
+	}
+
+	private raw(length: number, write: (view: DataView) => void) {
+		const view = new DataView(new ArrayBuffer(length));
+		write(view);
+		new Uint8Array(view.buffer).forEach((b) => this.bytes.push(b));
+	}
+}
+
+class Reader {
+	private offset = 0;
+	private view: DataView;
+
+	constructor(private bytes: Uint8Array) {
+		this.view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+	}
+
+	u8(): number {
+		return this.view.getUint8(this.advance(1));
+	}
+
+	u16(): number {
+		return this.view.getUint16(this.advance(2), true);
+	}
+
+	u32(): number {
+		return this.view.getUint32(this.advance(4), true);
+	}
+
+	f32(): number {
+		return this.view.getFloat32(this.advance(4), true);
+	}
+
+	f64(): number {
+		return this.view.getFloat64(this.advance(8), true);
+	}
+
+	vec3(): Vec3 {
+		return [this.f32(), this.f32(), this.f32()];
+	}
+
+	quat(): Quat {
+		return [this.f32(), this.f32(), this.f32(), this.f32()];
+	}
+
+	string(): string {
+		const length = this.u8();
+		const start = this.advance(length);
+		return new TextDecoder().decode(this.bytes.subarray(start, start + length));
+	}
+
+	items<T>(count: number, read: () => T): T[] {
+		const items: T[] = [];
+		for (; count > 0; count--) {
+			items.push(read());
+		}
+		return items;
+	}
+
+	private advance(length: number): number {
+		if (this.offset + length > this.bytes.length) {
+			throw new Error('Invalid message length');
+		}
+		const offset = this.offset;
+		this.offset += length;
+		return offset;
+	}
+}
+
+function writeTarget(w: Writer, target: Target) {
+	if (target === 'owner') {
+		w.u8(TargetType.OWNER);
+	} else if ('id' in target) {
+		w.u8(TargetType.ID);
+		w.u32(target.id);
+	} else {
+		w.u8(TargetType.NAME);
+		w.string(target.name);
+	}
+}
+
+function readTarget(r: Reader): Target {
+	const type = r.u8();
+	switch (type) {
+		case TargetType.OWNER:
+			return 'owner';
+		case TargetType.ID:
+			return { id: r.u32() };
+		case TargetType.NAME:
+			return { name: r.string() };
+		default:
+			throw new Error(`Invalid target type: ${type}`);
+	}
+}
+
+const ROLES: [Role, RoleType][] = [
+	['controller', RoleType.CONTROLLER],
+	['viewer', RoleType.VIEWER],
+	['recorder', RoleType.RECORDER],
+];
+
+const CODECS: [Codec, CodecFlag][] = [
+	['binary', CodecFlag.BINARY],
+	['json', CodecFlag.JSON],
+	['compact', CodecFlag.COMPACT],
+];
+
+function writeRole(w: Writer, role: Role) {
+	const found = ROLES.find(([name]) => name === role);
+	w.u8(found ? found[1] : RoleType.CONTROLLER);
+}
+
+function readRole(r: Reader): Role {
+	const type = r.u8();
+	const role = ROLES.find(([, value]) => value === type);
+	if (!role) {
+		throw new Error(`Invalid role: ${type}`);
+	}
+	return role[0];
+}
+
+function writeCodecs(w: Writer, codecs: Codec[]) {
+	w.u8(
+		CODECS.filter(([name]) => codecs.includes(name)).reduce(
+			(flags, [, flag]) => flags | flag,
+			0,
+		),
+	);
+}
+
+function readCodecs(r: Reader): Codec[] {
+	const flags = r.u8();
+	return CODECS.filter(([, flag]) => flags & flag).map(([name]) => name);
+}
+
+{{WRITE_TRANSFORM}}
+
+{{READ_TRANSFORM}}
+
+{{WRITE_MESSAGE}}
+
+{{READ_MESSAGE}}
+
+function messageToBytes(message: Message, stamp: Stamp): Uint8Array {
+	const payload = new Writer();
+	writeMessage(payload, message);
+	const body = payload.finish();
+
+	const w = new Writer();
+	MAGIC.forEach((b) => w.u8(b));
+	w.u8(PROTOCOL_VERSION);
+	w.u8({{FLAGS_NONE}});
+	w.u32(body.length);
+	w.u32(stamp.sender);
+	w.u32(stamp.sequence);
+	w.f64(stamp.timestamp);
+	body.forEach((b) => w.u8(b));
+	return w.finish();
+}
+
+function messageFromBytes(bytes: Uint8Array): Envelope {
+	const r = new Reader(bytes);
+	if (r.u8() !== MAGIC[0] || r.u8() !== MAGIC[1]) {
+		throw new Error('Invalid magic');
+	}
+	const version = r.u8();
+	if (version !== PROTOCOL_VERSION) {
+		throw new Error(`Unsupported version: ${version}`);
+	}
+	const flags = r.u8();
+	if (flags !== {{FLAGS_NONE}}) {
+		throw new Error(`Unsupported flags: ${flags}`);
+	}
+	const length = r.u32();
+	if (bytes.length !== HEADER_LEN + length) {
+		throw new Error('Invalid message length');
+	}
+	const stamp = { sender: r.u32(), sequence: r.u32(), timestamp: r.f64() };
+	return { version, ...stamp, ...readMessage(r) };
+}
+
+export { messageToBytes, messageFromBytes };
@@ -0,0 +1,566 @@
+//! Compact transform encoding
+//!
+//! Frames with the `COMPACT` flag carry their transforms in a smaller encoding, picked per
+//! transform by the top bits of the transform type byte (see the `encoding` module):
+//! - `FULL`: the regular `f32` components.
+//! - `QUANTIZED`: every component as an `i16` multiple of the kind's unit.
+//! - `DELTA`: every component as an `i8` difference, in units, against the last transform of
+//!   the same kind sent by the same sender to the same target.
+//!
+//! Units are `ANGLE_UNIT` for Euler angles, `QUAT_UNIT` for quaternion components and
+//! `LINEAR_UNIT` for translations and scales, so quantized and delta components are off by at
+//! most half a unit. Every other message type is encoded exactly as in full frames.
+//!
+//! The compact encoding is only used once every peer on the connection has listed the
+//! `COMPACT` codec in its handshake.
+//!
+//! Deltas are taken against the last transform the peer has decoded. WebSocket frames are
+//! delivered in order, so that is the last one sent on the same connection; both sides must
+//! `reset` their codec whenever the connection is reopened.
+//!
+//! A peer that joins an open connection, e.g. through the relay, hasn't decoded anything yet.
+//! The sender calls `resend_baselines` when a new peer shakes hands, or one reports
+//! `MissingBaseline`, so that the next transform of every kind and target is sent whole.
+//!
+//! Whatever the encoding, a codec rejects decoded messages outside of its `Limits`.
+
+use std::collections::HashMap;
+
+use super::{
+    codecs, flags, message_types, EntityTransform, Envelope, Header, Limits, Message, MessageError,
+    Serializable, Target, Transform,
+};
+
+pub const ANGLE_UNIT: f32 = std::f32::consts::PI / i16::MAX as f32;
+pub const QUAT_UNIT: f32 = 1.0 / i16::MAX as f32;
+pub const LINEAR_UNIT: f32 = 1.0 / 512.0;
+
+pub mod encoding {
+    pub const FULL: u8 = 0x00;
+    pub const QUANTIZED: u8 = 0x40;
+    pub const DELTA: u8 = 0x80;
+    pub const MASK: u8 = 0xC0;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Full,
+    Compact,
+}
+
+type Quantized = [i32; 4];
+
+/// Per-connection encoder and decoder state.
+///
+/// Outgoing frames are only compacted when the codec prefers it and the peers' handshakes
+/// said they can read them. Incoming frames are checked against `limits`.
+#[derive(Debug)]
+pub struct Codec {
+    preferred: Encoding,
+    peer_codecs: u8,
+    limits: Limits,
+    sent: HashMap<(Target, u8), Quantized>,
+    received: HashMap<(u32, Target, u8), Quantized>,
+}
+
+impl Codec {
+    pub fn new(preferred: Encoding) -> Codec {
+        Codec {
+            preferred,
+            peer_codecs: codecs::NONE,
+            limits: Limits::default(),
+            sent: HashMap::new(),
+            received: HashMap::new(),
+        }
+    }
+
+    /// The encoding used for outgoing transforms.
+    pub fn encoding(&self) -> Encoding {
+        if self.preferred == Encoding::Compact && self.peer_codecs & codecs::COMPACT != 0 {
+            Encoding::Compact
+        } else {
+            Encoding::Full
+        }
+    }
+
+    pub fn set_preferred(&mut self, preferred: Encoding) {
+        self.preferred = preferred;
+    }
+
+    /// Sets the codecs every peer on the connection can read, as listed in their handshakes.
+    pub fn set_peer_codecs(&mut self, peer_codecs: u8) {
+        self.peer_codecs = peer_codecs;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Sends the next transform of every kind and target quantized, or full, instead of as a
+    /// delta, for peers that didn't decode the previous ones.
+    pub fn resend_baselines(&mut self) {
+        self.sent.clear();
+    }
+
+    /// Forgets the negotiated encoding and every delta baseline.
+    pub fn reset(&mut self) {
+        self.peer_codecs = codecs::NONE;
+        self.sent.clear();
+        self.received.clear();
+    }
+
+    /// Encodes a binary frame, in the compact encoding once negotiated. Fails like
+    /// `Envelope::encode` does.
+    pub fn encode(&mut self, envelope: &Envelope) -> Result<Vec<u8>, MessageError> {
+        if self.encoding() == Encoding::Full {
+            return envelope.encode();
+        }
+        envelope.message.check_encodable()?;
+
+        let payload = match &envelope.message {
+            Message::Transform(transform) => {
+                let mut bytes = vec![message_types::TRANSFORM];
+                self.write_transform(&mut bytes, transform);
+                bytes
+            }
+            Message::Batch(transforms) => {
+                // checked against `MAX_ITEMS` above
+                let mut bytes = vec![message_types::BATCH];
+                bytes.extend_from_slice(&(transforms.len() as u16).to_le_bytes());
+                for transform in transforms {
+                    self.write_transform(&mut bytes, transform);
+                }
+                bytes
+            }
+            message => message.to_bytes(),
+        };
+        let mut header = Header::new(payload.len(), envelope.stamp);
+        header.flags |= flags::COMPACT;
+        let mut bytes = header.to_bytes();
+        bytes.extend_from_slice(&payload);
+        Ok(bytes)
+    }
+
+    /// Decodes a binary frame and checks it against the codec's limits.
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<Envelope, MessageError> {
+        self.limits.check_frame_len(bytes.len())?;
+        let envelope = self.decode_unchecked(bytes)?;
+        envelope.validate(&self.limits)?;
+        Ok(envelope)
+    }
+
+    /// Decodes a text frame, see the `json` module, and checks it against the codec's limits.
+    pub fn decode_json(&self, text: &str) -> Result<Envelope, MessageError> {
+        self.limits.check_frame_len(text.len())?;
+        let envelope = Envelope::from_json(text)?;
+        envelope.validate(&self.limits)?;
+        Ok(envelope)
+    }
+
+    fn decode_unchecked(&mut self, bytes: &[u8]) -> Result<Envelope, MessageError> {
+        if !Header::is_framed(bytes) {
+            return Envelope::from_bytes(bytes);
+        }
+        let header = Header::from_bytes(bytes)?;
+        if header.flags & flags::COMPACT == 0 {
+            return Envelope::from_bytes(bytes);
+        }
+
+        let payload = &bytes[header.encoded_len()..];
+        if payload.len() != header.length as usize || payload.is_empty() {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let sender = header.stamp.sender;
+        let (message, len) = match payload[0] {
+            message_types::TRANSFORM => {
+                let (transform, len) = self.read_transform(sender, &payload[1..])?;
+                (Message::Transform(transform), 1 + len)
+            }
+            message_types::BATCH => {
+                if payload.len() < 3 {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let count = u16::from_le_bytes([payload[1], payload[2]]) as usize;
+                let mut transforms = Vec::with_capacity(count);
+                let mut offset = 3;
+                for _ in 0..count {
+                    let (transform, len) = self.read_transform(sender, &payload[offset..])?;
+                    offset += len;
+                    transforms.push(transform);
+                }
+                (Message::Batch(transforms), offset)
+            }
+            _ => (
+                Message::from_versioned_bytes(header.version, payload)?,
+                payload.len(),
+            ),
+        };
+        if len != payload.len() {
+            return Err(MessageError::TrailingBytes);
+        }
+        Ok(Envelope::new(header.stamp, message))
+    }
+
+    fn write_transform(&mut self, bytes: &mut Vec<u8>, transform: &EntityTransform) {
+        bytes.extend_from_slice(&transform.target.to_bytes());
+
+        let kind = kind(&transform.transform);
+        let values = components(&transform.transform);
+        let unit = unit(kind);
+        let quantized = quantize(&values, unit);
+        let n = values.len();
+        let key = (transform.target.clone(), kind);
+
+        let delta = self.sent.get(&key).and_then(|base| {
+            let delta: Vec<i32> = (0..n).map(|i| quantized[i] - base[i]).collect();
+            delta
+                .iter()
+                .all(|d| i8::try_from(*d).is_ok())
+                .then_some(delta)
+        });
+        if let Some(delta) = delta {
+            bytes.push(kind | encoding::DELTA);
+            bytes.extend(delta.iter().map(|&d| d as i8 as u8));
+        } else if quantized[..n].iter().all(|q| i16::try_from(*q).is_ok()) {
+            bytes.push(kind | encoding::QUANTIZED);
+            for q in &quantized[..n] {
+                bytes.extend_from_slice(&(*q as i16).to_le_bytes());
+            }
+        } else {
+            bytes.extend_from_slice(&transform.transform.to_bytes());
+        }
+        self.sent.insert(key, quantized);
+    }
+
+    /// Reads one target and transform, returning it along with the number of bytes read.
+    fn read_transform(
+        &mut self,
+        sender: u32,
+        bytes: &[u8],
+    ) -> Result<(EntityTransform, usize), MessageError> {
+        let target = Target::from_bytes(bytes)?;
+        let offset = target.encoded_len();
+        if bytes.len() <= offset {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let kind = bytes[offset] & !encoding::MASK;
+        let n = match kind {
+            message_types::transform::ROTATE_QUAT => 4,
+            message_types::transform::ROTATE
+            | message_types::transform::TRANSLATE
+            | message_types::transform::SCALE => 3,
+            _ => return Err(MessageError::InvalidTransformType),
+        };
+        let unit = unit(kind);
+        let key = (sender, target.clone(), kind);
+        let body = &bytes[offset + 1..];
+
+        let (transform, len) = match bytes[offset] & encoding::MASK {
+            encoding::FULL => {
+                let transform = Transform::from_bytes(&bytes[offset..])?;
+                let len = transform.encoded_len() - 1;
+                self.received
+                    .insert(key, quantize(&components(&transform), unit));
+                (transform, len)
+            }
+            encoding::QUANTIZED => {
+                if body.len() < 2 * n {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let mut quantized = [0; 4];
+                for (i, q) in quantized.iter_mut().take(n).enumerate() {
+                    *q = i16::from_le_bytes([body[2 * i], body[2 * i + 1]]) as i32;
+                }
+                self.received.insert(key, quantized);
+                (from_quantized(kind, &quantized, unit), 2 * n)
+            }
+            encoding::DELTA => {
+                if body.len() < n {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let base = self
+                    .received
+                    .get(&key)
+                    .ok_or(MessageError::MissingBaseline)?;
+                let mut quantized = *base;
+                for (i, q) in quantized.iter_mut().take(n).enumerate() {
+                    *q = q
+                        .checked_add(body[i] as i8 as i32)
+                        .ok_or(MessageError::OutOfRange)?;
+                }
+                self.received.insert(key, quantized);
+                (from_quantized(kind, &quantized, unit), n)
+            }
+            _ => return Err(MessageError::InvalidTransformType),
+        };
+        Ok((EntityTransform { target, transform }, offset + 1 + len))
+    }
+}
+
+fn kind(transform: &Transform) -> u8 {
+    match transform {
+        Transform::Rotate(_) => message_types::transform::ROTATE,
+        Transform::RotateQuat(_) => message_types::transform::ROTATE_QUAT,
+        Transform::Translate(_) => message_types::transform::TRANSLATE,
+        Transform::Scale(_) => message_types::transform::SCALE,
+    }
+}
+
+fn unit(kind: u8) -> f32 {
+    match kind {
+        message_types::transform::ROTATE => ANGLE_UNIT,
+        message_types::transform::ROTATE_QUAT => QUAT_UNIT,
+        _ => LINEAR_UNIT,
+    }
+}
+
+fn components(transform: &Transform) -> Vec<f32> {
+    match transform {
+        Transform::Rotate(v) | Transform::Translate(v) | Transform::Scale(v) => {
+            vec![v.x, v.y, v.z]
+        }
+        Transform::RotateQuat(q) => q.coords.iter().copied().collect(),
+    }
+}
+
+fn quantize(values: &[f32], unit: f32) -> Quantized {
+    let mut quantized = [0; 4];
+    for (q, v) in quantized.iter_mut().zip(values) {
+        *q = (v / unit).round() as i32;
+    }
+    quantized
+}
+
+fn from_quantized(kind: u8, quantized: &Quantized, unit: f32) -> Transform {
+    let c = |i: usize| quantized[i] as f32 * unit;
+    match kind {
+        message_types::transform::ROTATE => Transform::Rotate(glm::vec3(c(0), c(1), c(2))),
+        message_types::transform::ROTATE_QUAT => {
+            Transform::RotateQuat(glm::quat(c(0), c(1), c(2), c(3)))
+        }
+        message_types::transform::TRANSLATE => Transform::Translate(glm::vec3(c(0), c(1), c(2))),
+        _ => Transform::Scale(glm::vec3(c(0), c(1), c(2))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Stamp, HEADER_LEN, MAX_ITEMS};
+    use super::*;
+
+    fn envelope(sequence: u32, message: Message) -> Envelope {
+        let stamp = Stamp {
+            sender: 7,
+            sequence,
+            timestamp: 0.0,
+        };
+        Envelope::new(stamp, message)
+    }
+
+    fn rotate(target: Target, euler: glm::Vec3) -> Message {
+        Message::Transform(EntityTransform {
+            target,
+            transform: Transform::Rotate(euler),
+        })
+    }
+
+    /// Two codecs that already agreed on the compact encoding.
+    fn negotiated() -> (Codec, Codec) {
+        let mut sender = Codec::new(Encoding::Compact);
+        let receiver = Codec::new(Encoding::Compact);
+        sender.set_peer_codecs(codecs::ALL);
+        (sender, receiver)
+    }
+
+    fn round_trip(sender: &mut Codec, receiver: &mut Codec, message: Message) -> (usize, Message) {
+        let bytes = sender.encode(&envelope(1, message)).unwrap();
+        (bytes.len(), receiver.decode(&bytes).unwrap().message)
+    }
+
+    fn max_error(sent: &[f32], received: &Transform) -> f32 {
+        sent.iter()
+            .zip(components(received))
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn stays_full_until_the_peer_reads_compact() {
+        let mut sender = Codec::new(Encoding::Compact);
+        assert_eq!(sender.encoding(), Encoding::Full);
+
+        let message = rotate(Target::Owner, glm::vec3(0.1, 0.2, 0.3));
+        let bytes = sender.encode(&envelope(1, message)).unwrap();
+        let header = Header::from_bytes(&bytes).unwrap();
+        assert_eq!(header.flags & flags::COMPACT, 0);
+
+        sender.set_peer_codecs(codecs::BINARY | codecs::JSON);
+        assert_eq!(sender.encoding(), Encoding::Full);
+        sender.set_peer_codecs(codecs::ALL);
+        assert_eq!(sender.encoding(), Encoding::Compact);
+
+        let mut full = Codec::new(Encoding::Full);
+        full.set_peer_codecs(codecs::ALL);
+        assert_eq!(full.encoding(), Encoding::Full);
+    }
+
+    #[test]
+    fn quantized_euler_angles_are_within_half_a_unit() {
+        let (mut sender, mut receiver) = negotiated();
+        let angles = [-3.1, -1.0, 0.0, 0.123_456, 1.5, 3.0];
+        for (i, a) in angles.iter().enumerate() {
+            // a new target every time, so there's no delta baseline
+            let sent = glm::vec3(*a, -a / 2.0, a / 3.0);
+            let message = rotate(Target::Id(i as u32), sent);
+            let (len, received) = round_trip(&mut sender, &mut receiver, message);
+            let Message::Transform(received) = received else {
+                panic!("expected a transform")
+            };
+            assert_eq!(len, HEADER_LEN + 1 + 5 + 1 + 3 * 2);
+            assert!(max_error(sent.as_slice(), &received.transform) <= ANGLE_UNIT / 2.0);
+        }
+    }
+
+    #[test]
+    fn deltas_are_within_half_a_unit_and_do_not_drift() {
+        let (mut sender, mut receiver) = negotiated();
+        let mut sent = glm::quat_identity();
+        for i in 0..500 {
+            let step = glm::quat_angle_axis(0.001 * i as f32, &glm::vec3(0.3, 1.0, 0.2));
+            sent = glm::quat_normalize(&(sent * step));
+            let message = Message::Transform(EntityTransform {
+                target: Target::Name("cube".to_string()),
+                transform: Transform::RotateQuat(sent),
+            });
+            let (_, received) = round_trip(&mut sender, &mut receiver, message);
+            let Message::Transform(received) = received else {
+                panic!("expected a transform")
+            };
+            let sent: Vec<f32> = sent.coords.iter().copied().collect();
+            assert!(max_error(&sent, &received.transform) <= QUAT_UNIT / 2.0);
+        }
+    }
+
+    #[test]
+    fn small_changes_are_sent_as_deltas() {
+        let (mut sender, mut receiver) = negotiated();
+        let first = Message::Transform(EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Translate(glm::vec3(1.0, 2.0, 3.0)),
+        });
+        let (quantized_len, _) = round_trip(&mut sender, &mut receiver, first);
+        let second = Message::Transform(EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Translate(glm::vec3(1.01, 2.0, 2.99)),
+        });
+        let (delta_len, received) = round_trip(&mut sender, &mut receiver, second);
+        assert_eq!(quantized_len - delta_len, 3);
+        let Message::Transform(received) = received else {
+            panic!("expected a transform")
+        };
+        assert!(max_error(&[1.01, 2.0, 2.99], &received.transform) <= LINEAR_UNIT / 2.0);
+    }
+
+    #[test]
+    fn out_of_range_values_fall_back_to_full() {
+        let (mut sender, mut receiver) = negotiated();
+        receiver.set_limits(Limits {
+            max_scale: 1e5,
+            ..Limits::default()
+        });
+        let sent = glm::vec3(1000.0, -0.5, 12345.678);
+        let message = Message::Transform(EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Scale(sent),
+        });
+        let (_, received) = round_trip(&mut sender, &mut receiver, message);
+        let Message::Transform(received) = received else {
+            panic!("expected a transform")
+        };
+        assert_eq!(components(&received.transform), sent.as_slice());
+    }
+
+    #[test]
+    fn batches_round_trip() {
+        let (mut sender, mut receiver) = negotiated();
+        let message = Message::Batch(vec![
+            EntityTransform {
+                target: Target::Owner,
+                transform: Transform::Rotate(glm::vec3(0.5, 0.25, -0.25)),
+            },
+            EntityTransform {
+                target: Target::Name("light-0".to_string()),
+                transform: Transform::Translate(glm::vec3(-3.0, 2.0, -5.0)),
+            },
+        ]);
+        let (_, received) = round_trip(&mut sender, &mut receiver, message);
+        let Message::Batch(received) = received else {
+            panic!("expected a batch")
+        };
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[1].target, Target::Name("light-0".to_string()));
+        let error = max_error(&[-3.0, 2.0, -5.0], &received[1].transform);
+        assert!(error <= LINEAR_UNIT / 2.0);
+    }
+
+    #[test]
+    fn oversized_batches_are_not_encoded() {
+        let rotate = EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Rotate(glm::vec3(0.0, 0.0, 0.0)),
+        };
+        let batch = Message::Batch(vec![rotate; MAX_ITEMS + 1]);
+        let (mut compact, _) = negotiated();
+        let mut full = Codec::new(Encoding::Full);
+        for codec in [&mut compact, &mut full] {
+            assert_eq!(
+                codec.encode(&envelope(1, batch.clone())).unwrap_err(),
+                MessageError::TooManyItems
+            );
+        }
+    }
+
+    #[test]
+    fn deltas_without_a_baseline_are_rejected() {
+        let (mut sender, mut receiver) = negotiated();
+        let message = |x| rotate(Target::Owner, glm::vec3(x, 0.0, 0.0));
+        sender.encode(&envelope(1, message(0.5))).unwrap();
+        let delta = sender.encode(&envelope(2, message(0.501))).unwrap();
+        assert_eq!(
+            receiver.decode(&delta).unwrap_err(),
+            MessageError::MissingBaseline
+        );
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        let mut receiver = Codec::new(Encoding::Full);
+
+        let mut trailing = envelope(1, Message::Ping(1)).to_bytes();
+        trailing.push(0);
+        let length = (trailing.len() - HEADER_LEN) as u32;
+        trailing[4..8].copy_from_slice(&length.to_le_bytes());
+        assert_eq!(
+            receiver.decode(&trailing).unwrap_err(),
+            MessageError::TrailingBytes
+        );
+
+        let nan = rotate(Target::Owner, glm::vec3(f32::NAN, 0.0, 0.0));
+        let nan = envelope(1, nan).to_bytes();
+        assert_eq!(receiver.decode(&nan).unwrap_err(), MessageError::NonFinite);
+
+        let json = envelope(1, Message::Ping(1)).to_json();
+        receiver.set_limits(Limits {
+            max_frame_len: json.len() - 1,
+            ..Limits::default()
+        });
+        assert_eq!(
+            receiver.decode_json(&json).unwrap_err(),
+            MessageError::FrameTooLarge
+        );
+    }
+}
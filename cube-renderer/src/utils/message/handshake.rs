@@ -0,0 +1,335 @@
+//! Handshake message
+//!
+//! The first message sent on every connection, telling peers who the sender is and what it
+//! understands. From version 3 on, the `HANDSHAKE` message type is followed by:
+//! - `role` (1 byte): one of the `role` constants.
+//! - `min_version` and `max_version` (1 byte each): the protocol versions the sender can read.
+//! - `codecs` (1 byte): bit set of the `codecs` the sender can read.
+//! - `name`: the name length (1 byte) and the UTF-8 encoded name.
+//! - `bindings`: the number of entities the sender wants to drive (1 byte), then each of them
+//!   encoded as a transform target.
+//!
+//! Older handshakes only carry the name, as the rest of the payload. They are read as a
+//! controller that only understands binary frames of the version it sent.
+
+use super::{
+    check_name_len, MessageError, Serializable, Target, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+
+/// Most bindings a handshake can carry.
+pub const MAX_BINDINGS: usize = u8::MAX as usize;
+
+mod role {
+    pub const CONTROLLER: u8 = 0;
+    pub const VIEWER: u8 = 1;
+    pub const RECORDER: u8 = 2;
+}
+
+pub mod codecs {
+    pub const NONE: u8 = 0;
+    /// Binary frames, see the `message` module.
+    pub const BINARY: u8 = 1 << 0;
+    /// Text frames, see the `json` module.
+    pub const JSON: u8 = 1 << 1;
+    /// Binary frames with the `COMPACT` flag, see the `compact` module.
+    pub const COMPACT: u8 = 1 << 2;
+    /// Every codec understood by this build.
+    pub const ALL: u8 = BINARY | JSON | COMPACT;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Drives entities, e.g. the phone controller.
+    Controller,
+    /// Renders the scene, e.g. this renderer.
+    Viewer,
+    /// Records the traffic for later replay.
+    Recorder,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handshake {
+    pub name: String,
+    pub role: Role,
+    pub min_version: u8,
+    pub max_version: u8,
+    pub codecs: u8,
+    pub bindings: Vec<Target>,
+}
+
+impl Role {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Role::Controller => role::CONTROLLER,
+            Role::Viewer => role::VIEWER,
+            Role::Recorder => role::RECORDER,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Result<Role, MessageError> {
+        match value {
+            role::CONTROLLER => Ok(Role::Controller),
+            role::VIEWER => Ok(Role::Viewer),
+            role::RECORDER => Ok(Role::Recorder),
+            _ => Err(MessageError::InvalidRole),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Controller => "controller",
+            Role::Viewer => "viewer",
+            Role::Recorder => "recorder",
+        }
+    }
+
+    pub fn from_name(value: &str) -> Result<Role, MessageError> {
+        match value {
+            "controller" => Ok(Role::Controller),
+            "viewer" => Ok(Role::Viewer),
+            "recorder" => Ok(Role::Recorder),
+            _ => Err(MessageError::InvalidRole),
+        }
+    }
+}
+
+impl Handshake {
+    /// A handshake advertising everything this build supports.
+    pub fn new(name: &str, role: Role, bindings: Vec<Target>) -> Handshake {
+        Handshake {
+            name: name.to_string(),
+            role,
+            min_version: MIN_PROTOCOL_VERSION,
+            max_version: PROTOCOL_VERSION,
+            codecs: codecs::ALL,
+            bindings,
+        }
+    }
+
+    /// The handshake implied by a pre-version 3 `name`, sent with protocol `version`.
+    pub fn legacy(name: String, version: u8) -> Handshake {
+        Handshake {
+            name,
+            role: Role::Controller,
+            min_version: version,
+            max_version: version,
+            codecs: codecs::BINARY,
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Checks that this side can read `peer`'s frames: peers always send frames of their
+    /// `max_version`, and need at least one codec in common with this side. Legacy peers
+    /// pass, even though they can't read this side's frames, see `reads`.
+    pub fn check(&self, peer: &Handshake) -> Result<(), MessageError> {
+        if !self.reads(peer.max_version) {
+            return Err(MessageError::UnsupportedVersion(peer.max_version));
+        }
+        if self.codecs & peer.codecs & (codecs::BINARY | codecs::JSON) == 0 {
+            return Err(MessageError::UnsupportedCodecs(peer.codecs));
+        }
+        Ok(())
+    }
+
+    /// Whether the sender of this handshake can read frames of protocol `version`.
+    pub fn reads(&self, version: u8) -> bool {
+        (self.min_version..=self.max_version).contains(&version)
+    }
+
+    /// Decodes the bytes following the message type, as sent with protocol `version`.
+    pub fn from_versioned_bytes(version: u8, bytes: &[u8]) -> Result<Self, MessageError> {
+        if version < 3 {
+            let name =
+                String::from_utf8(bytes.to_vec()).map_err(|_| MessageError::InvalidString)?;
+            return Ok(Handshake::legacy(name, version));
+        }
+        Handshake::from_bytes(bytes)
+    }
+
+    /// Checks that the name and bindings fit their length bytes.
+    pub fn check_encodable(&self) -> Result<(), MessageError> {
+        check_name_len(&self.name)?;
+        if self.bindings.len() > MAX_BINDINGS {
+            return Err(MessageError::TooManyItems);
+        }
+        self.bindings.iter().try_for_each(Target::check_encodable)
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        let bindings: usize = self.bindings.iter().map(Target::encoded_len).sum();
+        4 + 1 + self.name.len() + 1 + bindings
+    }
+}
+
+impl Serializable for Handshake {
+    /// Panics if `check_encodable` fails, see `Envelope::encode`.
+    fn to_bytes(&self) -> Vec<u8> {
+        assert!(
+            self.check_encodable().is_ok(),
+            "handshake name or bindings too long"
+        );
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.push(self.role.to_u8());
+        bytes.push(self.min_version);
+        bytes.push(self.max_version);
+        bytes.push(self.codecs);
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(self.name.as_bytes());
+        bytes.push(self.bindings.len() as u8);
+        for target in &self.bindings {
+            bytes.extend_from_slice(&target.to_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        if bytes.len() < 5 {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let role = Role::from_u8(bytes[0])?;
+        let name_len = bytes[4] as usize;
+        if bytes.len() < 6 + name_len {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let name = String::from_utf8(bytes[5..5 + name_len].to_vec())
+            .map_err(|_| MessageError::InvalidString)?;
+
+        let count = bytes[5 + name_len] as usize;
+        let mut bindings = Vec::with_capacity(count);
+        let mut offset = 6 + name_len;
+        for _ in 0..count {
+            let target = Target::from_bytes(&bytes[offset..])?;
+            offset += target.encoded_len();
+            bindings.push(target);
+        }
+        Ok(Handshake {
+            name,
+            role,
+            min_version: bytes[1],
+            max_version: bytes[2],
+            codecs: bytes[3],
+            bindings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        message_types, EntitySnapshot, EntityTransform, Envelope, Header, Message, Stamp,
+        Transform, MAX_NAME_LEN,
+    };
+    use super::*;
+
+    fn handshake() -> Handshake {
+        Handshake::new(
+            "phone",
+            Role::Controller,
+            vec![Target::Name("cube".to_string())],
+        )
+    }
+
+    #[test]
+    fn round_trips() {
+        let envelope = Envelope::new(Stamp::default(), Message::Handshake(handshake()));
+        let decoded = Envelope::from_bytes(&envelope.to_bytes()).unwrap();
+        let Message::Handshake(decoded) = decoded.message else {
+            panic!("expected a handshake")
+        };
+        assert_eq!(decoded, handshake());
+    }
+
+    #[test]
+    fn legacy_handshakes_only_carry_a_name() {
+        let mut bytes = Header {
+            version: 2,
+            ..Header::new(6, Stamp::default())
+        }
+        .to_bytes();
+        bytes.push(message_types::HANDSHAKE);
+        bytes.extend_from_slice(b"phone");
+        let Message::Handshake(decoded) = Envelope::from_bytes(&bytes).unwrap().message else {
+            panic!("expected a handshake")
+        };
+        assert_eq!(decoded, Handshake::legacy("phone".to_string(), 2));
+        assert_eq!(handshake().check(&decoded), Ok(()));
+        assert!(!decoded.reads(PROTOCOL_VERSION));
+    }
+
+    #[test]
+    fn peers_need_a_common_version_and_codec() {
+        let local = Handshake::new("renderer", Role::Viewer, Vec::new());
+        assert_eq!(local.check(&handshake()), Ok(()));
+
+        let newer = Handshake {
+            min_version: PROTOCOL_VERSION + 1,
+            max_version: PROTOCOL_VERSION + 1,
+            ..handshake()
+        };
+        assert_eq!(
+            local.check(&newer),
+            Err(MessageError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+        );
+
+        let compact_only = Handshake {
+            codecs: codecs::COMPACT,
+            ..handshake()
+        };
+        assert_eq!(
+            local.check(&compact_only),
+            Err(MessageError::UnsupportedCodecs(codecs::COMPACT))
+        );
+    }
+
+    #[test]
+    fn names_and_bindings_must_fit_their_length_byte() {
+        let encode = |message| Envelope::new(Stamp::default(), message).encode();
+        let long = "x".repeat(MAX_NAME_LEN + 1);
+
+        let named = Handshake::new(&long, Role::Controller, Vec::new());
+        assert_eq!(
+            encode(Message::Handshake(named)).unwrap_err(),
+            MessageError::NameTooLong
+        );
+        let bound = Handshake::new("phone", Role::Controller, vec![Target::Owner; 256]);
+        assert_eq!(
+            encode(Message::Handshake(bound)).unwrap_err(),
+            MessageError::TooManyItems
+        );
+        let snapshot = EntitySnapshot {
+            id: 1,
+            name: Some(long.clone()),
+            position: glm::vec3(0.0, 0.0, 0.0),
+            rotation: glm::quat_identity(),
+            scale: glm::vec3(1.0, 1.0, 1.0),
+        };
+        assert_eq!(
+            encode(Message::Snapshot(vec![snapshot])).unwrap_err(),
+            MessageError::NameTooLong
+        );
+        let transform = EntityTransform {
+            target: Target::Name(long),
+            transform: Transform::Scale(glm::vec3(1.0, 1.0, 1.0)),
+        };
+        assert_eq!(
+            encode(Message::Transform(transform)).unwrap_err(),
+            MessageError::NameTooLong
+        );
+
+        let longest = Handshake::new(&"x".repeat(MAX_NAME_LEN), Role::Viewer, Vec::new());
+        assert!(encode(Message::Handshake(longest)).is_ok());
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        let mut bytes = handshake().to_bytes();
+        bytes[0] = 9;
+        assert_eq!(
+            Handshake::from_bytes(&bytes).unwrap_err(),
+            MessageError::InvalidRole
+        );
+    }
+}
@@ -0,0 +1,525 @@
+//! JSON encoding
+//!
+//! A human-readable mapping of `Envelope` and `Message`, sent as WebSocket text frames. It
+//! carries the same information as the binary encoding, so either can be used on any
+//! connection. For example, rotating the cube:
+//!
+//! ```json
+//! {
+//!   "sender": 1, "sequence": 42, "timestamp": 1700000000000.0,
+//!   "type": "transform", "target": { "name": "cube" }, "kind": "rotate", "value": [0, 1.5, 0]
+//! }
+//! ```
+//!
+//! - `version`, `sender`, `sequence` and `timestamp` are optional and default to
+//!   `PROTOCOL_VERSION` and zero; the message fields sit next to them.
+//! - `type` is one of `handshake`, `sync`, `snapshot` (with `entities`), `transform`,
+//!   `batch` (with `transforms`), `ping` (with `id`), `pong` (with `to` and `id`), `ack`
+//!   (with `to` and the `acked` sequence number) and `error` (with `to`, the `offending`
+//!   sequence number, `code` and `detail`).
+//! - A handshake has a `name`, a `role` (`controller`, `viewer` or `recorder`), the
+//!   `[min, max]` protocol `versions`, the `codecs` it reads (`binary`, `json`, `compact`)
+//!   and the `bindings` it wants to drive, as targets. Everything but the name is optional,
+//!   defaulting to a controller that reads binary and JSON frames of the envelope's version.
+//! - A transform has a `target`, a `kind` (`rotate`, `rotate_quat`, `translate` or `scale`)
+//!   and a `value` array. The target is `"owner"`, `{ "id": 1 }` or `{ "name": "cube" }`, and
+//!   defaults to `"owner"` when missing.
+//! - A snapshot entity has an `id`, an optional `name`, and `position`, `rotation` (x, y, z,
+//!   w) and `scale` arrays.
+
+use serde_json::{json, Map, Value};
+
+use super::{
+    codecs, EntitySnapshot, EntityTransform, Envelope, Handshake, Message, MessageError, Role,
+    Stamp, Target, Transform, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+
+const CODECS: [(u8, &str); 3] = [
+    (codecs::BINARY, "binary"),
+    (codecs::JSON, "json"),
+    (codecs::COMPACT, "compact"),
+];
+
+type Object = Map<String, Value>;
+
+impl Envelope {
+    pub fn to_json(&self) -> String {
+        let mut object = message_to_json(&self.message);
+        object.insert("version".into(), json!(PROTOCOL_VERSION));
+        object.insert("sender".into(), json!(self.stamp.sender));
+        object.insert("sequence".into(), json!(self.stamp.sequence));
+        object.insert("timestamp".into(), json!(self.stamp.timestamp));
+        Value::Object(object).to_string()
+    }
+
+    pub fn from_json(text: &str) -> Result<Envelope, MessageError> {
+        let value: Value = serde_json::from_str(text).map_err(|_| MessageError::InvalidJson)?;
+        let object = value.as_object().ok_or(MessageError::InvalidJson)?;
+        let (version, stamp) = header_from_json(object)?;
+        Ok(Envelope::new(stamp, message_from_json(version, object)?))
+    }
+}
+
+impl Stamp {
+    /// The stamp of a JSON message whose version and stamp can be read, even if the rest
+    /// can't.
+    pub fn peek_json(text: &str) -> Option<Stamp> {
+        let value: Value = serde_json::from_str(text).ok()?;
+        let (_, stamp) = header_from_json(value.as_object()?).ok()?;
+        Some(stamp)
+    }
+}
+
+fn header_from_json(object: &Object) -> Result<(u8, Stamp), MessageError> {
+    let version = optional_u32(object, "version")?.unwrap_or(PROTOCOL_VERSION as u32);
+    if !(MIN_PROTOCOL_VERSION as u32..=PROTOCOL_VERSION as u32).contains(&version) {
+        return Err(MessageError::UnsupportedVersion(
+            version.min(u8::MAX as u32) as u8,
+        ));
+    }
+    let timestamp = match object.get("timestamp") {
+        None => 0.0,
+        Some(value) => value.as_f64().ok_or(MessageError::InvalidJson)?,
+    };
+    let stamp = Stamp {
+        sender: optional_u32(object, "sender")?.unwrap_or(0),
+        sequence: optional_u32(object, "sequence")?.unwrap_or(0),
+        timestamp,
+    };
+    Ok((version as u8, stamp))
+}
+
+fn message_to_json(message: &Message) -> Object {
+    let mut object = Object::new();
+    match message {
+        Message::Handshake(handshake) => {
+            object = handshake_to_json(handshake);
+            object.insert("type".into(), json!("handshake"));
+        }
+        Message::Sync => {
+            object.insert("type".into(), json!("sync"));
+        }
+        Message::Snapshot(entities) => {
+            let entities: Vec<Value> = entities.iter().map(snapshot_to_json).collect();
+            object.insert("type".into(), json!("snapshot"));
+            object.insert("entities".into(), Value::Array(entities));
+        }
+        Message::Transform(transform) => {
+            object = transform_to_json(transform);
+            object.insert("type".into(), json!("transform"));
+        }
+        Message::Batch(transforms) => {
+            let transforms = transforms
+                .iter()
+                .map(|t| Value::Object(transform_to_json(t)))
+                .collect();
+            object.insert("type".into(), json!("batch"));
+            object.insert("transforms".into(), Value::Array(transforms));
+        }
+        Message::Ping(id) => {
+            object.insert("type".into(), json!("ping"));
+            object.insert("id".into(), json!(id));
+        }
+        Message::Pong { to, id } => {
+            object.insert("type".into(), json!("pong"));
+            object.insert("to".into(), json!(to));
+            object.insert("id".into(), json!(id));
+        }
+        Message::Ack { to, sequence } => {
+            object.insert("type".into(), json!("ack"));
+            object.insert("to".into(), json!(to));
+            object.insert("acked".into(), json!(sequence));
+        }
+        Message::Error {
+            to,
+            sequence,
+            error,
+        } => {
+            let (code, detail) = error.code();
+            object.insert("type".into(), json!("error"));
+            object.insert("to".into(), json!(to));
+            object.insert("offending".into(), json!(sequence));
+            object.insert("code".into(), json!(code));
+            object.insert("detail".into(), json!(detail));
+        }
+    }
+    object
+}
+
+fn message_from_json(version: u8, object: &Object) -> Result<Message, MessageError> {
+    let kind = object.get("type").and_then(Value::as_str);
+    match kind.ok_or(MessageError::InvalidMessageType)? {
+        "handshake" => Ok(Message::Handshake(handshake_from_json(version, object)?)),
+        "sync" => Ok(Message::Sync),
+        "snapshot" => {
+            let entities = array(object, "entities")?
+                .iter()
+                .map(snapshot_from_json)
+                .collect::<Result<_, _>>()?;
+            Ok(Message::Snapshot(entities))
+        }
+        "transform" => Ok(Message::Transform(transform_from_json(object)?)),
+        "batch" => {
+            let transforms = array(object, "transforms")?
+                .iter()
+                .map(|t| transform_from_json(t.as_object().ok_or(MessageError::InvalidJson)?))
+                .collect::<Result<_, _>>()?;
+            Ok(Message::Batch(transforms))
+        }
+        "ping" => Ok(Message::Ping(required_u32(object, "id")?)),
+        "pong" => Ok(Message::Pong {
+            to: required_u32(object, "to")?,
+            id: required_u32(object, "id")?,
+        }),
+        "ack" => Ok(Message::Ack {
+            to: required_u32(object, "to")?,
+            sequence: required_u32(object, "acked")?,
+        }),
+        "error" => {
+            let byte = |key| {
+                u8::try_from(required_u32(object, key)?).map_err(|_| MessageError::InvalidJson)
+            };
+            Ok(Message::Error {
+                to: required_u32(object, "to")?,
+                sequence: required_u32(object, "offending")?,
+                error: MessageError::from_code(byte("code")?, byte("detail")?),
+            })
+        }
+        _ => Err(MessageError::InvalidMessageType),
+    }
+}
+
+fn handshake_to_json(handshake: &Handshake) -> Object {
+    let codecs: Vec<&str> = CODECS
+        .iter()
+        .filter(|(codec, _)| handshake.codecs & codec != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    let bindings: Vec<Value> = handshake.bindings.iter().map(target_to_json).collect();
+    let mut object = Object::new();
+    object.insert("name".into(), json!(handshake.name));
+    object.insert("role".into(), json!(handshake.role.name()));
+    object.insert(
+        "versions".into(),
+        json!([handshake.min_version, handshake.max_version]),
+    );
+    object.insert("codecs".into(), json!(codecs));
+    object.insert("bindings".into(), Value::Array(bindings));
+    object
+}
+
+fn handshake_from_json(version: u8, object: &Object) -> Result<Handshake, MessageError> {
+    let mut handshake = Handshake::legacy(string(object, "name")?, version);
+    handshake.codecs = codecs::BINARY | codecs::JSON;
+
+    if let Some(role) = object.get("role") {
+        let role = role.as_str().ok_or(MessageError::InvalidRole)?;
+        handshake.role = Role::from_name(role)?;
+    }
+    if object.contains_key("versions") {
+        let versions = array(object, "versions")?;
+        let version = |i: usize| {
+            versions
+                .get(i)
+                .and_then(Value::as_u64)
+                .and_then(|v| u8::try_from(v).ok())
+                .ok_or(MessageError::InvalidJson)
+        };
+        if versions.len() != 2 {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        handshake.min_version = version(0)?;
+        handshake.max_version = version(1)?;
+    }
+    if object.contains_key("codecs") {
+        handshake.codecs = codecs::NONE;
+        for codec in array(object, "codecs")? {
+            // unknown codecs are skipped, the peer may simply be newer
+            let name = codec.as_str().ok_or(MessageError::InvalidJson)?;
+            if let Some((codec, _)) = CODECS.iter().find(|(_, n)| *n == name) {
+                handshake.codecs |= codec;
+            }
+        }
+    }
+    if object.contains_key("bindings") {
+        handshake.bindings = array(object, "bindings")?
+            .iter()
+            .map(|target| target_from_json(Some(target)))
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(handshake)
+}
+
+fn target_to_json(target: &Target) -> Value {
+    match target {
+        Target::Owner => json!("owner"),
+        Target::Id(id) => json!({ "id": id }),
+        Target::Name(name) => json!({ "name": name }),
+    }
+}
+
+fn target_from_json(value: Option<&Value>) -> Result<Target, MessageError> {
+    let value = match value {
+        None => return Ok(Target::Owner),
+        Some(value) => value,
+    };
+    if value.as_str() == Some("owner") {
+        return Ok(Target::Owner);
+    }
+    let object = value.as_object().ok_or(MessageError::InvalidTargetType)?;
+    if let Some(id) = optional_u32(object, "id")? {
+        return Ok(Target::Id(id));
+    }
+    match object.get("name").and_then(Value::as_str) {
+        Some(name) => Ok(Target::Name(name.to_string())),
+        None => Err(MessageError::InvalidTargetType),
+    }
+}
+
+fn transform_to_json(transform: &EntityTransform) -> Object {
+    let (kind, value) = match &transform.transform {
+        Transform::Rotate(v) => ("rotate", vec3_to_json(v)),
+        Transform::RotateQuat(q) => ("rotate_quat", quat_to_json(q)),
+        Transform::Translate(v) => ("translate", vec3_to_json(v)),
+        Transform::Scale(v) => ("scale", vec3_to_json(v)),
+    };
+    let mut object = Object::new();
+    object.insert("target".into(), target_to_json(&transform.target));
+    object.insert("kind".into(), json!(kind));
+    object.insert("value".into(), value);
+    object
+}
+
+fn transform_from_json(object: &Object) -> Result<EntityTransform, MessageError> {
+    let target = target_from_json(object.get("target"))?;
+    let value = object.get("value");
+    let transform = match object.get("kind").and_then(Value::as_str) {
+        Some("rotate") => Transform::Rotate(vec3_from_json(value)?),
+        Some("rotate_quat") => Transform::RotateQuat(quat_from_json(value)?),
+        Some("translate") => Transform::Translate(vec3_from_json(value)?),
+        Some("scale") => Transform::Scale(vec3_from_json(value)?),
+        _ => return Err(MessageError::InvalidTransformType),
+    };
+    Ok(EntityTransform { target, transform })
+}
+
+fn snapshot_to_json(snapshot: &EntitySnapshot) -> Value {
+    json!({
+        "id": snapshot.id,
+        "name": snapshot.name,
+        "position": vec3_to_json(&snapshot.position),
+        "rotation": quat_to_json(&snapshot.rotation),
+        "scale": vec3_to_json(&snapshot.scale),
+    })
+}
+
+fn snapshot_from_json(value: &Value) -> Result<EntitySnapshot, MessageError> {
+    let object = value.as_object().ok_or(MessageError::InvalidJson)?;
+    let name = match object.get("name") {
+        None | Some(Value::Null) => None,
+        Some(_) => Some(string(object, "name")?),
+    };
+    Ok(EntitySnapshot {
+        id: required_u32(object, "id")?,
+        name,
+        position: vec3_from_json(object.get("position"))?,
+        rotation: quat_from_json(object.get("rotation"))?,
+        scale: vec3_from_json(object.get("scale"))?,
+    })
+}
+
+fn vec3_to_json(v: &glm::Vec3) -> Value {
+    json!([v.x, v.y, v.z])
+}
+
+fn quat_to_json(q: &glm::Quat) -> Value {
+    json!([q.coords.x, q.coords.y, q.coords.z, q.coords.w])
+}
+
+fn floats(value: Option<&Value>, len: usize) -> Result<Vec<f32>, MessageError> {
+    let array = value
+        .and_then(Value::as_array)
+        .ok_or(MessageError::InvalidJson)?;
+    if array.len() != len {
+        return Err(MessageError::InvalidMessageLength);
+    }
+    array
+        .iter()
+        .map(|v| {
+            v.as_f64()
+                .map(|f| f as f32)
+                .ok_or(MessageError::InvalidJson)
+        })
+        .collect()
+}
+
+fn vec3_from_json(value: Option<&Value>) -> Result<glm::Vec3, MessageError> {
+    let c = floats(value, 3)?;
+    Ok(glm::vec3(c[0], c[1], c[2]))
+}
+
+fn quat_from_json(value: Option<&Value>) -> Result<glm::Quat, MessageError> {
+    let c = floats(value, 4)?;
+    Ok(glm::quat(c[0], c[1], c[2], c[3]))
+}
+
+fn string(object: &Object, key: &str) -> Result<String, MessageError> {
+    match object.get(key).and_then(Value::as_str) {
+        Some(s) => Ok(s.to_string()),
+        None => Err(MessageError::InvalidString),
+    }
+}
+
+fn array<'a>(object: &'a Object, key: &str) -> Result<&'a Vec<Value>, MessageError> {
+    object
+        .get(key)
+        .and_then(Value::as_array)
+        .ok_or(MessageError::InvalidJson)
+}
+
+fn required_u32(object: &Object, key: &str) -> Result<u32, MessageError> {
+    optional_u32(object, key)?.ok_or(MessageError::InvalidJson)
+}
+
+fn optional_u32(object: &Object, key: &str) -> Result<Option<u32>, MessageError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .map(Some)
+            .ok_or(MessageError::InvalidJson),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(message: Message) -> Message {
+        let stamp = Stamp {
+            sender: 7,
+            sequence: 42,
+            timestamp: 1700000000000.0,
+        };
+        let decoded = Envelope::from_json(&Envelope::new(stamp, message).to_json()).unwrap();
+        assert_eq!(decoded.stamp, stamp);
+        decoded.message
+    }
+
+    #[test]
+    fn round_trips() {
+        let transform = EntityTransform {
+            target: Target::Name("cube".to_string()),
+            transform: Transform::RotateQuat(glm::quat(0.0, 0.6, 0.0, 0.8)),
+        };
+        let Message::Transform(decoded) = round_trip(Message::Transform(transform)) else {
+            panic!("expected a transform")
+        };
+        assert_eq!(decoded.target, Target::Name("cube".to_string()));
+        assert!(matches!(decoded.transform, Transform::RotateQuat(q) if q.coords.w == 0.8));
+
+        let snapshot = EntitySnapshot {
+            id: 3,
+            name: None,
+            position: glm::vec3(1.0, 2.0, 3.0),
+            rotation: glm::quat_identity(),
+            scale: glm::vec3(1.0, 1.0, 1.0),
+        };
+        let Message::Snapshot(decoded) = round_trip(Message::Snapshot(vec![snapshot.clone()]))
+        else {
+            panic!("expected a snapshot")
+        };
+        assert_eq!(decoded, [snapshot]);
+
+        let handshake = Handshake::new("phone", Role::Controller, vec![Target::Id(1)]);
+        let Message::Handshake(decoded) = round_trip(Message::Handshake(handshake.clone())) else {
+            panic!("expected a handshake")
+        };
+        assert_eq!(decoded, handshake);
+
+        let error = Message::Error {
+            to: 1,
+            sequence: 2,
+            error: MessageError::UnsupportedVersion(9),
+        };
+        assert!(matches!(
+            round_trip(error),
+            Message::Error {
+                to: 1,
+                sequence: 2,
+                error: MessageError::UnsupportedVersion(9)
+            }
+        ));
+        assert!(matches!(
+            round_trip(Message::Ack { to: 1, sequence: 5 }),
+            Message::Ack { to: 1, sequence: 5 }
+        ));
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let envelope =
+            Envelope::from_json(r#"{"type": "transform", "kind": "scale", "value": [1, 2, 3]}"#)
+                .unwrap();
+        assert_eq!(envelope.stamp, Stamp::default());
+        let Message::Transform(transform) = envelope.message else {
+            panic!("expected a transform")
+        };
+        assert_eq!(transform.target, Target::Owner);
+
+        let envelope = Envelope::from_json(r#"{"type": "handshake", "name": "phone"}"#).unwrap();
+        let Message::Handshake(handshake) = envelope.message else {
+            panic!("expected a handshake")
+        };
+        assert_eq!(handshake.role, Role::Controller);
+        assert_eq!(handshake.max_version, PROTOCOL_VERSION);
+        assert_eq!(handshake.codecs, codecs::BINARY | codecs::JSON);
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let error = |text| Envelope::from_json(text).unwrap_err();
+        assert_eq!(error("{"), MessageError::InvalidJson);
+        assert_eq!(error("[1, 2]"), MessageError::InvalidJson);
+        assert_eq!(error(r#"{"id": 1}"#), MessageError::InvalidMessageType);
+        assert_eq!(
+            error(r#"{"type": "teleport"}"#),
+            MessageError::InvalidMessageType
+        );
+        assert_eq!(
+            error(r#"{"type": "sync", "version": 200}"#),
+            MessageError::UnsupportedVersion(200)
+        );
+        assert_eq!(
+            error(r#"{"type": "sync", "sender": -1}"#),
+            MessageError::InvalidJson
+        );
+        assert_eq!(
+            error(r#"{"type": "transform", "kind": "spin", "value": [0, 0, 0]}"#),
+            MessageError::InvalidTransformType
+        );
+        assert_eq!(
+            error(r#"{"type": "transform", "kind": "rotate", "value": [0, 0]}"#),
+            MessageError::InvalidMessageLength
+        );
+        assert_eq!(
+            error(r#"{"type": "transform", "target": 3, "kind": "rotate", "value": [0, 0, 0]}"#),
+            MessageError::InvalidTargetType
+        );
+        assert_eq!(
+            error(r#"{"type": "handshake", "name": "phone", "role": "pilot"}"#),
+            MessageError::InvalidRole
+        );
+        assert_eq!(
+            error(r#"{"type": "error", "to": 1, "offending": 2, "code": 300, "detail": 0}"#),
+            MessageError::InvalidJson
+        );
+        assert_eq!(
+            Stamp::peek_json(r#"{"type": "?", "sender": 4}"#)
+                .unwrap()
+                .sender,
+            4
+        );
+    }
+}
@@ -0,0 +1,994 @@
+//! Message types and structs
+//!
+//! This module contains the message types and structs used by the server and client.
+//!
+//! # Summary
+//! Messages are sent in binary frames, laid out as described below, or in text frames with
+//! the equivalent JSON encoding described in the `json` module.
+//!
+//! Every message is wrapped in a frame. The frame header identifies the protocol and its
+//! version, and tells how many payload bytes follow it. The first byte of the payload is the
+//! message type. The message type is used to determine how to parse the rest of the message.
+//! The message types are defined in the `message_types` module.
+//!
+//! The TypeScript bindings used by the controller are generated from this module, see the
+//! `codegen` module.
+//!
+//! # Frame Header
+//! The header is `HEADER_LEN` bytes long:
+//! - `magic` (2 bytes): always `MAGIC`. Frames that don't start with it are decoded with the
+//!   legacy, unframed layout (version 0).
+//! - `version` (1 byte): the protocol version the sender was built with. Frames newer than
+//!   `PROTOCOL_VERSION` or older than `MIN_PROTOCOL_VERSION` are rejected.
+//! - `flags` (1 byte): bit flags defined in the `flags` module. Unknown bits are rejected.
+//!   Frames with the `COMPACT` flag must be decoded by a `Codec`.
+//! - `length` (4 bytes, little endian): the payload length in bytes.
+//! - `sender` (4 bytes, little endian): an id picked by the sender when it starts.
+//! - `sequence` (4 bytes, little endian): incremented by the sender for every frame it sends,
+//!   starting at 1. Zero means the frame is unsequenced.
+//! - `timestamp` (8 bytes, little endian): the sender's clock, as an `f64` in milliseconds.
+//!
+//! Version 1 headers stop after `length`; their frames, like legacy ones, are unsequenced.
+//! Version 3 only changes the `HANDSHAKE` payload.
+//!
+//! # Message Types
+//! The message types are defined in the `message_types` module. The message types are:
+//! - `HANDSHAKE`: The first message sent on a connection, with the sender's name, role,
+//!   supported protocol versions and codecs, and the entities it wants to drive. Peers that
+//!   see a new sender respond with their own handshake. See the `handshake` module.
+//! - `SYNC`: Sent by a client that just connected, asking its peers for the scene state.
+//!   Peers respond with a `SNAPSHOT`.
+//! - `SNAPSHOT`: The position, rotation and scale of every entity in the sender's scene.
+//!   The receiver applies it to its own entities.
+//! - `TRANSFORM`: The client sends this message to the server indicating that some change
+//!   has been made to the client's state. The server does not respond to this message.
+//! - `BATCH`: An ordered list of transforms, possibly for different entities, that must be
+//!   applied together in the same frame.
+//! - `PING`: Sent on an interval to check that peers are still there. Peers respond with a
+//!   `PONG`.
+//! - `PONG`: The answer to a `PING`, addressed to the sender that sent it.
+//! - `ACK`: Tells a sender the last sequence number received from it.
+//! - `ERROR`: Tells a sender that one of its frames couldn't be decoded, and why.
+//!
+//! ## Transform Message
+//! The `TRANSFORM` message type is used to indicate that some change has been made to the
+//! client's state. The `TRANSFORM` message type is followed by the target entity and then by
+//! a transform type byte. The transform type byte is used to determine how to parse the rest
+//! of the message. The transform types are defined in the `transform` module.
+//!
+//! ### Targets
+//! The target starts with a tag byte, defined in the `target` module:
+//! - `OWNER`: The entity that owns the connection the message arrived on.
+//! - `ID`: Followed by the entity id (4 bytes, little endian).
+//! - `NAME`: Followed by the name length (1 byte) and the UTF-8 encoded name.
+//!
+//! Legacy (version 0) frames have no target and are always addressed to `OWNER`.
+//!
+//! ### Transform Types
+//! - `ROTATE`: The client has rotated the object, as Euler angles in radians.
+//! - `ROTATE_QUAT`: The client has rotated the object, as a quaternion (x, y, z, w).
+//! - `TRANSLATE`: The client has translated the object.
+//! - `SCALE`: The client has scaled the object.
+//!
+//! ## Snapshot Message
+//! The `SNAPSHOT` message type is followed by the number of entities (2 bytes, little endian)
+//! and then by each entity: id (4 bytes), name length (1 byte, zero when unnamed), the UTF-8
+//! name, position (3 floats), rotation quaternion (4 floats) and scale (3 floats).
+//!
+//! ## Batch Message
+//! The `BATCH` message type is followed by the number of transforms (2 bytes, little endian)
+//! and then by each transform, encoded as in the `TRANSFORM` message: target and transform.
+//!
+//! ## Ping and Pong Messages
+//! The `PING` message type is followed by an id (4 bytes, little endian) picked by the
+//! sender. The `PONG` message type is followed by the sender id of the pinging peer and the
+//! ping's id (4 bytes each, little endian), so that every peer can tell which pongs answer its
+//! own pings.
+//!
+//! ## Ack and Error Messages
+//! The `ACK` message type is followed by the sender id the ack is addressed to and the
+//! sequence number being acknowledged (4 bytes each, little endian). Acks are cumulative: they
+//! cover every frame up to that sequence number.
+//!
+//! The `ERROR` message type is followed by the sender id and sequence number of the offending
+//! frame (4 bytes each, little endian), then by an error code (1 byte) from the `error`
+//! module and its detail (1 byte): the version for `UNSUPPORTED_VERSION`, the flags for
+//! `UNSUPPORTED_FLAGS`, the codecs for `UNSUPPORTED_CODECS` and zero otherwise. Errors are
+//! only sent back when the offending frame's header could be read, since otherwise the
+//! sender most likely can't read the error either.
+//!
+//! # Validation
+//! Names longer than `MAX_NAME_LEN` don't fit their length byte, nor batches and snapshots of
+//! more than `MAX_ITEMS` their count, so `Envelope::encode` and `Codec::encode` refuse to
+//! encode them rather than truncate the length.
+//!
+//! A payload must end with its message: trailing bytes are rejected. Decoded messages can
+//! further be checked against configurable `Limits`, see the `validate` module.
+
+pub const MAGIC: [u8; 2] = [0xC0, 0xBE];
+pub const PROTOCOL_VERSION: u8 = 3;
+pub const MIN_PROTOCOL_VERSION: u8 = 0;
+pub const HEADER_LEN: usize = 24;
+/// Longest name a frame can carry, in bytes.
+pub const MAX_NAME_LEN: usize = u8::MAX as usize;
+/// Most transforms a batch, or entities a snapshot, can carry.
+pub const MAX_ITEMS: usize = u16::MAX as usize;
+const V1_HEADER_LEN: usize = 8;
+
+#[cfg(test)]
+mod codegen;
+mod compact;
+mod handshake;
+mod json;
+#[cfg(test)]
+mod tests;
+mod validate;
+
+pub use compact::{Codec, Encoding};
+pub use handshake::{codecs, Handshake, Role};
+pub use validate::Limits;
+
+pub mod flags {
+    pub const NONE: u8 = 0;
+    /// Transforms use the compact encoding, see the `compact` module.
+    pub const COMPACT: u8 = 1 << 0;
+    /// Mask of every flag understood by this build.
+    pub const KNOWN: u8 = COMPACT;
+}
+
+pub(crate) mod message_types {
+    pub const HANDSHAKE: u8 = 0;
+    pub const SYNC: u8 = 1;
+    pub const TRANSFORM: u8 = 2;
+    pub const BATCH: u8 = 3;
+    pub const SNAPSHOT: u8 = 4;
+    pub const PING: u8 = 5;
+    pub const PONG: u8 = 6;
+    pub const ACK: u8 = 7;
+    pub const ERROR: u8 = 8;
+
+    pub mod target {
+        pub const OWNER: u8 = 0;
+        pub const ID: u8 = 1;
+        pub const NAME: u8 = 2;
+    }
+
+    pub mod transform {
+        pub const ROTATE: u8 = 0;
+        pub const TRANSLATE: u8 = 1;
+        pub const SCALE: u8 = 2;
+        pub const ROTATE_QUAT: u8 = 3;
+    }
+
+    pub mod error {
+        pub const INVALID_MAGIC: u8 = 0;
+        pub const UNSUPPORTED_VERSION: u8 = 1;
+        pub const UNSUPPORTED_FLAGS: u8 = 2;
+        pub const INVALID_MESSAGE_TYPE: u8 = 3;
+        pub const INVALID_TRANSFORM_TYPE: u8 = 4;
+        pub const INVALID_TARGET_TYPE: u8 = 5;
+        pub const INVALID_MESSAGE_LENGTH: u8 = 6;
+        pub const INVALID_STRING: u8 = 7;
+        pub const MISSING_BASELINE: u8 = 8;
+        pub const INVALID_JSON: u8 = 9;
+        pub const INVALID_ROLE: u8 = 10;
+        pub const UNSUPPORTED_CODECS: u8 = 11;
+        pub const FRAME_TOO_LARGE: u8 = 12;
+        pub const TRAILING_BYTES: u8 = 13;
+        pub const NAME_TOO_LONG: u8 = 14;
+        pub const TOO_MANY_ITEMS: u8 = 15;
+        pub const NON_FINITE: u8 = 16;
+        pub const OUT_OF_RANGE: u8 = 17;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Handshake(Handshake),
+    Sync,
+    Snapshot(Vec<EntitySnapshot>),
+    Transform(EntityTransform),
+    Batch(Vec<EntityTransform>),
+    Ping(u32),
+    Pong {
+        to: u32,
+        id: u32,
+    },
+    Ack {
+        to: u32,
+        sequence: u32,
+    },
+    Error {
+        to: u32,
+        sequence: u32,
+        error: MessageError,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntitySnapshot {
+    pub id: u32,
+    pub name: Option<String>,
+    pub position: glm::Vec3,
+    pub rotation: glm::Quat,
+    pub scale: glm::Vec3,
+}
+
+/// The entity a transform should be applied to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Target {
+    Owner,
+    Id(u32),
+    Name(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct EntityTransform {
+    pub target: Target,
+    pub transform: Transform,
+}
+
+#[derive(Debug, Clone)]
+pub enum Transform {
+    Rotate(glm::Vec3),
+    RotateQuat(glm::Quat),
+    Translate(glm::Vec3),
+    Scale(glm::Vec3),
+}
+
+/// Who sent a frame, and when.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Stamp {
+    pub sender: u32,
+    pub sequence: u32,
+    pub timestamp: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub version: u8,
+    pub flags: u8,
+    pub length: u32,
+    pub stamp: Stamp,
+}
+
+/// A message together with the stamp of the frame that carried it.
+#[derive(Debug)]
+pub struct Envelope {
+    pub stamp: Stamp,
+    pub message: Message,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageError {
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    UnsupportedFlags(u8),
+    InvalidMessageType,
+    InvalidTransformType,
+    InvalidTargetType,
+    InvalidMessageLength,
+    InvalidString,
+    MissingBaseline,
+    InvalidJson,
+    InvalidRole,
+    UnsupportedCodecs(u8),
+    /// The frame is longer than `Limits::max_frame_len`.
+    FrameTooLarge,
+    /// The payload goes on after its message.
+    TrailingBytes,
+    /// A name is longer than `Limits::max_name_len`.
+    NameTooLong,
+    /// A batch, snapshot or handshake lists more than `Limits::max_items`.
+    TooManyItems,
+    /// A value is NaN or infinite.
+    NonFinite,
+    /// A value is outside the range `Limits` allows for it.
+    OutOfRange,
+    /// An error code this build doesn't know, received in an `ERROR` message.
+    Unknown(u8),
+}
+
+impl MessageError {
+    /// The error code and its detail, as sent in `ERROR` messages.
+    pub fn code(&self) -> (u8, u8) {
+        use message_types::error::*;
+        match *self {
+            MessageError::InvalidMagic => (INVALID_MAGIC, 0),
+            MessageError::UnsupportedVersion(version) => (UNSUPPORTED_VERSION, version),
+            MessageError::UnsupportedFlags(flags) => (UNSUPPORTED_FLAGS, flags),
+            MessageError::InvalidMessageType => (INVALID_MESSAGE_TYPE, 0),
+            MessageError::InvalidTransformType => (INVALID_TRANSFORM_TYPE, 0),
+            MessageError::InvalidTargetType => (INVALID_TARGET_TYPE, 0),
+            MessageError::InvalidMessageLength => (INVALID_MESSAGE_LENGTH, 0),
+            MessageError::InvalidString => (INVALID_STRING, 0),
+            MessageError::MissingBaseline => (MISSING_BASELINE, 0),
+            MessageError::InvalidJson => (INVALID_JSON, 0),
+            MessageError::InvalidRole => (INVALID_ROLE, 0),
+            MessageError::UnsupportedCodecs(codecs) => (UNSUPPORTED_CODECS, codecs),
+            MessageError::FrameTooLarge => (FRAME_TOO_LARGE, 0),
+            MessageError::TrailingBytes => (TRAILING_BYTES, 0),
+            MessageError::NameTooLong => (NAME_TOO_LONG, 0),
+            MessageError::TooManyItems => (TOO_MANY_ITEMS, 0),
+            MessageError::NonFinite => (NON_FINITE, 0),
+            MessageError::OutOfRange => (OUT_OF_RANGE, 0),
+            MessageError::Unknown(code) => (code, 0),
+        }
+    }
+
+    pub fn from_code(code: u8, detail: u8) -> MessageError {
+        use message_types::error::*;
+        match code {
+            INVALID_MAGIC => MessageError::InvalidMagic,
+            UNSUPPORTED_VERSION => MessageError::UnsupportedVersion(detail),
+            UNSUPPORTED_FLAGS => MessageError::UnsupportedFlags(detail),
+            INVALID_MESSAGE_TYPE => MessageError::InvalidMessageType,
+            INVALID_TRANSFORM_TYPE => MessageError::InvalidTransformType,
+            INVALID_TARGET_TYPE => MessageError::InvalidTargetType,
+            INVALID_MESSAGE_LENGTH => MessageError::InvalidMessageLength,
+            INVALID_STRING => MessageError::InvalidString,
+            MISSING_BASELINE => MessageError::MissingBaseline,
+            INVALID_JSON => MessageError::InvalidJson,
+            INVALID_ROLE => MessageError::InvalidRole,
+            UNSUPPORTED_CODECS => MessageError::UnsupportedCodecs(detail),
+            FRAME_TOO_LARGE => MessageError::FrameTooLarge,
+            TRAILING_BYTES => MessageError::TrailingBytes,
+            NAME_TOO_LONG => MessageError::NameTooLong,
+            TOO_MANY_ITEMS => MessageError::TooManyItems,
+            NON_FINITE => MessageError::NonFinite,
+            OUT_OF_RANGE => MessageError::OutOfRange,
+            code => MessageError::Unknown(code),
+        }
+    }
+}
+
+pub trait Serializable {
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized;
+}
+
+impl Header {
+    pub fn new(length: usize, stamp: Stamp) -> Header {
+        Header {
+            version: PROTOCOL_VERSION,
+            flags: flags::NONE,
+            length: length as u32,
+            stamp,
+        }
+    }
+
+    /// Length of the header itself, which depends on its version.
+    pub fn encoded_len(&self) -> usize {
+        if self.version < 2 {
+            V1_HEADER_LEN
+        } else {
+            HEADER_LEN
+        }
+    }
+
+    /// Whether `bytes` starts with a frame header rather than a legacy, unframed payload.
+    pub fn is_framed(bytes: &[u8]) -> bool {
+        bytes.starts_with(&MAGIC)
+    }
+}
+
+impl Stamp {
+    /// The stamp of a framed message, read from its fixed offsets without validating the
+    /// rest of the header, so that frames of an unsupported version or with unsupported flags
+    /// can still be answered. Headers before version 2 carry no stamp.
+    pub fn peek(bytes: &[u8]) -> Option<Stamp> {
+        if !Header::is_framed(bytes) || bytes.len() < V1_HEADER_LEN {
+            return None;
+        }
+        if bytes[2] < 2 {
+            return Some(Stamp::default());
+        }
+        if bytes.len() < 16 {
+            return None;
+        }
+        let timestamp = match bytes.get(16..HEADER_LEN) {
+            Some(timestamp) => f64::from_le_bytes(timestamp.try_into().unwrap()),
+            None => 0.0,
+        };
+        Some(Stamp {
+            sender: u32_at(bytes, 8),
+            sequence: u32_at(bytes, 12),
+            timestamp,
+        })
+    }
+}
+
+impl Serializable for Header {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(self.version);
+        bytes.push(self.flags);
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.sender.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.sequence.to_le_bytes());
+        bytes.extend_from_slice(&self.stamp.timestamp.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        if bytes.len() < V1_HEADER_LEN {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        if !Header::is_framed(bytes) {
+            return Err(MessageError::InvalidMagic);
+        }
+        let version = bytes[2];
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version) {
+            return Err(MessageError::UnsupportedVersion(version));
+        }
+        let flags = bytes[3];
+        if flags & !flags::KNOWN != 0 {
+            return Err(MessageError::UnsupportedFlags(flags));
+        }
+        let length = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let mut header = Header {
+            version,
+            flags,
+            length,
+            stamp: Stamp::default(),
+        };
+        if version < 2 {
+            return Ok(header);
+        }
+
+        if bytes.len() < HEADER_LEN {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[16..24]);
+        header.stamp = Stamp {
+            sender: u32_at(bytes, 8),
+            sequence: u32_at(bytes, 12),
+            timestamp: f64::from_le_bytes(timestamp),
+        };
+        Ok(header)
+    }
+}
+
+impl Serializable for glm::Vec3 {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&self.x.to_le_bytes());
+        bytes.extend_from_slice(&self.y.to_le_bytes());
+        bytes.extend_from_slice(&self.z.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        if bytes.len() < 12 {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let x = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let y = f32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        let z = f32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]);
+        Ok(glm::vec3(x, y, z))
+    }
+}
+
+impl Serializable for glm::Quat {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for c in self.coords.iter() {
+            bytes.extend_from_slice(&c.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        if bytes.len() < 16 {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let c = |i: usize| f32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Ok(glm::quat(c(0), c(4), c(8), c(12)))
+    }
+}
+
+impl Target {
+    fn encoded_len(&self) -> usize {
+        match self {
+            Target::Owner => 1,
+            Target::Id(_) => 5,
+            Target::Name(name) => 2 + name.len(),
+        }
+    }
+
+    fn check_encodable(&self) -> Result<(), MessageError> {
+        match self {
+            Target::Name(name) => check_name_len(name),
+            Target::Owner | Target::Id(_) => Ok(()),
+        }
+    }
+}
+
+/// Checks that a batch or snapshot of `count` items fits its count field.
+fn check_count(count: usize) -> Result<(), MessageError> {
+    if count > MAX_ITEMS {
+        return Err(MessageError::TooManyItems);
+    }
+    Ok(())
+}
+
+/// Checks that `name` fits its length byte.
+fn check_name_len(name: &str) -> Result<(), MessageError> {
+    if name.len() > MAX_NAME_LEN {
+        return Err(MessageError::NameTooLong);
+    }
+    Ok(())
+}
+
+impl Serializable for Target {
+    /// Panics if the name is longer than `MAX_NAME_LEN`, see `Envelope::encode`.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        match self {
+            Target::Owner => bytes.push(message_types::target::OWNER),
+            Target::Id(id) => {
+                bytes.push(message_types::target::ID);
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+            Target::Name(name) => {
+                assert!(name.len() <= MAX_NAME_LEN, "target name too long");
+                bytes.push(message_types::target::NAME);
+                bytes.push(name.len() as u8);
+                bytes.extend_from_slice(name.as_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        if bytes.is_empty() {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        match bytes[0] {
+            message_types::target::OWNER => Ok(Target::Owner),
+            message_types::target::ID => {
+                if bytes.len() < 5 {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let id = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+                Ok(Target::Id(id))
+            }
+            message_types::target::NAME => {
+                if bytes.len() < 2 || bytes.len() < 2 + bytes[1] as usize {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let name = &bytes[2..2 + bytes[1] as usize];
+                let name =
+                    String::from_utf8(name.to_vec()).map_err(|_| MessageError::InvalidString)?;
+                Ok(Target::Name(name))
+            }
+            _ => Err(MessageError::InvalidTargetType),
+        }
+    }
+}
+
+impl EntityTransform {
+    fn encoded_len(&self) -> usize {
+        self.target.encoded_len() + self.transform.encoded_len()
+    }
+}
+
+impl Serializable for EntityTransform {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.target.to_bytes();
+        bytes.extend_from_slice(&self.transform.to_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        let target = Target::from_bytes(bytes)?;
+        let transform = Transform::from_bytes(&bytes[target.encoded_len()..])?;
+        Ok(EntityTransform { target, transform })
+    }
+}
+
+impl EntitySnapshot {
+    fn encoded_len(&self) -> usize {
+        4 + 1 + self.name.as_ref().map_or(0, |name| name.len()) + 12 + 16 + 12
+    }
+}
+
+impl Serializable for EntitySnapshot {
+    /// Panics if the name is longer than `MAX_NAME_LEN`, see `Envelope::encode`.
+    fn to_bytes(&self) -> Vec<u8> {
+        let name = self.name.as_deref().unwrap_or("");
+        assert!(name.len() <= MAX_NAME_LEN, "entity name too long");
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        bytes.extend_from_slice(&self.id.to_le_bytes());
+        bytes.push(name.len() as u8);
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&self.position.to_bytes());
+        bytes.extend_from_slice(&self.rotation.to_bytes());
+        bytes.extend_from_slice(&self.scale.to_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        if bytes.len() < 5 {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let id = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let name_len = bytes[4] as usize;
+        if bytes.len() < 5 + name_len {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let name = match name_len {
+            0 => None,
+            _ => Some(
+                String::from_utf8(bytes[5..5 + name_len].to_vec())
+                    .map_err(|_| MessageError::InvalidString)?,
+            ),
+        };
+        let offset = 5 + name_len;
+        let position = glm::Vec3::from_bytes(&bytes[offset..])?;
+        let rotation = glm::Quat::from_bytes(&bytes[offset + 12..])?;
+        let scale = glm::Vec3::from_bytes(&bytes[offset + 28..])?;
+        Ok(EntitySnapshot {
+            id,
+            name,
+            position,
+            rotation,
+            scale,
+        })
+    }
+}
+
+impl Transform {
+    /// The transform's type on the wire, one of `message_types::transform`.
+    pub fn kind(&self) -> u8 {
+        match self {
+            Transform::Rotate(_) => message_types::transform::ROTATE,
+            Transform::RotateQuat(_) => message_types::transform::ROTATE_QUAT,
+            Transform::Translate(_) => message_types::transform::TRANSLATE,
+            Transform::Scale(_) => message_types::transform::SCALE,
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        match self {
+            Transform::RotateQuat(_) => 1 + 16,
+            _ => 1 + 12,
+        }
+    }
+}
+
+impl Serializable for Transform {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Transform::Rotate(vec) => {
+                bytes.push(message_types::transform::ROTATE);
+                bytes.extend_from_slice(&vec.to_bytes());
+            }
+            Transform::RotateQuat(quat) => {
+                bytes.push(message_types::transform::ROTATE_QUAT);
+                bytes.extend_from_slice(&quat.to_bytes());
+            }
+            Transform::Translate(vec) => {
+                bytes.push(message_types::transform::TRANSLATE);
+                bytes.extend_from_slice(&vec.to_bytes());
+            }
+            Transform::Scale(vec) => {
+                bytes.push(message_types::transform::SCALE);
+                bytes.extend_from_slice(&vec.to_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        if bytes.is_empty() {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        match bytes[0] {
+            message_types::transform::ROTATE => {
+                let vec = glm::Vec3::from_bytes(&bytes[1..])?;
+                Ok(Transform::Rotate(vec))
+            }
+            message_types::transform::ROTATE_QUAT => {
+                let quat = glm::Quat::from_bytes(&bytes[1..])?;
+                Ok(Transform::RotateQuat(quat))
+            }
+            message_types::transform::TRANSLATE => {
+                let vec = glm::Vec3::from_bytes(&bytes[1..])?;
+                Ok(Transform::Translate(vec))
+            }
+            message_types::transform::SCALE => {
+                let vec = glm::Vec3::from_bytes(&bytes[1..])?;
+                Ok(Transform::Scale(vec))
+            }
+            _ => Err(MessageError::InvalidTransformType),
+        }
+    }
+}
+
+impl Message {
+    /// Checks that every name and count in the message fits its length field, so that it can
+    /// be encoded without corrupting the frame.
+    pub fn check_encodable(&self) -> Result<(), MessageError> {
+        match self {
+            Message::Handshake(handshake) => handshake.check_encodable(),
+            Message::Snapshot(entities) => {
+                check_count(entities.len())?;
+                entities
+                    .iter()
+                    .try_for_each(|entity| check_name_len(entity.name.as_deref().unwrap_or("")))
+            }
+            Message::Transform(transform) => transform.target.check_encodable(),
+            Message::Batch(transforms) => {
+                check_count(transforms.len())?;
+                transforms
+                    .iter()
+                    .try_for_each(|transform| transform.target.check_encodable())
+            }
+            Message::Sync
+            | Message::Ping(_)
+            | Message::Pong { .. }
+            | Message::Ack { .. }
+            | Message::Error { .. } => Ok(()),
+        }
+    }
+
+    /// Decodes a payload laid out as in protocol `version`, which must end with the message.
+    pub fn from_versioned_bytes(version: u8, bytes: &[u8]) -> Result<Self, MessageError> {
+        let (message, len) = Self::read_versioned(version, bytes)?;
+        if len != bytes.len() {
+            return Err(MessageError::TrailingBytes);
+        }
+        Ok(message)
+    }
+
+    /// Decodes the message at the start of `bytes`, returning it along with its length.
+    fn read_versioned(version: u8, bytes: &[u8]) -> Result<(Self, usize), MessageError> {
+        if bytes.is_empty() {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        match bytes[0] {
+            message_types::HANDSHAKE => {
+                let handshake = Handshake::from_versioned_bytes(version, &bytes[1..])?;
+                let len = if version < 3 {
+                    bytes.len()
+                } else {
+                    1 + handshake.encoded_len()
+                };
+                Ok((Message::Handshake(handshake), len))
+            }
+            message_types::SYNC => Ok((Message::Sync, 1)),
+            message_types::SNAPSHOT => {
+                if bytes.len() < 3 {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let count = u16::from_le_bytes([bytes[1], bytes[2]]) as usize;
+                let mut entities = Vec::with_capacity(count);
+                let mut offset = 3;
+                for _ in 0..count {
+                    let entity = EntitySnapshot::from_bytes(&bytes[offset..])?;
+                    offset += entity.encoded_len();
+                    entities.push(entity);
+                }
+                Ok((Message::Snapshot(entities), offset))
+            }
+            message_types::TRANSFORM if version == 0 => {
+                let transform = Transform::from_bytes(&bytes[1..])?;
+                let len = 1 + transform.encoded_len();
+                let transform = EntityTransform {
+                    target: Target::Owner,
+                    transform,
+                };
+                Ok((Message::Transform(transform), len))
+            }
+            message_types::TRANSFORM => {
+                let transform = EntityTransform::from_bytes(&bytes[1..])?;
+                let len = 1 + transform.encoded_len();
+                Ok((Message::Transform(transform), len))
+            }
+            message_types::BATCH => {
+                if bytes.len() < 3 {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let count = u16::from_le_bytes([bytes[1], bytes[2]]) as usize;
+                let mut transforms = Vec::with_capacity(count);
+                let mut offset = 3;
+                for _ in 0..count {
+                    let transform = EntityTransform::from_bytes(&bytes[offset..])?;
+                    offset += transform.encoded_len();
+                    transforms.push(transform);
+                }
+                Ok((Message::Batch(transforms), offset))
+            }
+            message_types::PING => {
+                if bytes.len() < 5 {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                Ok((Message::Ping(u32_at(bytes, 1)), 5))
+            }
+            message_types::PONG => {
+                if bytes.len() < 9 {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let pong = Message::Pong {
+                    to: u32_at(bytes, 1),
+                    id: u32_at(bytes, 5),
+                };
+                Ok((pong, 9))
+            }
+            message_types::ACK => {
+                if bytes.len() < 9 {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let ack = Message::Ack {
+                    to: u32_at(bytes, 1),
+                    sequence: u32_at(bytes, 5),
+                };
+                Ok((ack, 9))
+            }
+            message_types::ERROR => {
+                if bytes.len() < 11 {
+                    return Err(MessageError::InvalidMessageLength);
+                }
+                let error = Message::Error {
+                    to: u32_at(bytes, 1),
+                    sequence: u32_at(bytes, 5),
+                    error: MessageError::from_code(bytes[9], bytes[10]),
+                };
+                Ok((error, 11))
+            }
+            _ => Err(MessageError::InvalidMessageType),
+        }
+    }
+}
+
+impl Serializable for Message {
+    /// Panics if `check_encodable` fails, see `Envelope::encode`.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Message::Handshake(handshake) => {
+                bytes.push(message_types::HANDSHAKE);
+                bytes.extend_from_slice(&handshake.to_bytes());
+            }
+            Message::Sync => bytes.push(message_types::SYNC),
+            Message::Snapshot(entities) => {
+                bytes.push(message_types::SNAPSHOT);
+                assert!(entities.len() <= MAX_ITEMS, "too many entities");
+                bytes.extend_from_slice(&(entities.len() as u16).to_le_bytes());
+                for entity in entities {
+                    bytes.extend_from_slice(&entity.to_bytes());
+                }
+            }
+            Message::Transform(transform) => {
+                bytes.push(message_types::TRANSFORM);
+                bytes.extend_from_slice(&transform.to_bytes());
+            }
+            Message::Batch(transforms) => {
+                bytes.push(message_types::BATCH);
+                assert!(transforms.len() <= MAX_ITEMS, "too many transforms");
+                bytes.extend_from_slice(&(transforms.len() as u16).to_le_bytes());
+                for transform in transforms {
+                    bytes.extend_from_slice(&transform.to_bytes());
+                }
+            }
+            Message::Ping(id) => {
+                bytes.push(message_types::PING);
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+            Message::Pong { to, id } => {
+                bytes.push(message_types::PONG);
+                bytes.extend_from_slice(&to.to_le_bytes());
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+            Message::Ack { to, sequence } => {
+                bytes.push(message_types::ACK);
+                bytes.extend_from_slice(&to.to_le_bytes());
+                bytes.extend_from_slice(&sequence.to_le_bytes());
+            }
+            Message::Error {
+                to,
+                sequence,
+                error,
+            } => {
+                let (code, detail) = error.code();
+                bytes.push(message_types::ERROR);
+                bytes.extend_from_slice(&to.to_le_bytes());
+                bytes.extend_from_slice(&sequence.to_le_bytes());
+                bytes.push(code);
+                bytes.push(detail);
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        Self::from_versioned_bytes(PROTOCOL_VERSION, bytes)
+    }
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+}
+
+impl Envelope {
+    pub fn new(stamp: Stamp, message: Message) -> Envelope {
+        Envelope { stamp, message }
+    }
+
+    /// The entities this envelope's transforms are addressed to.
+    pub fn targets(&self) -> Vec<&Target> {
+        match &self.message {
+            Message::Transform(transform) => vec![&transform.target],
+            Message::Batch(transforms) => transforms.iter().map(|t| &t.target).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Encodes the envelope as a full binary frame, unless something in it doesn't fit the
+    /// frame, see `Message::check_encodable`.
+    pub fn encode(&self) -> Result<Vec<u8>, MessageError> {
+        self.message.check_encodable()?;
+        Ok(self.to_bytes())
+    }
+}
+
+impl Serializable for Envelope {
+    fn to_bytes(&self) -> Vec<u8> {
+        let payload = self.message.to_bytes();
+        let mut bytes = Header::new(payload.len(), self.stamp).to_bytes();
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Decodes a framed message. Frames without a header are downgraded to the legacy
+    /// (version 0) layout, where the payload starts right at the first byte.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, MessageError>
+    where
+        Self: Sized,
+    {
+        if !Header::is_framed(bytes) {
+            let message = Message::from_versioned_bytes(0, bytes)?;
+            return Ok(Envelope::new(Stamp::default(), message));
+        }
+        let header = Header::from_bytes(bytes)?;
+        if header.flags & flags::COMPACT != 0 {
+            return Err(MessageError::UnsupportedFlags(header.flags));
+        }
+        let payload = &bytes[header.encoded_len()..];
+        if payload.len() != header.length as usize {
+            return Err(MessageError::InvalidMessageLength);
+        }
+        let message = Message::from_versioned_bytes(header.version, payload)?;
+        Ok(Envelope::new(header.stamp, message))
+    }
+}
@@ -0,0 +1,118 @@
+use super::*;
+
+fn stamp() -> Stamp {
+    Stamp {
+        sender: 7,
+        sequence: 42,
+        timestamp: 1234.5,
+    }
+}
+
+fn rotate() -> Transform {
+    Transform::Rotate(glm::vec3(0.5, -1.0, 2.0))
+}
+
+/// A framed `Sync` with its header's version and flags bytes replaced.
+fn sync_frame(version: u8, flags: u8) -> Vec<u8> {
+    let mut bytes = Envelope::new(stamp(), Message::Sync).to_bytes();
+    bytes[2] = version;
+    bytes[3] = flags;
+    bytes
+}
+
+#[test]
+fn header_round_trips() {
+    let header = Header::new(17, stamp());
+    let bytes = header.to_bytes();
+    assert_eq!(bytes.len(), HEADER_LEN);
+    assert_eq!(header.encoded_len(), HEADER_LEN);
+    assert_eq!(Header::from_bytes(&bytes), Ok(header));
+    assert_eq!(Stamp::peek(&bytes), Some(stamp()));
+
+    let envelope = Envelope::new(stamp(), Message::Ping(3));
+    let decoded = Envelope::from_bytes(&envelope.to_bytes()).unwrap();
+    assert_eq!(decoded.stamp, stamp());
+    assert!(matches!(decoded.message, Message::Ping(3)));
+}
+
+#[test]
+fn rejects_unknown_versions_and_flags() {
+    let newer = sync_frame(PROTOCOL_VERSION + 1, flags::NONE);
+    assert_eq!(
+        Header::from_bytes(&newer),
+        Err(MessageError::UnsupportedVersion(PROTOCOL_VERSION + 1))
+    );
+    assert!(matches!(
+        Envelope::from_bytes(&newer),
+        Err(MessageError::UnsupportedVersion(_))
+    ));
+
+    let unknown = 0x80;
+    let flagged = sync_frame(PROTOCOL_VERSION, unknown);
+    assert_eq!(
+        Header::from_bytes(&flagged),
+        Err(MessageError::UnsupportedFlags(unknown))
+    );
+    // compact frames are understood, but only by a `Codec`
+    assert!(matches!(
+        Envelope::from_bytes(&sync_frame(PROTOCOL_VERSION, flags::COMPACT)),
+        Err(MessageError::UnsupportedFlags(flags::COMPACT))
+    ));
+}
+
+#[test]
+fn decodes_legacy_frames() {
+    // version 0: no header, and transforms without a target
+    let mut unframed = vec![message_types::TRANSFORM];
+    unframed.extend_from_slice(&rotate().to_bytes());
+    let envelope = Envelope::from_bytes(&unframed).unwrap();
+    assert_eq!(envelope.stamp, Stamp::default());
+    let Message::Transform(transform) = envelope.message else {
+        panic!("expected a transform, got {:?}", envelope.message);
+    };
+    assert_eq!(transform.target, Target::Owner);
+    assert_eq!(transform.transform.to_bytes(), rotate().to_bytes());
+
+    // version 1: a header without a stamp, and transforms with a target
+    let transform = EntityTransform {
+        target: Target::Id(3),
+        transform: rotate(),
+    };
+    let payload = Message::Transform(transform.clone()).to_bytes();
+    let mut framed = MAGIC.to_vec();
+    framed.extend_from_slice(&[1, flags::NONE]);
+    framed.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    framed.extend_from_slice(&payload);
+    let header = Header::from_bytes(&framed).unwrap();
+    assert_eq!(header.encoded_len(), V1_HEADER_LEN);
+    assert_eq!(Stamp::peek(&framed), Some(Stamp::default()));
+    let envelope = Envelope::from_bytes(&framed).unwrap();
+    assert_eq!(envelope.stamp, Stamp::default());
+    assert_eq!(
+        envelope.message.to_bytes(),
+        Message::Transform(transform).to_bytes()
+    );
+}
+
+#[test]
+fn rejects_trailing_bytes() {
+    let mut payload = Message::Ping(3).to_bytes();
+    assert!(Message::from_versioned_bytes(PROTOCOL_VERSION, &payload).is_ok());
+    payload.push(0);
+    assert_eq!(
+        Message::read_versioned(PROTOCOL_VERSION, &payload).map(|(_, len)| len),
+        Ok(5)
+    );
+    assert!(matches!(
+        Message::from_versioned_bytes(PROTOCOL_VERSION, &payload),
+        Err(MessageError::TrailingBytes)
+    ));
+
+    // a frame's length covers the trailing byte, so its payload is rejected too
+    let mut frame = Header::new(payload.len(), stamp()).to_bytes();
+    frame.extend_from_slice(&payload);
+    assert!(matches!(
+        Envelope::from_bytes(&frame),
+        Err(MessageError::TrailingBytes)
+    ));
+}
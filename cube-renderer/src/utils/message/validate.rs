@@ -0,0 +1,255 @@
+//! Message validation
+//!
+//! Decoding only checks that a frame is laid out as described in the `message` module. A
+//! frame can still carry values that would poison an entity's state, such as NaN angles or a
+//! translation far outside the scene, or be much larger than any peer would send. `Limits`
+//! bounds what a `Codec` accepts, and `Envelope::validate` checks a decoded message against
+//! them.
+
+use std::f32::consts::PI;
+
+use super::{EntitySnapshot, EntityTransform, Envelope, Handshake, Message, MessageError};
+use super::{Target, Transform};
+
+/// What a decoded message may contain.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Largest frame accepted, in bytes, header included.
+    pub max_frame_len: usize,
+    /// Longest handshake, target or entity name, in bytes.
+    pub max_name_len: usize,
+    /// Most transforms in a batch, entities in a snapshot or bindings in a handshake.
+    pub max_items: usize,
+    /// Largest Euler angle, in radians, either way.
+    pub max_angle: f32,
+    /// Largest translation or position component, either way.
+    pub max_translation: f32,
+    /// Largest scale component, either way.
+    pub max_scale: f32,
+    /// How far a quaternion's norm may be from 1.
+    pub quat_tolerance: f32,
+    /// Whether NaN and infinite values are rejected. Only turned off to inspect traffic.
+    pub finite_only: bool,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_frame_len: 64 * 1024,
+            max_name_len: 64,
+            max_items: 256,
+            max_angle: 4.0 * PI,
+            max_translation: 1000.0,
+            max_scale: 1000.0,
+            quat_tolerance: 0.1,
+            finite_only: true,
+        }
+    }
+}
+
+impl Limits {
+    /// Checks the length of a frame before it is decoded.
+    pub fn check_frame_len(&self, len: usize) -> Result<(), MessageError> {
+        if len > self.max_frame_len {
+            return Err(MessageError::FrameTooLarge);
+        }
+        Ok(())
+    }
+
+    fn check_name(&self, name: &str) -> Result<(), MessageError> {
+        if name.len() > self.max_name_len {
+            return Err(MessageError::NameTooLong);
+        }
+        Ok(())
+    }
+
+    fn check_items(&self, count: usize) -> Result<(), MessageError> {
+        if count > self.max_items {
+            return Err(MessageError::TooManyItems);
+        }
+        Ok(())
+    }
+
+    fn check_values(&self, values: &[f32], max: f32) -> Result<(), MessageError> {
+        for &value in values {
+            if !value.is_finite() {
+                if self.finite_only {
+                    return Err(MessageError::NonFinite);
+                }
+                continue;
+            }
+            if value.abs() > max {
+                return Err(MessageError::OutOfRange);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_quat(&self, quat: &glm::Quat) -> Result<(), MessageError> {
+        self.check_values(quat.coords.as_slice(), f32::INFINITY)?;
+        let norm = quat.norm();
+        if norm.is_finite() && (norm - 1.0).abs() > self.quat_tolerance {
+            return Err(MessageError::OutOfRange);
+        }
+        Ok(())
+    }
+
+    fn check_target(&self, target: &Target) -> Result<(), MessageError> {
+        match target {
+            Target::Name(name) => self.check_name(name),
+            Target::Owner | Target::Id(_) => Ok(()),
+        }
+    }
+
+    fn check_transform(&self, transform: &EntityTransform) -> Result<(), MessageError> {
+        self.check_target(&transform.target)?;
+        match &transform.transform {
+            Transform::Rotate(euler) => self.check_values(euler.as_slice(), self.max_angle),
+            Transform::RotateQuat(quat) => self.check_quat(quat),
+            Transform::Translate(v) => self.check_values(v.as_slice(), self.max_translation),
+            Transform::Scale(v) => self.check_values(v.as_slice(), self.max_scale),
+        }
+    }
+
+    fn check_snapshot(&self, entity: &EntitySnapshot) -> Result<(), MessageError> {
+        if let Some(name) = &entity.name {
+            self.check_name(name)?;
+        }
+        self.check_values(entity.position.as_slice(), self.max_translation)?;
+        self.check_quat(&entity.rotation)?;
+        self.check_values(entity.scale.as_slice(), self.max_scale)
+    }
+
+    fn check_handshake(&self, handshake: &Handshake) -> Result<(), MessageError> {
+        self.check_name(&handshake.name)?;
+        self.check_items(handshake.bindings.len())?;
+        handshake
+            .bindings
+            .iter()
+            .try_for_each(|target| self.check_target(target))
+    }
+}
+
+impl Envelope {
+    /// Checks that the message stays within `limits`, so that it can be applied as is.
+    pub fn validate(&self, limits: &Limits) -> Result<(), MessageError> {
+        if limits.finite_only && !self.stamp.timestamp.is_finite() {
+            return Err(MessageError::NonFinite);
+        }
+        match &self.message {
+            Message::Handshake(handshake) => limits.check_handshake(handshake),
+            Message::Transform(transform) => limits.check_transform(transform),
+            Message::Batch(transforms) => {
+                limits.check_items(transforms.len())?;
+                transforms
+                    .iter()
+                    .try_for_each(|transform| limits.check_transform(transform))
+            }
+            Message::Snapshot(entities) => {
+                limits.check_items(entities.len())?;
+                entities
+                    .iter()
+                    .try_for_each(|entity| limits.check_snapshot(entity))
+            }
+            Message::Sync
+            | Message::Ping(_)
+            | Message::Pong { .. }
+            | Message::Ack { .. }
+            | Message::Error { .. } => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Stamp;
+    use super::*;
+
+    fn transform(target: Target, transform: Transform) -> Envelope {
+        Envelope::new(
+            Stamp::default(),
+            Message::Transform(EntityTransform { target, transform }),
+        )
+    }
+
+    #[test]
+    fn rejects_values_outside_the_limits() {
+        let limits = Limits::default();
+        let check = |target, t| transform(target, t).validate(&limits);
+
+        assert_eq!(
+            check(Target::Owner, Transform::Rotate(glm::vec3(0.5, -1.0, 6.0))),
+            Ok(())
+        );
+        assert_eq!(
+            check(
+                Target::Owner,
+                Transform::Rotate(glm::vec3(f32::NAN, 0.0, 0.0))
+            ),
+            Err(MessageError::NonFinite)
+        );
+        assert_eq!(
+            check(
+                Target::Owner,
+                Transform::Scale(glm::vec3(1.0, f32::INFINITY, 1.0))
+            ),
+            Err(MessageError::NonFinite)
+        );
+        assert_eq!(
+            check(
+                Target::Owner,
+                Transform::Translate(glm::vec3(0.0, 0.0, -1e6))
+            ),
+            Err(MessageError::OutOfRange)
+        );
+        assert_eq!(
+            check(
+                Target::Owner,
+                Transform::RotateQuat(glm::quat(0.0, 0.0, 0.0, 0.0))
+            ),
+            Err(MessageError::OutOfRange)
+        );
+        assert_eq!(
+            check(
+                Target::Name("x".repeat(65)),
+                Transform::Scale(glm::vec3(1.0, 1.0, 1.0))
+            ),
+            Err(MessageError::NameTooLong)
+        );
+
+        let lenient = Limits {
+            finite_only: false,
+            ..Limits::default()
+        };
+        let nan = transform(
+            Target::Owner,
+            Transform::Rotate(glm::vec3(f32::NAN, 0.0, 0.0)),
+        );
+        assert_eq!(nan.validate(&lenient), Ok(()));
+    }
+
+    #[test]
+    fn rejects_oversized_messages() {
+        let limits = Limits {
+            max_items: 2,
+            ..Limits::default()
+        };
+        let rotate = EntityTransform {
+            target: Target::Owner,
+            transform: Transform::Rotate(glm::vec3(0.0, 0.0, 0.0)),
+        };
+        let batch = Envelope::new(Stamp::default(), Message::Batch(vec![rotate; 3]));
+        assert_eq!(batch.validate(&limits), Err(MessageError::TooManyItems));
+        assert_eq!(
+            limits.check_frame_len(64 * 1024 + 1),
+            Err(MessageError::FrameTooLarge)
+        );
+
+        let stamp = Stamp {
+            timestamp: f64::NAN,
+            ..Stamp::default()
+        };
+        let ping = Envelope::new(stamp, Message::Ping(1));
+        assert_eq!(ping.validate(&limits), Err(MessageError::NonFinite));
+    }
+}
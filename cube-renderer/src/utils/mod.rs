@@ -1,5 +1,7 @@
+mod math;
 mod message;
 mod wasm;
 
+pub use math::*;
 pub use message::*;
 pub use wasm::*;